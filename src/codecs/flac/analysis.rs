@@ -0,0 +1,597 @@
+// src/codecs/flac/analysis.rs
+
+// This module reports how every frame and subframe of a FLAC stream was coded, like flac --analyze
+
+use super::{
+    data::Metadata,
+    decoder::Decoder,
+    frame::{Frame, FrameHeader},
+    subframe::{SubframeDetails, SubframeType},
+};
+use crate::common::{errors::AudioError, stream::Stream};
+use std::{collections::BTreeMap, fmt::Write};
+
+// Struct to represent how one subframe was coded
+#[derive(Debug, Clone)]
+pub struct SubframeReport {
+    subframe_type: SubframeType,
+    order: u32,
+    wasted_bits: u8,
+    details: SubframeDetails,
+}
+
+// Struct to represent how one frame was coded, and where it is in the stream
+#[derive(Debug, Clone)]
+pub struct FrameReport {
+    offset: u64, // Byte offset of the frame in the stream
+    size: u64,   // Size of the frame in bytes, header and CRC-16 included
+    header: FrameHeader,
+    crc: u16,
+    subframes: Vec<SubframeReport>,
+}
+
+/**
+ * Counts of how often each coding choice was made across a stream
+ * Rice parameters are counted per partition and the rest per frame or subframe
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Histograms {
+    block_sizes: BTreeMap<u32, u64>,
+    channel_assignments: BTreeMap<u8, u64>,
+    subframe_types: BTreeMap<&'static str, u64>,
+    fixed_orders: BTreeMap<u32, u64>,
+    lpc_orders: BTreeMap<u32, u64>,
+    lpc_precisions: BTreeMap<u8, u64>,
+    partition_orders: BTreeMap<u8, u64>,
+    rice_parameters: BTreeMap<u32, u64>,
+    escaped_partitions: u64,
+    wasted_bits: BTreeMap<u8, u64>,
+}
+
+// Struct to represent a frame that could not be decoded, and why
+#[derive(Debug, Clone)]
+pub struct DamagedFrame {
+    offset: u64,   // Byte offset of the damaged data in the stream
+    error: String, // Message of the error the frame failed with
+}
+
+// Analysis of every frame of a stream, in stream order
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    frames: Vec<FrameReport>,
+    damaged_frames: Vec<DamagedFrame>, // Frames skipped when recovery is enabled
+    histograms: Histograms,
+}
+
+impl SubframeReport {
+    pub fn get_type(&self) -> SubframeType {
+        self.subframe_type
+    }
+
+    pub fn get_order(&self) -> u32 {
+        self.order
+    }
+
+    pub fn get_wasted_bits(&self) -> u8 {
+        self.wasted_bits
+    }
+
+    pub fn get_details(&self) -> &SubframeDetails {
+        &self.details
+    }
+
+    // Method to get the size of the residual section in bits, 0 for subframes without one
+    pub fn get_residual_bits(&self) -> u64 {
+        self.details
+            .get_residual()
+            .map_or(0, |residual| residual.get_bits())
+    }
+}
+
+impl FrameReport {
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn get_header(&self) -> &FrameHeader {
+        &self.header
+    }
+
+    pub fn get_crc(&self) -> u16 {
+        self.crc
+    }
+
+    pub fn get_subframes(&self) -> &[SubframeReport] {
+        &self.subframes
+    }
+}
+
+impl DamagedFrame {
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_error(&self) -> &str {
+        &self.error
+    }
+}
+
+impl Histograms {
+    pub fn get_block_sizes(&self) -> &BTreeMap<u32, u64> {
+        &self.block_sizes
+    }
+
+    pub fn get_channel_assignments(&self) -> &BTreeMap<u8, u64> {
+        &self.channel_assignments
+    }
+
+    // Method to get the count of each subframe type, keyed by its name
+    pub fn get_subframe_types(&self) -> &BTreeMap<&'static str, u64> {
+        &self.subframe_types
+    }
+
+    pub fn get_fixed_orders(&self) -> &BTreeMap<u32, u64> {
+        &self.fixed_orders
+    }
+
+    pub fn get_lpc_orders(&self) -> &BTreeMap<u32, u64> {
+        &self.lpc_orders
+    }
+
+    pub fn get_lpc_precisions(&self) -> &BTreeMap<u8, u64> {
+        &self.lpc_precisions
+    }
+
+    pub fn get_partition_orders(&self) -> &BTreeMap<u8, u64> {
+        &self.partition_orders
+    }
+
+    pub fn get_rice_parameters(&self) -> &BTreeMap<u32, u64> {
+        &self.rice_parameters
+    }
+
+    pub fn get_escaped_partitions(&self) -> u64 {
+        self.escaped_partitions
+    }
+
+    pub fn get_wasted_bits(&self) -> &BTreeMap<u8, u64> {
+        &self.wasted_bits
+    }
+
+    // Count the coding choices of one frame
+    fn add(&mut self, frame: &FrameReport) {
+        *self
+            .block_sizes
+            .entry(frame.header.get_block_size())
+            .or_default() += 1;
+        *self
+            .channel_assignments
+            .entry(frame.header.get_channel_assignment())
+            .or_default() += 1;
+
+        for subframe in &frame.subframes {
+            *self
+                .subframe_types
+                .entry(type_name(subframe.subframe_type))
+                .or_default() += 1;
+            *self.wasted_bits.entry(subframe.wasted_bits).or_default() += 1;
+            match subframe.subframe_type {
+                SubframeType::Fixed => *self.fixed_orders.entry(subframe.order).or_default() += 1,
+                SubframeType::LPC => {
+                    *self.lpc_orders.entry(subframe.order).or_default() += 1;
+                    *self
+                        .lpc_precisions
+                        .entry(subframe.details.get_precision())
+                        .or_default() += 1;
+                }
+                _ => {}
+            }
+
+            if let Some(residual) = subframe.details.get_residual() {
+                *self
+                    .partition_orders
+                    .entry(residual.get_partition_order())
+                    .or_default() += 1;
+                for partition in residual.get_partitions() {
+                    match partition.get_rice_parameter() {
+                        Some(parameter) => *self.rice_parameters.entry(parameter).or_default() += 1,
+                        None => self.escaped_partitions += 1,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Analysis {
+    /**
+     * Constructor method to analyze every frame of the stream the metadata describes
+     * Frames are fully decoded with the CRC policy, recovery mode and arithmetic
+     * mode of the decoder. When recovery is enabled, damaged frames are recorded
+     * and the analysis resumes at the next valid frame header; otherwise they
+     * fail the analysis
+     * The stream is left at its end
+     */
+    pub fn analyze(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        decoder: &Decoder,
+    ) -> Result<Self, AudioError> {
+        let first_frame = metadata
+            .get_first_frame_offset()
+            .ok_or(AudioError::InvalidData(
+                "Cannot analyze frames before the metadata has been read".to_string(),
+            ))?;
+        stream.seek_to(first_frame)?;
+
+        let mut analysis = Self::default();
+        loop {
+            match stream.peek_u16() {
+                Ok(_) => {}
+                Err(AudioError::EOF) => break,
+                Err(err) => return Err(err),
+            }
+
+            let offset = stream.get_position()?;
+            match Self::analyze_frame(stream, metadata, decoder, offset) {
+                Ok(report) => {
+                    analysis.histograms.add(&report);
+                    analysis.frames.push(report);
+                }
+                Err(err) => {
+                    let error = err.to_string();
+                    decoder.get_recovery_mode().skip(err)?;
+                    analysis.damaged_frames.push(DamagedFrame { offset, error });
+                    if Decoder::find_next_frame(stream, metadata, offset + 1)?.is_none() {
+                        let end = stream.get_len()?;
+                        stream.seek_to(end)?;
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(analysis)
+    }
+
+    // Decode the frame at a byte offset and report how it was coded
+    fn analyze_frame(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        decoder: &Decoder,
+        offset: u64,
+    ) -> Result<FrameReport, AudioError> {
+        let (frame, details) = Frame::read_next_frame_with_details(
+            stream,
+            metadata,
+            decoder.get_crc_policy(),
+            decoder.get_arithmetic_mode(),
+        )?;
+        let subframes = frame
+            .get_subframes()
+            .iter()
+            .zip(details)
+            .map(|(subframe, details)| SubframeReport {
+                subframe_type: *subframe.get_type(),
+                order: subframe.get_order(),
+                wasted_bits: subframe.get_wasted_bits(),
+                details,
+            })
+            .collect();
+
+        Ok(FrameReport {
+            offset,
+            size: stream.get_position()? - offset,
+            header: frame.get_header().clone(),
+            crc: frame.get_crc(),
+            subframes,
+        })
+    }
+
+    pub fn get_frames(&self) -> &[FrameReport] {
+        &self.frames
+    }
+
+    pub fn get_damaged_frames(&self) -> &[DamagedFrame] {
+        &self.damaged_frames
+    }
+
+    pub fn get_histograms(&self) -> &Histograms {
+        &self.histograms
+    }
+
+    // Method to write the analysis as text, one line per frame, subframe and partition
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            let header = &frame.header;
+            let _ = writeln!(
+                text,
+                "frame={}\toffset={}\tbits={}\tblocksize={}\tsample_rate={}\tchannels={}\tchannel_assignment={}\tbits_per_sample={}\tfirst_sample={}\tcrc={:#06x}",
+                i,
+                frame.offset,
+                frame.size * 8,
+                header.get_block_size(),
+                header.get_sample_rate(),
+                header.get_num_channels(),
+                header.get_channel_assignment(),
+                header.get_bit_depth(),
+                header.get_first_sample(),
+                frame.crc
+            );
+
+            for (channel, subframe) in frame.subframes.iter().enumerate() {
+                let details = &subframe.details;
+                let _ = write!(
+                    text,
+                    "\tsubframe={}\twasted_bits={}\ttype={}\torder={}\tbits={}",
+                    channel,
+                    subframe.wasted_bits,
+                    type_name(subframe.subframe_type),
+                    subframe.order,
+                    details.get_bits()
+                );
+                if subframe.subframe_type == SubframeType::LPC {
+                    let _ = write!(
+                        text,
+                        "\tqlp_coeff_precision={}\tquantization_level={}",
+                        details.get_precision(),
+                        details.get_shift()
+                    );
+                }
+                text.push('\n');
+
+                if subframe.subframe_type == SubframeType::LPC {
+                    for (i, coefficient) in details.get_coefficients().iter().enumerate() {
+                        let _ = writeln!(text, "\t\tqlp_coeff[{}]={}", i, coefficient);
+                    }
+                }
+
+                if let Some(residual) = details.get_residual() {
+                    let _ = writeln!(
+                        text,
+                        "\t\tresidual_coding={:?}\tpartition_order={}\tresidual_bits={}",
+                        residual.get_coding_method(),
+                        residual.get_partition_order(),
+                        residual.get_bits()
+                    );
+                    for (i, partition) in residual.get_partitions().iter().enumerate() {
+                        let _ = match partition.get_rice_parameter() {
+                            Some(parameter) => {
+                                write!(text, "\t\t\tpartition={}\tparameter={}", i, parameter)
+                            }
+                            None => write!(
+                                text,
+                                "\t\t\tpartition={}\tescape_bits={}",
+                                i,
+                                partition.get_escape_bits()
+                            ),
+                        };
+                        let _ = writeln!(
+                            text,
+                            "\tsamples={}\tbits={}",
+                            partition.get_num_samples(),
+                            partition.get_bits()
+                        );
+                    }
+                }
+            }
+        }
+
+        for damaged in &self.damaged_frames {
+            let _ = writeln!(
+                text,
+                "damaged_frame\toffset={}\terror={}",
+                damaged.offset, damaged.error
+            );
+        }
+
+        let histograms = &self.histograms;
+        let _ = writeln!(text, "\nframes={}", self.frames.len());
+        let _ = writeln!(text, "damaged_frames={}", self.damaged_frames.len());
+        write_text_histogram(&mut text, "block_sizes", &histograms.block_sizes);
+        write_text_histogram(
+            &mut text,
+            "channel_assignments",
+            &histograms.channel_assignments,
+        );
+        write_text_histogram(&mut text, "subframe_types", &histograms.subframe_types);
+        write_text_histogram(&mut text, "fixed_orders", &histograms.fixed_orders);
+        write_text_histogram(&mut text, "lpc_orders", &histograms.lpc_orders);
+        write_text_histogram(&mut text, "lpc_precisions", &histograms.lpc_precisions);
+        write_text_histogram(&mut text, "partition_orders", &histograms.partition_orders);
+        write_text_histogram(&mut text, "rice_parameters", &histograms.rice_parameters);
+        let _ = writeln!(text, "escaped_partitions={}", histograms.escaped_partitions);
+        write_text_histogram(&mut text, "wasted_bits", &histograms.wasted_bits);
+        text
+    }
+
+    /**
+     * Method to write the analysis as a JSON object holding a "frames" array,
+     * a "damaged_frames" array and a "histograms" object, whose keys are the
+     * values counted
+     */
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"frames\":[");
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let header = &frame.header;
+            let _ = write!(
+                json,
+                "{{\"offset\":{},\"size\":{},\"block_size\":{},\"sample_rate\":{},\"channels\":{},\"channel_assignment\":{},\"bit_depth\":{},\"first_sample\":{},\"header_crc\":{},\"crc\":{},\"subframes\":[",
+                frame.offset,
+                frame.size,
+                header.get_block_size(),
+                header.get_sample_rate(),
+                header.get_num_channels(),
+                header.get_channel_assignment(),
+                header.get_bit_depth(),
+                header.get_first_sample(),
+                header.get_crc(),
+                frame.crc
+            );
+
+            for (j, subframe) in frame.subframes.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                let details = &subframe.details;
+                let _ = write!(
+                    json,
+                    "{{\"type\":\"{}\",\"order\":{},\"wasted_bits\":{},\"bits\":{}",
+                    type_name(subframe.subframe_type),
+                    subframe.order,
+                    subframe.wasted_bits,
+                    details.get_bits()
+                );
+                if subframe.subframe_type == SubframeType::LPC {
+                    let _ = write!(
+                        json,
+                        ",\"precision\":{},\"shift\":{},\"coefficients\":[",
+                        details.get_precision(),
+                        details.get_shift()
+                    );
+                    for (k, coefficient) in details.get_coefficients().iter().enumerate() {
+                        let _ = write!(json, "{}{}", if k > 0 { "," } else { "" }, coefficient);
+                    }
+                    json.push(']');
+                }
+
+                if let Some(residual) = details.get_residual() {
+                    let _ = write!(
+                        json,
+                        ",\"residual\":{{\"coding_method\":\"{:?}\",\"partition_order\":{},\"bits\":{},\"partitions\":[",
+                        residual.get_coding_method(),
+                        residual.get_partition_order(),
+                        residual.get_bits()
+                    );
+                    for (k, partition) in residual.get_partitions().iter().enumerate() {
+                        if k > 0 {
+                            json.push(',');
+                        }
+                        let _ = match partition.get_rice_parameter() {
+                            Some(parameter) => write!(json, "{{\"rice_parameter\":{}", parameter),
+                            None => {
+                                write!(json, "{{\"escape_bits\":{}", partition.get_escape_bits())
+                            }
+                        };
+                        let _ = write!(
+                            json,
+                            ",\"samples\":{},\"bits\":{}}}",
+                            partition.get_num_samples(),
+                            partition.get_bits()
+                        );
+                    }
+                    json.push_str("]}");
+                }
+                json.push('}');
+            }
+            json.push_str("]}");
+        }
+
+        json.push_str("],\"damaged_frames\":[");
+        for (i, damaged) in self.damaged_frames.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{{\"offset\":{},\"error\":\"{}\"}}",
+                if i > 0 { "," } else { "" },
+                damaged.offset,
+                escape_json(&damaged.error)
+            );
+        }
+
+        let histograms = &self.histograms;
+        json.push_str("],\"histograms\":{");
+        write_json_histogram(&mut json, "block_sizes", &histograms.block_sizes);
+        json.push(',');
+        write_json_histogram(
+            &mut json,
+            "channel_assignments",
+            &histograms.channel_assignments,
+        );
+        json.push(',');
+        write_json_histogram(&mut json, "subframe_types", &histograms.subframe_types);
+        json.push(',');
+        write_json_histogram(&mut json, "fixed_orders", &histograms.fixed_orders);
+        json.push(',');
+        write_json_histogram(&mut json, "lpc_orders", &histograms.lpc_orders);
+        json.push(',');
+        write_json_histogram(&mut json, "lpc_precisions", &histograms.lpc_precisions);
+        json.push(',');
+        write_json_histogram(&mut json, "partition_orders", &histograms.partition_orders);
+        json.push(',');
+        write_json_histogram(&mut json, "rice_parameters", &histograms.rice_parameters);
+        let _ = write!(
+            json,
+            ",\"escaped_partitions\":{},",
+            histograms.escaped_partitions
+        );
+        write_json_histogram(&mut json, "wasted_bits", &histograms.wasted_bits);
+        json.push_str("}}");
+        json
+    }
+}
+
+// Function to get the name of a subframe type as RFC 9639 spells it
+pub fn type_name(subframe_type: SubframeType) -> &'static str {
+    match subframe_type {
+        SubframeType::Constant => "CONSTANT",
+        SubframeType::Verbatim => "VERBATIM",
+        SubframeType::Fixed => "FIXED",
+        SubframeType::LPC => "LPC",
+    }
+}
+
+// Write a histogram as one line of value:count pairs
+fn write_text_histogram<K: std::fmt::Display>(
+    text: &mut String,
+    name: &str,
+    counts: &BTreeMap<K, u64>,
+) {
+    let _ = write!(text, "{}=", name);
+    for (i, (value, count)) in counts.iter().enumerate() {
+        let _ = write!(text, "{}{}:{}", if i > 0 { " " } else { "" }, value, count);
+    }
+    text.push('\n');
+}
+
+// Escape a string for a JSON string literal
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Write a histogram as a named JSON object, whose keys must be strings
+fn write_json_histogram<K: std::fmt::Display>(
+    json: &mut String,
+    name: &str,
+    counts: &BTreeMap<K, u64>,
+) {
+    let _ = write!(json, "\"{}\":{{", name);
+    for (i, (value, count)) in counts.iter().enumerate() {
+        let _ = write!(
+            json,
+            "{}\"{}\":{}",
+            if i > 0 { "," } else { "" },
+            value,
+            count
+        );
+    }
+    json.push('}');
+}
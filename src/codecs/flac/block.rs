@@ -23,6 +23,7 @@ pub enum BlockType {
 #[derive(Debug)]
 pub struct Block {
     block_type: BlockType,
+    is_last: bool, // This marks the last metadata block before the frames
     length: usize, // This represents the length of the block in bytes
     data: Vec<u8>, // This is the raw data contained in the block
 }
@@ -32,6 +33,10 @@ impl Block {
         &self.block_type
     }
 
+    pub fn is_last(&self) -> bool {
+        self.is_last
+    }
+
     pub fn get_length(&self) -> usize {
         self.length
     }
@@ -51,7 +56,12 @@ pub const METADATA_BLOCK_TYPES: [BlockType; 7] = [
     BlockType::Picture,
 ];
 
-// Fuction to Read and return the next block from the stream
+/**
+ * Fuction to Read and return the next block from the stream
+ * Blocks of types not listed in read_types are skipped and returned without
+ * their data, so callers can still tell where the metadata ends
+ * Returns None if the stream ends before a complete block header
+ */
 pub fn read_next_block(
     stream: &mut Stream,
     read_types: &[BlockType],
@@ -92,26 +102,18 @@ pub fn read_next_block(
         | block_length_bytes[2] as usize;
 
     // Check if we should read this block type
-    if !read_types.contains(&block_type) {
+    let data = if read_types.contains(&block_type) {
+        // Read the data in the block
+        stream.read_bytes(length)?
+    } else {
         // If not, skip the block
         stream.reader().seek(SeekFrom::Current(length as i64))?;
-        return Ok(None);
-    }
-
-    // Read the data in the block
-    let data = match stream.read_bytes(length) {
-        Ok(bytes) => bytes,
-        Err(ref e) if e.is_eof() => return Ok(None),
-        Err(e) => return Err(e),
+        Vec::new()
     };
 
-    // If it's the last block, return None to indicate end of metadata blocks
-    if is_last {
-        return Ok(None);
-    }
-
     Ok(Some(Block {
         block_type,
+        is_last,
         length,
         data,
     }))
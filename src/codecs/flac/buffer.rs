@@ -0,0 +1,121 @@
+// src/codecs/flac/buffer.rs
+
+// This module holds the reusable buffers that frames are decoded into
+
+use super::{data::Metadata, predictor::MAX_LPC_ORDER};
+use crate::common::errors::AudioError;
+
+/**
+ * Caller-owned planar buffers that frames are decoded into
+ * Sized once from STREAMINFO and reused for every frame, so decoding a
+ * stream allocates nothing per frame once the buffers are in place
+ */
+pub struct FrameBuffer {
+    pub(crate) channels: Vec<Vec<i32>>, // Decoded samples of each channel
+    pub(crate) subframes: Vec<Vec<i64>>, // Subframe samples before stereo decorrelation
+    pub(crate) scratch: Vec<i32>,       // Working space of the 32-bit LPC kernels
+    pub(crate) num_channels: usize,     // Channels in the last decoded frame
+    pub(crate) block_size: usize,       // Samples per channel in the last decoded frame
+    pub(crate) start: usize,            // Samples skipped at the start of the block, after a seek
+    pub(crate) first_sample: u64,       // Index of the first sample of the block in the stream
+    pub(crate) sample_rate: u32,
+    pub(crate) bit_depth: u8,
+}
+
+impl FrameBuffer {
+    // Constructor method to create buffers holding blocks of up to max_block_size samples
+    pub fn new(num_channels: usize, max_block_size: usize) -> Self {
+        let mut buffer = Self {
+            channels: Vec::new(),
+            subframes: Vec::new(),
+            scratch: Vec::new(),
+            num_channels: 0,
+            block_size: 0,
+            start: 0,
+            first_sample: 0,
+            sample_rate: 0,
+            bit_depth: 0,
+        };
+        buffer.reserve(num_channels, max_block_size);
+        buffer
+    }
+
+    // Constructor method to create buffers sized for the largest frame of a stream
+    pub fn from_metadata(metadata: &Metadata) -> Result<Self, AudioError> {
+        match (metadata.get_num_channels(), metadata.get_max_block_size()) {
+            (Some(num_channels), Some(max_block_size)) => {
+                Ok(Self::new(num_channels as usize, max_block_size as usize))
+            }
+            _ => Err(AudioError::InvalidData(
+                "STREAMINFO is required to size the frame buffers".to_string(),
+            )),
+        }
+    }
+
+    /**
+     * Make room for a block, which only allocates if the block is larger than
+     * any before it, so a stream whose STREAMINFO understates its frames
+     * still decodes
+     */
+    pub(crate) fn reserve(&mut self, num_channels: usize, block_size: usize) {
+        if self.channels.len() < num_channels {
+            self.channels.resize_with(num_channels, Vec::new);
+            self.subframes.resize_with(num_channels, Vec::new);
+        }
+        for channel in self.channels.iter_mut().filter(|c| c.len() < block_size) {
+            channel.resize(block_size, 0);
+        }
+        for subframe in self.subframes.iter_mut().filter(|s| s.len() < block_size) {
+            subframe.resize(block_size, 0);
+        }
+        self.scratch
+            .reserve((MAX_LPC_ORDER + block_size).saturating_sub(self.scratch.len()));
+    }
+
+    // Hold a block of silence in place of samples lost to damaged data
+    pub(crate) fn fill_silence(
+        &mut self,
+        metadata: &Metadata,
+        first_sample: u64,
+        block_size: usize,
+    ) {
+        let num_channels = metadata.get_num_channels().unwrap_or(1) as usize;
+        self.reserve(num_channels, block_size);
+        for channel in &mut self.channels[..num_channels] {
+            channel[..block_size].fill(0);
+        }
+        self.num_channels = num_channels;
+        self.block_size = block_size;
+        self.start = 0;
+        self.first_sample = first_sample;
+        self.sample_rate = metadata.get_sample_rate().unwrap_or(0);
+        self.bit_depth = metadata.get_bit_depth().unwrap_or(8);
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    // Method to get the number of samples per channel in the last decoded block
+    pub fn get_block_size(&self) -> usize {
+        self.block_size - self.start
+    }
+
+    // Method to get the decoded samples of one channel
+    pub fn get_channel(&self, channel: usize) -> &[i32] {
+        &self.channels[channel][self.start..self.block_size]
+    }
+
+    // Method to get the index of the first sample of the block in the stream
+    pub fn get_first_sample(&self) -> u64 {
+        self.first_sample + self.start as u64
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+}
@@ -3,11 +3,43 @@
 // This module handles parsing FLAC metadata from the input stream.
 
 use super::block::{read_next_block, Block, BlockType};
-use crate::common::{errors::AudioError, stream::Stream};
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::common::{channels::ChannelLayout, errors::AudioError, stream::Stream};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use std::sync::Arc;
 
+// Sample number marking a placeholder seek point, which holds no position
+const PLACEHOLDER_SEEK_POINT: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+
+// Length of the STREAMINFO block in bytes
+const STREAM_INFO_LENGTH: usize = 34;
+
+// Vorbis comment holding the speaker positions of the channels, as a WAVEFORMATEXTENSIBLE mask
+const CHANNEL_MASK_TAG: &str = "WAVEFORMATEXTENSIBLE_CHANNEL_MASK";
+
+// Struct to represent one entry of the SEEKTABLE block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeekPoint {
+    sample_number: u64, // Index of the first sample in the target frame
+    offset: u64,        // Byte offset of the target frame from the first frame
+    num_samples: u16,   // Number of samples in the target frame
+}
+
+impl SeekPoint {
+    pub fn get_sample_number(&self) -> u64 {
+        self.sample_number
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_num_samples(&self) -> u16 {
+        self.num_samples
+    }
+}
+
 // Struct to hold parsed metadata of FLAC file
+#[derive(Debug, Clone)]
 pub struct Metadata {
     min_block_size: Option<u16>,
     max_block_size: Option<u16>,
@@ -18,51 +50,97 @@ pub struct Metadata {
     bit_depth: Option<u8>,
     total_samples: Option<u64>,
     md5_signature: Option<Arc<Vec<u8>>>, // Arc is used for efficient shared ownership
+    seek_points: Vec<SeekPoint>,         // Seek points in ascending sample order
+    first_frame_offset: Option<u64>,     // Byte offset of the first frame in the stream
+    vendor: Option<String>,
+    comments: Vec<(String, String)>, // Vorbis comments as field name and value, in stream order
 }
 
 impl Metadata {
     // Constructor that reads and parses metadata from a given Stream
     pub fn new(stream: &mut Stream) -> Result<Self, AudioError> {
-        // Initialization of Metadata with None values
-        let mut metadata = Metadata {
-            min_block_size: None,
-            max_block_size: None,
-            min_frame_size: None,
-            max_frame_size: None,
-            sample_rate: None,
-            num_channels: None,
-            bit_depth: None,
-            total_samples: None,
-            md5_signature: None,
-        };
+        let mut metadata = Self::empty();
 
         // Specify which block types to read
-        let read_types = &[BlockType::StreamInfo, BlockType::VorbisComment];
+        let read_types = &[
+            BlockType::StreamInfo,
+            BlockType::SeekTable,
+            BlockType::VorbisComment,
+        ];
 
-        // Loop to read blocks of the specified types
+        // Loop over the metadata blocks until the last one has been read
         while let Some(block) = read_next_block(stream, read_types)? {
+            let is_last = block.is_last();
+
             // Use block type to determine how to parse it
             match block.get_type() {
-                BlockType::StreamInfo => metadata.parse_stream_info(block)?,
+                BlockType::StreamInfo => metadata.parse_stream_info(block.get_data())?,
+                BlockType::SeekTable => metadata.parse_seek_table(block)?,
                 BlockType::VorbisComment => metadata.parse_vorbis_comment(block)?,
-                // Should not reach here as we only read the specified block types
-                _ => {
-                    return Err(AudioError::ParseError(
-                        "Unexpected block type encountered".into(),
-                    ))
-                }
+                // Other block types are skipped without their data
+                _ => {}
+            }
+
+            if is_last {
+                metadata.first_frame_offset = Some(stream.get_position()?);
+                break;
             }
         }
 
         Ok(metadata)
     }
 
-    // Parse STREAMINFO block and poplate corresponding fields in Metadata
-    fn parse_stream_info(&mut self, block: Block) -> Result<(), AudioError> {
-        let data = block.get_data();
+    /**
+     * Constructor for streams whose STREAMINFO comes from outside the FLAC
+     * stream, such as the codec private data of a container
+     * Takes the 34 bytes of STREAMINFO, optionally preceded by the 4-byte
+     * metadata block header as Ogg and MP4 store it
+     */
+    pub fn from_stream_info(data: &[u8]) -> Result<Self, AudioError> {
+        let data = match data.len() {
+            STREAM_INFO_LENGTH => data,
+            n if n == STREAM_INFO_LENGTH + 4
+                && data[0] & 0x7F == 0
+                && data[1..4] == [0, 0, STREAM_INFO_LENGTH as u8] =>
+            {
+                &data[4..]
+            }
+            n => {
+                return Err(AudioError::ParseError(format!(
+                    "STREAMINFO must be {} bytes long, not {}",
+                    STREAM_INFO_LENGTH, n
+                )))
+            }
+        };
+
+        let mut metadata = Self::empty();
+        metadata.parse_stream_info(data)?;
+        Ok(metadata)
+    }
+
+    // Metadata with no block parsed yet
+    fn empty() -> Self {
+        Metadata {
+            min_block_size: None,
+            max_block_size: None,
+            min_frame_size: None,
+            max_frame_size: None,
+            sample_rate: None,
+            num_channels: None,
+            bit_depth: None,
+            total_samples: None,
+            md5_signature: None,
+            seek_points: Vec::new(),
+            first_frame_offset: None,
+            vendor: None,
+            comments: Vec::new(),
+        }
+    }
 
+    // Parse STREAMINFO block and poplate corresponding fields in Metadata
+    fn parse_stream_info(&mut self, data: &[u8]) -> Result<(), AudioError> {
         // Check if the block has enough data to parse STREAMINFO block
-        if data.len() < 34 {
+        if data.len() < STREAM_INFO_LENGTH {
             return Err(AudioError::ParseError(
                 "Not enough data to parse STREAMINFO block".into(),
             ));
@@ -85,13 +163,13 @@ impl Metadata {
          * The number of channels is a 3-bit number
          * It's stored in the 2nd, 3rd, and 4th bits of the 13th byte
          */
-        let num_channels = Some((((data[12] & 0x0E) >> 1) + 1) as u8);
+        let num_channels = Some(((data[12] & 0x0E) >> 1) + 1);
 
         /*
          * The bit deptth per sample is a 5-bit number
          * It's stored in the last bit of the 13th byte and the first 4 bits of the 14th byte
          */
-        let bit_depth = Some((((data[12] & 0x01) << 4) | (data[13] >> 4) + 1) as u8);
+        let bit_depth = Some((((data[12] & 0x01) << 4) | (data[13] >> 4)) + 1);
 
         /*
          * The total number of samples is a 36-bit number
@@ -117,11 +195,58 @@ impl Metadata {
         Ok(())
     }
 
-    // Parse VORBIS_COMMENT block and poplate corresponding fields in Metadata
+    /**
+     * Parse SEEKTABLE block and populate the seek points in Metadata
+     * Each point is 18 bytes, and placeholder points are dropped
+     */
+    fn parse_seek_table(&mut self, block: Block) -> Result<(), AudioError> {
+        let data = block.get_data();
+
+        if data.len() % 18 != 0 {
+            return Err(AudioError::ParseError(
+                "SEEKTABLE block length is not a multiple of 18".into(),
+            ));
+        }
+
+        for mut point in data.chunks_exact(18) {
+            let sample_number = point.read_u64::<BigEndian>()?;
+            let offset = point.read_u64::<BigEndian>()?;
+            let num_samples = point.read_u16::<BigEndian>()?;
+
+            if sample_number != PLACEHOLDER_SEEK_POINT {
+                self.seek_points.push(SeekPoint {
+                    sample_number,
+                    offset,
+                    num_samples,
+                });
+            }
+        }
+
+        // The spec requires ascending order, but do not rely on it when seeking
+        self.seek_points.sort_by_key(|point| point.sample_number);
+
+        Ok(())
+    }
+
+    /**
+     * Parse VORBIS_COMMENT block and populate the vendor and comments in Metadata
+     * Unlike the rest of FLAC, lengths here are little-endian. Comments
+     * without an = sign are dropped
+     */
     fn parse_vorbis_comment(&mut self, block: Block) -> Result<(), AudioError> {
-        let _data = block.get_data();
+        let mut data = block.get_data();
+
+        self.vendor = Some(read_vorbis_string(&mut data)?);
+        let num_comments = data.read_u32::<LittleEndian>().map_err(|_| {
+            AudioError::ParseError("VORBIS_COMMENT block ends before its comment count".into())
+        })?;
 
-        // TODO: Parse binary data according to flac specs
+        for _ in 0..num_comments {
+            let comment = read_vorbis_string(&mut data)?;
+            if let Some((name, value)) = comment.split_once('=') {
+                self.comments.push((name.to_string(), value.to_string()));
+            }
+        }
 
         Ok(())
     }
@@ -162,6 +287,58 @@ impl Metadata {
         self.md5_signature.clone()
     }
 
+    pub fn get_seek_points(&self) -> &[SeekPoint] {
+        &self.seek_points
+    }
+
+    // Method to get where the frames start, known once the last metadata block has been read
+    pub fn get_first_frame_offset(&self) -> Option<u64> {
+        self.first_frame_offset
+    }
+
+    pub fn get_vendor(&self) -> Option<&str> {
+        self.vendor.as_deref()
+    }
+
+    pub fn get_comments(&self) -> &[(String, String)] {
+        &self.comments
+    }
+
+    // Method to get the first value of a Vorbis comment, whose field names ignore case
+    pub fn get_comment(&self, name: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Method to get the WAVEFORMATEXTENSIBLE channel mask tag, written in hex with 0x or in decimal
+    pub fn get_channel_mask(&self) -> Option<u32> {
+        let value = self.get_comment(CHANNEL_MASK_TAG)?.trim();
+        match value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        }
+    }
+
+    /**
+     * Method to get the speaker positions of the channels
+     * The channel mask tag overrides the FLAC default order when it sets at
+     * least one bit per channel
+     */
+    pub fn get_channel_layout(&self) -> Option<ChannelLayout> {
+        let num_channels = self.num_channels? as usize;
+        match self.get_channel_mask() {
+            Some(mask) if mask.count_ones() as usize >= num_channels => {
+                Some(ChannelLayout::from_mask(mask, num_channels))
+            }
+            _ => Some(ChannelLayout::new(num_channels)),
+        }
+    }
+
     /**
      * These two methods return a ShortFormat of LongFormat struct, which
      * are simplified version of the Metadata for use in other parts of the code
@@ -189,6 +366,18 @@ impl Metadata {
     }
 }
 
+// Read a Vorbis comment string, a little-endian u32 length followed by that many UTF-8 bytes
+fn read_vorbis_string(data: &mut &[u8]) -> Result<String, AudioError> {
+    let truncated = || AudioError::ParseError("VORBIS_COMMENT block is truncated".into());
+    let length = data.read_u32::<LittleEndian>().map_err(|_| truncated())? as usize;
+    if data.len() < length {
+        return Err(truncated());
+    }
+    let (bytes, rest) = data.split_at(length);
+    *data = rest;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
 #[derive(Debug)]
 pub struct ShortFormat {
     pub sample_rate: Option<u32>,
@@ -1,67 +1,562 @@
 // src/codecs/flac/decoder.rs
-use crate::common::{errors::AudioError, stream::Stream};
+use crate::common::{errors::AudioError, position::Position, stream::Stream};
 
 use super::{
-    block::{read_next_block, METADATA_BLOCK_TYPES},
-    frame::Frame,
+    buffer::FrameBuffer,
+    data::Metadata,
+    frame::{Frame, FrameHeader},
+    frame_decoder::FrameDecoder,
+    index::{self, FrameIndex, FrameIndexMode, IndexKey},
+    parallel::ParallelFrames,
+    seek::{self, FramePosition},
 };
+use std::ops::Range;
 
-pub struct Decoder {}
+// Largest block of silence produced at once when filling lost samples
+pub(crate) const MAX_SILENCE_BLOCK_SIZE: u64 = 4096;
+
+// Enum to represent how the decoder handles frames with a CRC mismatch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrcPolicy {
+    Error,  // Fail decoding with AudioError::CrcMismatch
+    Warn,   // Print a warning and keep the decoded frame
+    Ignore, // Silently keep the decoded frame
+}
+
+impl CrcPolicy {
+    // Apply the policy to a CRC mismatch described by the message
+    pub fn check(self, message: String) -> Result<(), AudioError> {
+        match self {
+            CrcPolicy::Error => Err(AudioError::CrcMismatch(message)),
+            CrcPolicy::Warn => {
+                eprintln!("Warning: CRC mismatch: {}", message);
+                Ok(())
+            }
+            CrcPolicy::Ignore => Ok(()),
+        }
+    }
+}
+
+// Enum to represent the outcome of checking the decoded audio against STREAMINFO
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Md5Status {
+    Match,    // The decoded audio has the MD5 signature stored in STREAMINFO
+    Mismatch, // The decoded audio differs from what was encoded
+    Unset,    // STREAMINFO holds no signature (all zero), so nothing was checked
+}
+
+// Enum to represent how the decoder handles damaged or missing frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecoveryMode {
+    Disabled,    // Fail decoding at the first damaged frame
+    Skip,        // Resynchronize on the next valid frame and drop the lost samples
+    FillSilence, // Resynchronize and fill the lost samples with silence
+}
+
+impl RecoveryMode {
+    /**
+     * Decide whether decoding goes on past an error met while reading a frame
+     * Damaged data is skipped when recovery is enabled, but I/O failures are
+     * not damaged data and always fail decoding
+     */
+    pub(crate) fn skip(self, err: AudioError) -> Result<(), AudioError> {
+        match err {
+            AudioError::Io(_) => Err(err),
+            _ if self == RecoveryMode::Disabled => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Enum to represent how sample predictions are computed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticMode {
+    Fast,    // Use kernels specialized per order, where overflow wraps around
+    Checked, // Check every step, failing with AudioError::ArithmeticOverflow
+}
+
+// Struct to represent where the next decode resumes after a seek
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResumePoint {
+    pub frame: FramePosition, // Frame holding the target sample
+    pub sample: u64,          // First sample to yield
+}
+
+pub struct Decoder {
+    crc_policy: CrcPolicy,
+    recovery_mode: RecoveryMode,
+    arithmetic_mode: ArithmeticMode,
+    num_threads: usize, // Threads decoding frames in parallel, 1 decodes serially
+    lost_ranges: Vec<Range<u64>>, // Sample ranges lost to damaged data in the last decode
+    md5_status: Option<Md5Status>, // Set once the whole stream has been decoded
+    resume_point: Option<ResumePoint>, // Set by a seek, used by the next decode
+    frame_index_mode: FrameIndexMode,
+    frame_index: Option<FrameIndex>, // Built or loaded on first use
+    next_sample: u64,                // First sample expected from the next decode_into
+    silence: Range<u64>,             // Lost samples decode_into still has to fill
+}
 
 impl Decoder {
     pub fn new(_stream: &mut Stream) -> Result<Self, AudioError> {
-        Ok(Self {})
+        Ok(Self {
+            crc_policy: CrcPolicy::Error,
+            recovery_mode: RecoveryMode::Disabled,
+            arithmetic_mode: ArithmeticMode::Fast,
+            num_threads: 1,
+            lost_ranges: Vec::new(),
+            md5_status: None,
+            resume_point: None,
+            frame_index_mode: FrameIndexMode::Disabled,
+            frame_index: None,
+            next_sample: 0,
+            silence: 0..0,
+        })
+    }
+
+    // Method to get the MD5 check result, available after decoding the whole stream
+    pub fn get_md5_status(&self) -> Option<Md5Status> {
+        self.md5_status
+    }
+
+    pub fn get_crc_policy(&self) -> CrcPolicy {
+        self.crc_policy
+    }
+
+    pub fn set_crc_policy(&mut self, crc_policy: CrcPolicy) {
+        self.crc_policy = crc_policy;
     }
 
-    pub fn decode(&mut self, stream: &mut Stream) -> Result<Vec<u32>, AudioError> {
-        let mut pcm_data = vec![];
-        let mut is_last_metadata_block = false;
+    pub fn get_recovery_mode(&self) -> RecoveryMode {
+        self.recovery_mode
+    }
+
+    pub fn set_recovery_mode(&mut self, recovery_mode: RecoveryMode) {
+        self.recovery_mode = recovery_mode;
+    }
+
+    pub fn get_arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    // Method to choose between fast predictions and checked arithmetic, which helps debug damaged streams
+    pub fn set_arithmetic_mode(&mut self, arithmetic_mode: ArithmeticMode) {
+        self.arithmetic_mode = arithmetic_mode;
+    }
 
-        while !is_last_metadata_block {
-            // Read next metadata block
-            match read_next_block(stream, &METADATA_BLOCK_TYPES) {
-                Ok(Some(block)) => {
-                    // println!("metadata block: {:?}", block);
-                    // println!("block type: {:?}", block.get_type());
-                    // println!("block length: {:?}", block.get_length());
+    pub fn get_num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /**
+     * Method to choose how many threads decode frames in parallel
+     * Only a decode of the whole stream runs in parallel, and 0 is taken as 1
+     */
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads.max(1);
+    }
+
+    pub fn get_frame_index_mode(&self) -> FrameIndexMode {
+        self.frame_index_mode
+    }
+
+    // Method to choose whether seeks use a frame index, kept in memory or also in a sidecar file
+    pub fn set_frame_index_mode(&mut self, frame_index_mode: FrameIndexMode) {
+        self.frame_index_mode = frame_index_mode;
+    }
+
+    /**
+     * Method to get the index of every frame in the stream, building it on
+     * first access
+     * In persistent mode the index is loaded from the sidecar of the file when
+     * its key still matches, and otherwise saved there once built. Failing to
+     * save only prints a warning
+     * The stream is left where it was
+     */
+    pub fn get_frame_index(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+    ) -> Result<&FrameIndex, AudioError> {
+        if self.frame_index.is_none() {
+            let position = stream.get_position()?;
+            let sidecar = match (self.frame_index_mode, stream.get_path()) {
+                (FrameIndexMode::Persistent, Some(path)) => {
+                    Some((index::sidecar_path(path), IndexKey::new(path, metadata)?))
                 }
-                Ok(None) => {
-                    is_last_metadata_block = true;
+                _ => None,
+            };
+
+            let cached = sidecar
+                .as_ref()
+                .and_then(|(path, key)| FrameIndex::load(path, key));
+            let frame_index = match cached {
+                Some(frame_index) => frame_index,
+                None => {
+                    let frame_index = FrameIndex::build(stream, metadata)?;
+                    if let Some((path, key)) = &sidecar {
+                        if let Err(err) = frame_index.save(path, key) {
+                            eprintln!(
+                                "Warning: could not save frame index to {}: {}",
+                                path.display(),
+                                err
+                            );
+                        }
+                    }
+                    frame_index
                 }
-                Err(err) => return Err(err),
+            };
+
+            stream.seek_to(position)?;
+            self.frame_index = Some(frame_index);
+        }
+        Ok(self.frame_index.as_ref().unwrap())
+    }
+
+    /**
+     * Method to get the sample ranges lost to damaged data during the last
+     * decode, or since the first decode_into call
+     */
+    pub fn get_lost_ranges(&self) -> &[Range<u64>] {
+        &self.lost_ranges
+    }
+
+    // Method to start decoding a stream positioned at its first frame, one block at a time
+    pub fn frames<'a>(
+        &'a mut self,
+        stream: &'a mut Stream,
+        metadata: &'a Metadata,
+    ) -> FrameDecoder<'a> {
+        FrameDecoder::new(self, stream, metadata)
+    }
+
+    /**
+     * Method to decode the whole stream into one buffer, holding the samples
+     * of each block channel after channel
+     * With more than one thread, frames are decoded ahead in batches and handed
+     * out in order, with the same samples, lost ranges, MD5 check and CRC
+     * warnings as a serial decode
+     */
+    pub fn decode(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+    ) -> Result<Vec<i32>, AudioError> {
+        let blocks = if self.num_threads > 1 {
+            let frames = ParallelFrames::new(
+                stream,
+                self.crc_policy,
+                self.arithmetic_mode,
+                self.num_threads,
+            )?;
+            FrameDecoder::with_frames(self, stream, metadata, frames)
+        } else {
+            self.frames(stream, metadata)
+        };
+
+        // Size the output up front when STREAMINFO gives the length of the stream
+        let mut pcm_data = Vec::with_capacity(
+            (metadata.get_total_samples().unwrap_or(0) as usize)
+                .saturating_mul(metadata.get_num_channels().unwrap_or(0) as usize),
+        );
+        for block in blocks {
+            for channel in block?.get_channels() {
+                pcm_data.extend_from_slice(channel);
             }
         }
+        Ok(pcm_data)
+    }
+
+    /**
+     * Method to seek to a sample, so the next decode of the stream starts
+     * exactly at that sample
+     * Uses the frame index when enabled, otherwise the SEEKTABLE or bisection
+     * The MD5 signature cannot be checked by a decode that starts after a seek
+     */
+    pub fn seek_to_sample(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+        sample: u64,
+    ) -> Result<(), AudioError> {
+        let frame = if self.frame_index_mode == FrameIndexMode::Disabled {
+            seek::find_frame_containing(
+                stream,
+                metadata,
+                sample,
+                self.crc_policy,
+                self.arithmetic_mode,
+            )?
+        } else {
+            self.get_frame_index(stream, metadata)?
+                .find_frame_containing(sample)?
+        };
+        stream.seek_to(frame.get_offset())?;
+        self.next_sample = frame.get_first_sample();
+        self.silence = 0..0;
+        self.resume_point = Some(ResumePoint { frame, sample });
+        Ok(())
+    }
+
+    /**
+     * Method to decode the samples from start up to but not including end,
+     * one block at a time
+     * Positions are sample indices or times, and an end past the stream stops
+     * at its last sample
+     */
+    pub fn frames_range<'a>(
+        &'a mut self,
+        stream: &'a mut Stream,
+        metadata: &'a Metadata,
+        start: impl Into<Position>,
+        end: impl Into<Position>,
+    ) -> Result<FrameDecoder<'a>, AudioError> {
+        let sample_rate = metadata.get_sample_rate().unwrap_or(0);
+        let start = start.into().to_sample(sample_rate);
+        let mut end = end.into().to_sample(sample_rate);
+        if let Some(total_samples) = metadata.get_total_samples().filter(|&total| total != 0) {
+            end = end.min(total_samples);
+        }
+        if start > end {
+            return Err(AudioError::InvalidData(format!(
+                "Range starts at sample {} after it ends at sample {}",
+                start, end
+            )));
+        }
+
+        // An empty range yields nothing, even at the end of the stream where there is no frame to seek to
+        if start == end {
+            return Ok(self.frames(stream, metadata).until(0));
+        }
+        self.seek_to_sample(stream, metadata, start)?;
+        Ok(self.frames(stream, metadata).until(end))
+    }
 
+    /**
+     * Method to decode the samples from start up to but not including end into
+     * one buffer, holding the samples of each block channel after channel
+     */
+    pub fn decode_range(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+        start: impl Into<Position>,
+        end: impl Into<Position>,
+    ) -> Result<Vec<i32>, AudioError> {
+        let mut pcm_data = Vec::new();
+        for block in self.frames_range(stream, metadata, start, end)? {
+            for channel in block?.get_channels() {
+                pcm_data.extend_from_slice(channel);
+            }
+        }
+        Ok(pcm_data)
+    }
+
+    /**
+     * Method to decode the next frame into caller-owned buffers, without
+     * allocating once the buffers are large enough for the stream
+     * Damaged frames are skipped when recovery is enabled, and the samples
+     * they held are recorded as lost and, with RecoveryMode::FillSilence,
+     * filled in blocks of silence. The MD5 signature is not checked
+     * Returns false once the stream has no more frames
+     */
+    pub fn decode_into(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+        buffer: &mut FrameBuffer,
+    ) -> Result<bool, AudioError> {
         loop {
-            let header_result = stream.peek_u16();
-            match header_result {
-                Ok(header) => {
-                    if header == 0xFFF8 {
-                        println!("Found frame header");
-                        // Frame sync code found
-                        match Frame::read_next_frame(stream) {
-                            Ok(frame) => {
-                                println!("decoding frame");
-                                for subframe in frame.get_subframes() {
-                                    pcm_data.extend_from_slice(&subframe.get_data());
-                                }
-                            }
-                            Err(err) => return Err(err),
-                        }
-                    } else {
-                        return Err(AudioError::InvalidData(
-                            "Expected frame header not found".to_string(),
-                        ));
+            // Lost samples are filled before the frame that follows them
+            if !self.silence.is_empty() {
+                let block_size =
+                    (self.silence.end - self.silence.start).min(MAX_SILENCE_BLOCK_SIZE);
+                buffer.fill_silence(metadata, self.silence.start, block_size as usize);
+                self.silence.start += block_size;
+                return Ok(true);
+            }
+
+            let (crc_policy, arithmetic_mode) = (self.crc_policy, self.arithmetic_mode);
+            let frame = self.read_frame(stream, metadata, |stream| {
+                let start = stream.get_bits_read();
+                let header =
+                    Frame::read_into(stream, metadata, crc_policy, arithmetic_mode, buffer)?;
+                Ok((header, stream.get_bits_read() - start))
+            })?;
+            let resume_sample = self
+                .resume_point
+                .map_or(0, |resume_point| resume_point.sample);
+
+            let Some((header, frame_bits)) = frame else {
+                // Samples missing from the end of the stream were lost as well
+                match metadata.get_total_samples() {
+                    Some(total_samples)
+                        if self.recovery_mode != RecoveryMode::Disabled
+                            && total_samples > self.next_sample =>
+                    {
+                        self.silence =
+                            self.lose_samples(self.next_sample..total_samples, resume_sample);
+                        self.next_sample = total_samples;
+                        continue;
                     }
+                    _ => return Ok(false),
                 }
+            };
+
+            // Samples between the previous frame and this one were lost to damaged data
+            let first_sample = header.get_first_sample();
+            if self.recovery_mode != RecoveryMode::Disabled && first_sample > self.next_sample {
+                self.silence = self.lose_samples(self.next_sample..first_sample, resume_sample);
+                self.next_sample = first_sample;
+
+                // Read the frame again once the silence before it has been filled
+                if !self.silence.is_empty() {
+                    let frame_start = stream.get_position()? - frame_bits.div_ceil(8);
+                    stream.seek_to(frame_start)?;
+                    continue;
+                }
+            }
+            self.next_sample = first_sample + header.get_block_size() as u64;
+            break;
+        }
+
+        // The first frame after a seek starts at the sample sought
+        if let Some(resume_point) = self.resume_point.take() {
+            let skipped = resume_point.sample.saturating_sub(buffer.first_sample);
+            buffer.start = (skipped as usize).min(buffer.block_size);
+        }
+        Ok(true)
+    }
+
+    /**
+     * Read the next frame with the read function, which starts at a sync code
+     * When recovery is enabled, damaged data is skipped by scanning for the
+     * next valid frame header from the byte after the start of the frame
+     * Returns None at the end of the stream
+     */
+    pub(crate) fn read_frame<T>(
+        &self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+        mut read: impl FnMut(&mut Stream) -> Result<T, AudioError>,
+    ) -> Result<Option<T>, AudioError> {
+        loop {
+            // Count the bytes of the frame as it is read, rather than asking the stream where it is
+            let start = stream.get_bits_read();
+            let result = match stream.peek_u16() {
+                // The last bit of the sync word is the blocking strategy
+                Ok(header) if header & 0xFFFE == 0xFFF8 => read(stream),
+                Ok(_) => Err(AudioError::InvalidData(
+                    "Expected frame header not found".to_string(),
+                )),
+                Err(AudioError::EOF) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+
+            match result {
+                Ok(frame) => return Ok(Some(frame)),
                 Err(err) => {
-                    if let AudioError::EOF = err {
-                        break; // If EOF is reached, exit the loop
+                    self.recovery_mode.skip(err)?;
+                    let frame_start =
+                        stream.get_position()? - (stream.get_bits_read() - start).div_ceil(8);
+                    if Self::find_next_frame(stream, metadata, frame_start + 1)?.is_none() {
+                        return Ok(None);
                     }
-                    return Err(err); // Return error if it's anything else
                 }
             }
         }
-        Ok(pcm_data)
+    }
+
+    /**
+     * Record a range of samples lost to damaged data
+     * Returns the part of the range from the start sample on that is to be
+     * filled with silence, which is empty unless the recovery mode fills it
+     */
+    pub(crate) fn lose_samples(&mut self, range: Range<u64>, start_sample: u64) -> Range<u64> {
+        let silence = match self.recovery_mode {
+            RecoveryMode::FillSilence => range.start.max(start_sample)..range.end,
+            _ => 0..0,
+        };
+        self.lost_ranges.push(range);
+        silence
+    }
+
+    /**
+     * Clear the results of a previous pass before decoding the stream again
+     * Returns where to resume if the stream was seeked since the last pass
+     */
+    pub(crate) fn start_pass(&mut self) -> Option<ResumePoint> {
+        self.lost_ranges.clear();
+        self.md5_status = None;
+        self.resume_point.take()
+    }
+
+    pub(crate) fn set_md5_status(&mut self, md5_status: Md5Status) {
+        self.md5_status = Some(md5_status);
+    }
+
+    /**
+     * Scan forward from a byte offset for the next frame sync code followed by
+     * a header whose CRC-8 checks out, and leave the stream positioned there
+     * Returns the byte offset of the frame, or None if the stream ends first
+     */
+    pub fn find_next_frame(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        from: u64,
+    ) -> Result<Option<u64>, AudioError> {
+        Self::find_next_frame_with(stream, metadata, from, CrcPolicy::Error)
+    }
+
+    /**
+     * Scan forward from a byte offset for the next frame sync code followed by
+     * a header, and leave the stream positioned there
+     * Headers with a CRC-8 mismatch are only found when the CRC policy keeps
+     * such frames, and no warning is printed for them
+     * Returns the byte offset of the frame, or None if the stream ends first
+     */
+    pub(crate) fn find_next_frame_with(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        from: u64,
+        crc_policy: CrcPolicy,
+    ) -> Result<Option<u64>, AudioError> {
+        let crc_policy = match crc_policy {
+            CrcPolicy::Error => CrcPolicy::Error,
+            _ => CrcPolicy::Ignore,
+        };
+        stream.seek_to(from)?;
+        let mut position = from;
+        let mut previous_byte = 0;
+
+        loop {
+            let byte = match stream.read_byte() {
+                Ok(byte) => byte,
+                Err(AudioError::EOF) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            position += 1;
+
+            if previous_byte == 0xFF && byte & 0xFE == 0xF8 {
+                let candidate = position - 2;
+                stream.seek_to(candidate)?;
+
+                match FrameHeader::read_next_header(stream, metadata, crc_policy) {
+                    Ok(_) => {
+                        stream.seek_to(candidate)?;
+                        return Ok(Some(candidate));
+                    }
+                    Err(AudioError::EOF) => return Ok(None),
+                    Err(AudioError::Io(err)) => return Err(AudioError::Io(err)),
+                    Err(_) => {
+                        // Resume the scan right after the false sync code
+                        position = candidate + 1;
+                        stream.seek_to(position)?;
+                        previous_byte = 0;
+                        continue;
+                    }
+                }
+            }
+            previous_byte = byte;
+        }
     }
 }
@@ -0,0 +1,130 @@
+// src/codecs/flac/demuxer.rs
+
+// This module splits a native FLAC stream into one packet per frame
+
+use super::{
+    data::Metadata,
+    decoder::{CrcPolicy, Decoder},
+    frame::FrameHeader,
+};
+use crate::codecs::packet::{CodecId, Demuxer, Packet, Track};
+use crate::common::{crc::crc16_update, errors::AudioError, stream::Stream};
+use std::io::Read;
+
+/**
+ * Demuxer for native FLAC streams, which hold a single track
+ * Frames do not record their length, so a frame ends where the next frame
+ * header starts, provided the bytes up to there pass the frame CRC-16
+ * Data that does not start with a valid frame header, such as tags after the
+ * last frame, is skipped
+ */
+pub struct FlacDemuxer {
+    metadata: Metadata,
+    tracks: Vec<Track>,
+}
+
+impl FlacDemuxer {
+    /**
+     * Constructor method to read the stream marker and metadata blocks,
+     * leaving the stream at the first frame
+     */
+    pub fn new(stream: &mut Stream) -> Result<Self, AudioError> {
+        let mut marker = [0; 4];
+        stream.reader().read_exact(&mut marker)?;
+        if &marker != b"fLaC" {
+            return Err(AudioError::UnsupportedFileCodec);
+        }
+
+        let metadata = Metadata::new(stream)?;
+        let track = Track::new(
+            0,
+            CodecId::Flac,
+            metadata.get_sample_rate().unwrap_or(0),
+            metadata.get_num_channels().unwrap_or(0),
+            metadata.get_bit_depth().unwrap_or(0),
+            metadata.get_total_samples().unwrap_or(0),
+        );
+
+        Ok(Self {
+            metadata,
+            tracks: vec![track],
+        })
+    }
+
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl Demuxer for FlacDemuxer {
+    fn get_tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    fn next_packet(&mut self, stream: &mut Stream) -> Result<Option<Packet>, AudioError> {
+        let position = stream.get_position()?;
+        let Some(start) = Decoder::find_next_frame(stream, &self.metadata, position)? else {
+            return Ok(None);
+        };
+
+        let header = FrameHeader::read_next_header(stream, &self.metadata, CrcPolicy::Error)?;
+        let bytes = read_frame_bytes(stream, &self.metadata, start)?;
+
+        Ok(Some(Packet::new(
+            CodecId::Flac,
+            0,
+            header.get_first_sample(),
+            header.get_block_size() as u64,
+            bytes,
+        )))
+    }
+}
+
+/**
+ * Function to find where the frame starting at an offset ends, by looking for
+ * the next frame header that the frame CRC-16 agrees with
+ * A damaged frame ends at the first header after it instead. The search stops
+ * max_frame_size bytes after the start when STREAMINFO sets it, and a frame
+ * with no header after it runs up to there or to the end of the stream
+ * Returns the bytes of the frame, with the stream left just past them
+ */
+fn read_frame_bytes(
+    stream: &mut Stream,
+    metadata: &Metadata,
+    start: u64,
+) -> Result<Vec<u8>, AudioError> {
+    let len = stream.get_len()?;
+    let limit = match metadata.get_max_frame_size().filter(|&size| size != 0) {
+        Some(size) => len.min(start + size as u64),
+        None => len,
+    };
+
+    // The bytes and their CRC-16 are carried over from one candidate end to the next
+    let mut bytes = Vec::new();
+    let mut crc = 0;
+    let mut first_header = None;
+    let mut from = start + 1;
+    while let Some(next) = Decoder::find_next_frame(stream, metadata, from)? {
+        if next > limit {
+            break;
+        }
+        stream.seek_to(start + bytes.len() as u64)?;
+        let body = bytes.len().saturating_sub(2);
+        bytes.extend(stream.read_bytes((next - start) as usize - bytes.len())?);
+        if bytes.len() >= 2 {
+            let end = bytes.len() - 2;
+            crc = bytes[body..end]
+                .iter()
+                .fold(crc, |crc, &byte| crc16_update(crc, byte));
+            if crc == u16::from_be_bytes([bytes[end], bytes[end + 1]]) {
+                return Ok(bytes);
+            }
+        }
+        first_header.get_or_insert(next);
+        from = next + 1;
+    }
+
+    let end = first_header.unwrap_or(limit);
+    stream.seek_to(start)?;
+    stream.read_bytes((end - start) as usize)
+}
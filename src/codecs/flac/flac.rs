@@ -1,8 +1,17 @@
 // src/codecs/flac/flac.rs
+use super::analysis::Analysis;
+use super::buffer::FrameBuffer;
 use super::data::Metadata;
-use super::decoder::Decoder;
+use super::decoder::{ArithmeticMode, CrcPolicy, Decoder, Md5Status, RecoveryMode};
+use super::frame_decoder::FrameDecoder;
+use super::index::{FrameIndex, FrameIndexMode};
+use crate::codecs::registry::{Codec, FormatReader};
+use crate::common::block::DecodedBlock;
 use crate::common::errors::AudioError;
+use crate::common::pcm::PCM;
+use crate::common::position::Position;
 use crate::common::stream::Stream;
+use std::{io::Read, ops::Range};
 
 // Define FLAC structure holding metadata and decoder
 pub struct FLAC {
@@ -27,11 +36,227 @@ impl FLAC {
         &self.metadata
     }
 
+    // Method to choose how frames with a CRC mismatch are handled
+    pub fn set_crc_policy(&mut self, crc_policy: CrcPolicy) {
+        self.decoder.set_crc_policy(crc_policy);
+    }
+
+    // Method to choose how damaged or missing frames are handled
+    pub fn set_recovery_mode(&mut self, recovery_mode: RecoveryMode) {
+        self.decoder.set_recovery_mode(recovery_mode);
+    }
+
+    // Method to choose between fast predictions and checked arithmetic
+    pub fn set_arithmetic_mode(&mut self, arithmetic_mode: ArithmeticMode) {
+        self.decoder.set_arithmetic_mode(arithmetic_mode);
+    }
+
+    // Method to choose how many threads decode frames in parallel, when decoding the whole stream
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.decoder.set_num_threads(num_threads);
+    }
+
+    // Method to choose whether seeks use a frame index, kept in memory or also in a sidecar file
+    pub fn set_frame_index_mode(&mut self, frame_index_mode: FrameIndexMode) {
+        self.decoder.set_frame_index_mode(frame_index_mode);
+    }
+
+    // Method to get the index of every frame in the stream, building it on first access
+    pub fn get_frame_index(&mut self, stream: &mut Stream) -> Result<&FrameIndex, AudioError> {
+        self.decoder.get_frame_index(stream, &self.metadata)
+    }
+
+    // Method to get the sample ranges lost to damaged data during the last decode
+    pub fn get_lost_ranges(&self) -> &[Range<u64>] {
+        self.decoder.get_lost_ranges()
+    }
+
+    /**
+     * Method to seek to a sample, so that decoding the stream next yields
+     * that sample first
+     * Uses the frame index when enabled, otherwise the SEEKTABLE when
+     * present, otherwise searches the frames
+     */
+    pub fn seek_to_sample(&mut self, stream: &mut Stream, sample: u64) -> Result<(), AudioError> {
+        self.decoder.seek_to_sample(stream, &self.metadata, sample)
+    }
+
+    /**
+     * Method to decode the FLAC stream incrementally
+     * Returns an iterator yielding one block of decoded samples per frame
+     */
+    pub fn frames<'a>(&'a mut self, stream: &'a mut Stream) -> FrameDecoder<'a> {
+        self.decoder.frames(stream, &self.metadata)
+    }
+
+    /**
+     * Method to decode the samples from start up to but not including end,
+     * one block at a time
+     * Positions are sample indices or Durations from the start of the stream
+     */
+    pub fn frames_range<'a>(
+        &'a mut self,
+        stream: &'a mut Stream,
+        start: impl Into<Position>,
+        end: impl Into<Position>,
+    ) -> Result<FrameDecoder<'a>, AudioError> {
+        self.decoder
+            .frames_range(stream, &self.metadata, start, end)
+    }
+
+    /**
+     * Method to decode the samples from start up to but not including end
+     * Returns the samples of each block stored channel after channel
+     */
+    pub fn decode_range(
+        &mut self,
+        stream: &mut Stream,
+        start: impl Into<Position>,
+        end: impl Into<Position>,
+    ) -> Result<Vec<i32>, AudioError> {
+        self.decoder
+            .decode_range(stream, &self.metadata, start, end)
+    }
+
+    /**
+     * Method to decode the next frame into caller-owned buffers, as created
+     * by FrameBuffer::from_metadata, without allocating per frame
+     * Returns false once the stream has no more frames
+     */
+    pub fn decode_into(
+        &mut self,
+        stream: &mut Stream,
+        buffer: &mut FrameBuffer,
+    ) -> Result<bool, AudioError> {
+        self.decoder.decode_into(stream, &self.metadata, buffer)
+    }
+
     /**
      *  Method to decode the FLAC stream
      *  Returns a Result that may contain a Vec<u8> or an AudioError
      */
-    pub fn decode(&mut self, stream: &mut Stream) -> Result<Vec<u32>, AudioError> {
-        self.decoder.decode(stream)
+    pub fn decode(&mut self, stream: &mut Stream) -> Result<Vec<i32>, AudioError> {
+        self.decoder.decode(stream, &self.metadata)
+    }
+
+    /**
+     * Method to decode the whole FLAC stream and check the decoded audio
+     * against the MD5 signature in STREAMINFO
+     * Returns a Result that may contain an Md5Status or an AudioError
+     */
+    pub fn verify(&mut self, stream: &mut Stream) -> Result<Md5Status, AudioError> {
+        self.decoder.decode(stream, &self.metadata)?;
+        self.get_md5_status().ok_or(AudioError::InvalidData(
+            "MD5 check did not complete".to_string(),
+        ))
+    }
+
+    // Method to get the MD5 check result of the last completed decode
+    pub fn get_md5_status(&self) -> Option<Md5Status> {
+        self.decoder.get_md5_status()
+    }
+
+    // Method to report how every frame and subframe of the stream was coded, under the decoder settings
+    pub fn analyze(&self, stream: &mut Stream) -> Result<Analysis, AudioError> {
+        Analysis::analyze(stream, &self.metadata, &self.decoder)
+    }
+}
+
+impl Codec for FLAC {
+    fn describe(&self) -> String {
+        format!(
+            "{:?}\n{:?}",
+            self.metadata.get_short_format(),
+            self.metadata.get_long_format()
+        )
+    }
+
+    fn decode(&mut self, stream: &mut Stream) -> Result<PCM, AudioError> {
+        let metadata = self.metadata.clone();
+        Self::collect_pcm(&metadata, self.frames(stream))
+    }
+
+    fn decode_range(
+        &mut self,
+        stream: &mut Stream,
+        start: Position,
+        end: Position,
+    ) -> Result<PCM, AudioError> {
+        let metadata = self.metadata.clone();
+        Self::collect_pcm(&metadata, self.frames_range(stream, start, end)?)
+    }
+}
+
+impl FLAC {
+    // Gather decoded blocks into a PCM buffer in the format STREAMINFO and the channel mask tag give
+    fn collect_pcm(
+        metadata: &Metadata,
+        blocks: impl Iterator<Item = Result<DecodedBlock, AudioError>>,
+    ) -> Result<PCM, AudioError> {
+        let num_channels = metadata.get_num_channels().unwrap_or(0) as usize;
+        let mut channels = vec![Vec::new(); num_channels];
+        for block in blocks {
+            for (channel, samples) in channels.iter_mut().zip(block?.get_channels()) {
+                channel.extend_from_slice(samples);
+            }
+        }
+        let mut pcm = PCM::from_integer_channels(
+            channels,
+            metadata.get_bit_depth().unwrap_or(0),
+            metadata.get_sample_rate().unwrap_or(0),
+        )?;
+        if let Some(layout) = metadata.get_channel_layout() {
+            pcm.set_layout(layout)?;
+        }
+        Ok(pcm)
+    }
+}
+
+// Format reader recognizing native FLAC streams
+pub struct FlacReader;
+
+impl FormatReader for FlacReader {
+    fn get_name(&self) -> &'static str {
+        "FLAC"
+    }
+
+    fn get_extensions(&self) -> &'static [&'static str] {
+        &["flac", "fla"]
+    }
+
+    /**
+     * The stream marker alone is fairly convincing, and a STREAMINFO block
+     * right after it with plausible block sizes and sample rate settles it
+     */
+    fn probe(&self, header: &[u8]) -> u8 {
+        if !header.starts_with(b"fLaC") {
+            return 0;
+        }
+        let mut score = 60;
+
+        // STREAMINFO must be the first metadata block, and is always 34 bytes long
+        if header.len() < 8 || header[4] & 0x7F != 0 || header[5..8] != [0, 0, 34] {
+            return score;
+        }
+        score += 30;
+
+        if header.len() >= 42 {
+            let min_block_size = u16::from_be_bytes([header[8], header[9]]);
+            let max_block_size = u16::from_be_bytes([header[10], header[11]]);
+            let sample_rate = u32::from_be_bytes([0, header[18], header[19], header[20]]) >> 4;
+            if min_block_size >= 16 && max_block_size >= min_block_size && sample_rate != 0 {
+                score += 10;
+            }
+        }
+        score
+    }
+
+    fn open(&self, stream: &mut Stream) -> Result<Box<dyn Codec>, AudioError> {
+        let mut marker = [0; 4];
+        stream.reader().read_exact(&mut marker)?;
+        if &marker != b"fLaC" {
+            return Err(AudioError::UnsupportedFileCodec);
+        }
+        Ok(Box::new(FLAC::new(stream)?))
     }
 }
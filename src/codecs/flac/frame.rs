@@ -2,26 +2,54 @@
 
 // This module handles reading and parsing frames of data from the FLAC stream
 
-use super::subframe::Subframe;
+use super::{
+    buffer::FrameBuffer,
+    data::Metadata,
+    decoder::{ArithmeticMode, CrcPolicy},
+    subframe::{Subframe, SubframeDetails},
+};
 use crate::common::{errors::AudioError, stream::Stream};
+use std::time::Duration;
 
-// Struct to represent a frame of data in a FLAC stream
-#[derive(Debug)]
-pub struct Frame {
+// Struct to represent the header of a frame in a FLAC stream
+#[derive(Debug, Clone)]
+pub struct FrameHeader {
     sync_code: u16,                     // Synchronization code
     block_strategy: bool,               // Block strategy
     block_size: u32,                    // Block size (number of samples per channel)
     sample_rate: u32,                   // Sample rate in Hz
     channel_assignment: u8,             // Channel assignment
     bit_depth: u8,                      // Bit depth
-    sample_number_or_frame_number: u64, // Sample number of first sample in frame
-    crc: u16,                           // Frame CRC-16
-    subframes: Vec<Subframe>,           // Subframes contained in the frame
+    sample_number_or_frame_number: u64, // Sample number (variable) or frame number (fixed)
+    first_sample: u64,                  // Index of the first sample in the frame
+    crc: u8,                            // Frame header CRC-8
 }
 
-impl Frame {
-    // Method to read and parse a frame from the stream
-    pub fn read_next_frame(stream: &mut Stream) -> Result<Self, AudioError> {
+// Struct to represent a frame of data in a FLAC stream
+#[derive(Debug)]
+pub struct Frame {
+    header: FrameHeader,      // Frame header
+    crc: u16,                 // Frame CRC-16
+    subframes: Vec<Subframe>, // Subframes contained in the frame
+    channels: Vec<Vec<i32>>,  // Decoded samples of each channel
+}
+
+impl FrameHeader {
+    /**
+     * Method to read and parse a frame header from the stream, up to and
+     * including its CRC-8
+     * The running CRCs are reset at the start of the header, so the caller
+     * can go on to check the CRC-16 of the whole frame
+     */
+    pub fn read_next_header(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+    ) -> Result<Self, AudioError> {
+        // Both CRCs cover the frame from its first byte
+        stream.align_to_byte();
+        stream.reset_crc();
+
         // Implements frame parsing
         let sync_code = stream.read_bits(14)? as u16;
         if sync_code != 0x3FFE {
@@ -30,14 +58,74 @@ impl Frame {
             ));
         }
 
-        // Read and discard 1 reserved bit
-        stream.read_bit();
+        // The reserved bit following the sync code must be zero
+        if stream.read_bit()? != 0 {
+            return Err(AudioError::InvalidData(
+                "Invalid reserved bit in FLAC frame header".to_string(),
+            ));
+        }
 
-        // Read and discard blocking strategy bit
+        // Read blocking strategy bit
         let block_strategy = stream.read_bit()? != 0;
 
-        // TODO: process block_size and sample_rate
+        /*
+         * Block size and sample rate codes may refer to values stored at the
+         * end of the header, after the coded number, so only read the codes here
+         */
         let block_size_code = stream.read_bits(4)?;
+        let sample_rate_code = stream.read_bits(4)?;
+
+        // Read channel_assignment and map to a specific channel layout
+        let channel_assignment = stream.read_bits(4)? as u8;
+        if channel_assignment > 10 {
+            return Err(AudioError::InvalidData(
+                "Reserved channel assignment value".to_string(),
+            ));
+        }
+
+        // Read bit_depth and map it to a specific sample size
+        let bit_depth_code = stream.read_bits(3)? as u8;
+        let bit_depth = match bit_depth_code {
+            0 => metadata.get_bit_depth().ok_or(AudioError::InvalidData(
+                "Bit depth must be retrieved from missing STREAMINFO metadata block".to_string(),
+            ))?,
+            1 => 8,
+            2 => 12,
+            3 => {
+                return Err(AudioError::InvalidData(
+                    "Reserved bit depth value".to_string(),
+                ))
+            }
+            4 => 16,
+            5 => 20,
+            6 => 24,
+            7 => 32,
+            _ => {
+                return Err(AudioError::InvalidData(
+                    "Unexpected bit depth code".to_string(),
+                ))
+            }
+        };
+
+        // The reserved bit following the bit depth must be zero
+        if stream.read_bit()? != 0 {
+            return Err(AudioError::InvalidData(
+                "Invalid reserved bit in FLAC frame header".to_string(),
+            ));
+        }
+
+        /*
+         * The frame number (fixed block size) or sample number (variable block
+         * size) is coded like UTF-8, extended to allow up to 36-bit values
+         */
+        let sample_number_or_frame_number = Self::read_coded_number(stream)?;
+        let max_coded_number = if block_strategy { 1 << 36 } else { 1 << 31 };
+        if sample_number_or_frame_number >= max_coded_number {
+            return Err(AudioError::InvalidData(
+                "Frame or sample number out of range".to_string(),
+            ));
+        }
+
         let block_size = match block_size_code {
             0 => {
                 return Err(AudioError::InvalidData(
@@ -46,18 +134,42 @@ impl Frame {
             }
             1 => 192,
             2..=5 => 576 * (1 << (block_size_code - 2)),
-            6 => (stream.read_bits(8)? + 1) as u32,
-            7 => (stream.read_bits(16)? + 1) as u32,
+            6 => stream.read_bits(8)? + 1,
+            7 => match stream.read_bits(16)? {
+                // The field holds one less than the block size, which cannot exceed 65535
+                0xFFFF => {
+                    return Err(AudioError::InvalidData(
+                        "Block size of 65536 is not allowed".to_string(),
+                    ))
+                }
+                size => size + 1,
+            },
             _ => 256 * (1 << (block_size_code - 8)),
         };
 
-        let sample_rate_code = stream.read_bits(4)?;
+        /*
+         * Variable block size frames carry the number of their first sample,
+         * while fixed block size frames carry their frame number, and every
+         * frame but the last holds the stream's nominal block size
+         * The frame's own block size cannot stand in for the nominal one, as
+         * the last frame may be shorter
+         */
+        let first_sample = if block_strategy || sample_number_or_frame_number == 0 {
+            sample_number_or_frame_number
+        } else {
+            let nominal_block_size = metadata.get_max_block_size().ok_or_else(|| {
+                AudioError::InvalidData(
+                    "Nominal block size must be retrieved from missing STREAMINFO metadata block"
+                        .to_string(),
+                )
+            })?;
+            sample_number_or_frame_number * nominal_block_size as u64
+        };
+
         let sample_rate = match sample_rate_code {
-            0 => {
-                return Err(AudioError::InvalidData(
-                    "Sample rate must be retrieved from STREAMINFO metadata block".to_string(),
-                ))
-            }
+            0 => metadata.get_sample_rate().ok_or(AudioError::InvalidData(
+                "Sample rate must be retrieved from missing STREAMINFO metadata block".to_string(),
+            ))?,
             1 => 88200,
             2 => 176400,
             3 => 192000,
@@ -84,79 +196,417 @@ impl Frame {
             }
         };
 
-        // Read channel_assignment and map to a specific channel layout
-        let channel_assignment = stream.read_bits(4)? as u8;
+        // Read the header CRC, which covers every header byte before it
+        let computed_header_crc = stream.get_crc8();
+        let header_crc = stream.read_bits(8)? as u8;
+        if header_crc != computed_header_crc {
+            crc_policy.check(format!(
+                "frame header CRC-8 is {:#04x}, expected {:#04x}",
+                computed_header_crc, header_crc
+            ))?;
+        }
 
-        // Read bit_depth and map it to a specific sample size
-        let bit_depth_code = stream.read_bits(3)? as u8;
-        let bit_depth = match bit_depth_code {
-            0 => {
-                return Err(AudioError::InvalidData(
-                    "Bit depth must be retrieved from STREAMINFO metadata block".to_string(),
-                ))
-            }
-            1 => 8,
-            2 => 12,
-            3 => {
-                return Err(AudioError::InvalidData(
-                    "Reserved bit depth value".to_string(),
-                ))
-            }
-            4 => 16,
-            5 => 20,
-            6 => 24,
-            7 => 32,
+        Ok(FrameHeader {
+            sync_code,
+            block_strategy,
+            block_size,
+            sample_rate,
+            channel_assignment,
+            bit_depth,
+            sample_number_or_frame_number,
+            first_sample,
+            crc: header_crc,
+        })
+    }
+
+    /**
+     * Read a UTF-8 style coded number from the stream
+     * The number of leading one bits in the first byte gives the total number
+     * of bytes (a leading zero bit means a single byte), and every following
+     * byte carries 6 bits of the value behind a 10 prefix
+     */
+    fn read_coded_number(stream: &mut Stream) -> Result<u64, AudioError> {
+        let first_byte = stream.read_bits(8)? as u8;
+        let num_bytes = match first_byte.leading_ones() {
+            0 => 1,
+            len @ 2..=7 => len as usize,
             _ => {
                 return Err(AudioError::InvalidData(
-                    "Unexpected bit depth code".to_string(),
+                    "Invalid coded number in FLAC frame header".to_string(),
                 ))
             }
-        } as u8;
-
-        // Read and discard 1 reserved bit
-        stream.read_bits(1)?;
+        };
 
-        let sample_number_or_frame_number = if block_strategy {
-            stream.read_bits(36)? as u64 // Read the sample number
+        // The first byte holds whatever bits remain after the length prefix
+        let mut value = if num_bytes == 1 {
+            first_byte as u64
         } else {
-            stream.read_bits(31)? as u64 // Read the frame number
+            (first_byte & (0x7F >> num_bytes)) as u64
         };
 
-        // Read the CRC
-        let crc = stream.read_bits(8)? as u16;
-
-        // println!("sync_code: {:?}", sync_code);
-        // println!("block_size: {:?}", block_size);
-        // println!("sample_rate: {}", sample_rate);
-        // println!("channel_assignment: {}", channel_assignment);
-        // println!("bit_depth: {}", bit_depth);
-        // println!(
-        //     "sample_number_or_frame_number: {}",
-        //     sample_number_or_frame_number
-        // );
-        // println!("crc: {}", crc);
-
-        // Read subframes
-        let mut subframes = Vec::new();
-        for _ in 0..channel_assignment {
-            let subframe = Subframe::read_next_subframe(stream, bit_depth, block_size as usize)?;
-            subframes.push(subframe);
+        for _ in 1..num_bytes {
+            let byte = stream.read_bits(8)? as u8;
+            if byte & 0xC0 != 0x80 {
+                return Err(AudioError::InvalidData(
+                    "Invalid continuation byte in coded number".to_string(),
+                ));
+            }
+            value = (value << 6) | (byte & 0x3F) as u64;
+        }
+
+        Ok(value)
+    }
+
+    // Map the channel assignment to the number of subframes in the frame
+    fn channel_count(channel_assignment: u8) -> usize {
+        match channel_assignment {
+            // Independent channels
+            0..=7 => channel_assignment as usize + 1,
+            // Left/side, side/right and mid/side stereo
+            _ => 2,
         }
+    }
+
+    pub fn get_sync_code(&self) -> u16 {
+        self.sync_code
+    }
+
+    pub fn get_block_strategy(&self) -> bool {
+        self.block_strategy
+    }
+
+    pub fn get_block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_channel_assignment(&self) -> u8 {
+        self.channel_assignment
+    }
+
+    pub fn get_bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn get_sample_number_or_frame_number(&self) -> u64 {
+        self.sample_number_or_frame_number
+    }
+
+    pub fn get_first_sample(&self) -> u64 {
+        self.first_sample
+    }
+
+    // Method to get the time of the first sample in the frame from the start of the stream
+    pub fn get_timestamp(&self) -> Duration {
+        let sample_rate = self.sample_rate as u64;
+        if sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        let seconds = self.first_sample / sample_rate;
+        let nanos = (self.first_sample % sample_rate) * 1_000_000_000 / sample_rate;
+        Duration::new(seconds, nanos as u32)
+    }
+
+    pub fn get_crc(&self) -> u8 {
+        self.crc
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        Self::channel_count(self.channel_assignment)
+    }
+}
+
+impl Frame {
+    // Method to read and parse a frame from the stream
+    pub fn read_next_frame(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+    ) -> Result<Self, AudioError> {
+        Self::read_frame(stream, metadata, crc_policy, arithmetic_mode, None)
+    }
+
+    /**
+     * Method to read and parse a frame from the stream, along with how each of
+     * its subframes was coded, for analysis
+     */
+    pub fn read_next_frame_with_details(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+    ) -> Result<(Self, Vec<SubframeDetails>), AudioError> {
+        let mut details = Vec::new();
+        let frame = Self::read_frame(
+            stream,
+            metadata,
+            crc_policy,
+            arithmetic_mode,
+            Some(&mut details),
+        )?;
+        Ok((frame, details))
+    }
+
+    fn read_frame(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+        details: Option<&mut Vec<SubframeDetails>>,
+    ) -> Result<Self, AudioError> {
+        let header = FrameHeader::read_next_header(stream, metadata, crc_policy)?;
+
+        // Buffers sized for this frame alone, whose samples the frame then keeps
+        let mut buffer =
+            FrameBuffer::new(header.get_num_channels(), header.get_block_size() as usize);
+        let mut subframes = Vec::with_capacity(header.get_num_channels());
+        let crc = Self::read_body(
+            stream,
+            &header,
+            crc_policy,
+            arithmetic_mode,
+            &mut buffer,
+            details,
+            |subframe| subframes.push(subframe),
+        )?;
+
+        let subframes = subframes
+            .into_iter()
+            .zip(buffer.subframes)
+            .map(|(subframe, data)| subframe.with_data(data))
+            .collect();
 
         Ok(Frame {
-            sync_code,
-            block_strategy,
-            block_size,
-            sample_rate,
-            channel_assignment,
-            bit_depth,
-            sample_number_or_frame_number,
+            header,
             crc,
             subframes,
+            channels: buffer.channels,
         })
     }
 
+    /**
+     * Method to read the next frame from the stream into caller-owned buffers
+     * Nothing is allocated unless the frame is larger than the buffers
+     * Returns the frame header, with the decoded samples left in the buffers
+     */
+    pub fn read_into(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+        buffer: &mut FrameBuffer,
+    ) -> Result<FrameHeader, AudioError> {
+        let header = FrameHeader::read_next_header(stream, metadata, crc_policy)?;
+        Self::read_body(
+            stream,
+            &header,
+            crc_policy,
+            arithmetic_mode,
+            buffer,
+            None,
+            |_| {},
+        )?;
+        Ok(header)
+    }
+
+    /**
+     * Read the subframes that follow a frame header into the buffers, passing
+     * each subframe to a callback, then check the frame CRC
+     * Details of each subframe are collected only if a vector is given for them
+     * Returns the frame CRC-16
+     */
+    fn read_body(
+        stream: &mut Stream,
+        header: &FrameHeader,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+        buffer: &mut FrameBuffer,
+        mut details: Option<&mut Vec<SubframeDetails>>,
+        mut on_subframe: impl FnMut(Subframe),
+    ) -> Result<u16, AudioError> {
+        let bit_depth = header.get_bit_depth();
+        let block_size = header.get_block_size() as usize;
+        let channel_assignment = header.get_channel_assignment();
+        let num_channels = header.get_num_channels();
+        buffer.reserve(num_channels, block_size);
+        if let Some(details) = details.as_mut() {
+            details.clear();
+            details.resize_with(num_channels, SubframeDetails::default);
+        }
+
+        // Read subframes, one per channel
+        for channel in 0..num_channels {
+            // The side channel carries one extra bit
+            let subframe_bit_depth = match (channel_assignment, channel) {
+                (8, 1) | (9, 0) | (10, 1) => bit_depth + 1,
+                _ => bit_depth,
+            };
+            let subframe = Subframe::read_into(
+                stream,
+                subframe_bit_depth,
+                &mut buffer.subframes[channel][..block_size],
+                &mut buffer.scratch,
+                arithmetic_mode,
+                details.as_mut().map(|details| &mut details[channel]),
+            )?;
+            on_subframe(subframe);
+        }
+
+        Self::decorrelate(
+            channel_assignment,
+            &buffer.subframes[..num_channels],
+            &mut buffer.channels[..num_channels],
+            block_size,
+        );
+
+        // The subframes are padded with zero bits to a byte boundary
+        stream.align_to_byte();
+
+        // Read the frame CRC, which covers every frame byte before it
+        let computed_crc = stream.get_crc16();
+        let crc = stream.read_bits(16)? as u16;
+        if crc != computed_crc {
+            crc_policy.check(format!(
+                "frame CRC-16 is {:#06x}, expected {:#06x}",
+                computed_crc, crc
+            ))?;
+        }
+
+        buffer.num_channels = num_channels;
+        buffer.block_size = block_size;
+        buffer.start = 0;
+        buffer.first_sample = header.get_first_sample();
+        buffer.sample_rate = header.get_sample_rate();
+        buffer.bit_depth = bit_depth;
+
+        Ok(crc)
+    }
+
+    /**
+     * Undo the inter-channel decorrelation of stereo frames, writing the
+     * first block_size samples of each channel
+     * The side channel of a 32-bit stream needs 33 bits, so the arithmetic is
+     * done in 64 bits, but the restored left and right channels fit in 32 bits
+     */
+    fn decorrelate(
+        channel_assignment: u8,
+        subframes: &[Vec<i64>],
+        channels: &mut [Vec<i32>],
+        block_size: usize,
+    ) {
+        match channel_assignment {
+            // Left/side: right = left - side
+            8 => Self::decorrelate_stereo(subframes, channels, block_size, |l, s| (l, l - s)),
+            // Side/right: left = side + right
+            9 => Self::decorrelate_stereo(subframes, channels, block_size, |s, r| (s + r, r)),
+            /*
+             * Mid/side: the lowest bit of the mid channel was dropped when it
+             * was halved, and equals the lowest bit of the side channel
+             */
+            10 => Self::decorrelate_stereo(subframes, channels, block_size, |m, s| {
+                let m = (m << 1) | (s & 1);
+                ((m + s) >> 1, (m - s) >> 1)
+            }),
+            // Independent channels
+            _ => {
+                for (output, subframe) in channels.iter_mut().zip(subframes) {
+                    for (out, &x) in output[..block_size].iter_mut().zip(&subframe[..block_size]) {
+                        *out = x as i32;
+                    }
+                }
+            }
+        }
+    }
+
+    // Restore the left and right channels from a stereo pair of subframes
+    fn decorrelate_stereo(
+        subframes: &[Vec<i64>],
+        channels: &mut [Vec<i32>],
+        block_size: usize,
+        restore: impl Fn(i64, i64) -> (i64, i64),
+    ) {
+        let (left, right) = channels.split_at_mut(1);
+        let outputs = left[0][..block_size]
+            .iter_mut()
+            .zip(&mut right[0][..block_size]);
+        let inputs = subframes[0][..block_size]
+            .iter()
+            .zip(&subframes[1][..block_size]);
+
+        for ((left, right), (&first, &second)) in outputs.zip(inputs) {
+            let (l, r) = restore(first, second);
+            *left = l as i32;
+            *right = r as i32;
+        }
+    }
+
+    pub fn get_header(&self) -> &FrameHeader {
+        &self.header
+    }
+
+    pub fn get_sync_code(&self) -> u16 {
+        self.header.get_sync_code()
+    }
+
+    pub fn get_block_strategy(&self) -> bool {
+        self.header.get_block_strategy()
+    }
+
+    pub fn get_block_size(&self) -> u32 {
+        self.header.get_block_size()
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.header.get_sample_rate()
+    }
+
+    pub fn get_channel_assignment(&self) -> u8 {
+        self.header.get_channel_assignment()
+    }
+
+    pub fn get_bit_depth(&self) -> u8 {
+        self.header.get_bit_depth()
+    }
+
+    pub fn get_sample_number_or_frame_number(&self) -> u64 {
+        self.header.get_sample_number_or_frame_number()
+    }
+
+    pub fn get_first_sample(&self) -> u64 {
+        self.header.get_first_sample()
+    }
+
+    pub fn get_timestamp(&self) -> Duration {
+        self.header.get_timestamp()
+    }
+
+    pub fn get_header_crc(&self) -> u8 {
+        self.header.get_crc()
+    }
+
+    pub fn get_crc(&self) -> u16 {
+        self.crc
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        self.header.get_num_channels()
+    }
+
     pub fn get_subframes(&self) -> &Vec<Subframe> {
         &self.subframes
     }
+
+    // Method to access the decoded samples of every channel
+    pub fn get_channels(&self) -> &[Vec<i32>] {
+        &self.channels
+    }
+
+    // Method to take ownership of the decoded samples of every channel
+    pub fn into_channels(self) -> Vec<Vec<i32>> {
+        self.channels
+    }
 }
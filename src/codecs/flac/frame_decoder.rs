@@ -0,0 +1,303 @@
+// src/codecs/flac/frame_decoder.rs
+
+// This module decodes a FLAC stream incrementally, one block of samples at a time
+
+use super::{
+    data::Metadata,
+    decoder::{Decoder, Md5Status, RecoveryMode, ResumePoint, MAX_SILENCE_BLOCK_SIZE},
+    frame::Frame,
+    parallel::ParallelFrames,
+};
+use crate::common::{block::DecodedBlock, errors::AudioError, md5::Md5, stream::Stream};
+use std::ops::Range;
+
+// A block holds every sample of the frame it was decoded from
+impl From<Frame> for DecodedBlock {
+    fn from(frame: Frame) -> Self {
+        Self::new(
+            frame.get_sample_rate(),
+            frame.get_bit_depth(),
+            frame.get_first_sample(),
+            frame.into_channels(),
+        )
+    }
+}
+
+/**
+ * Iterator decoding a FLAC stream frame by frame
+ * Yields one DecodedBlock per frame, plus blocks of silence for samples lost
+ * to damaged data when the decoder fills them. Once the whole stream has been
+ * decoded, the MD5 check result is available from the decoder
+ */
+pub struct FrameDecoder<'a> {
+    decoder: &'a mut Decoder,
+    stream: &'a mut Stream,
+    metadata: &'a Metadata,
+    block_strategy: Option<bool>, // Blocking strategy of the first frame
+    next_sample: u64,             // First sample expected in the next frame
+    start_sample: u64,            // Samples before this one are dropped, after a seek
+    end_sample: u64,              // Decoding stops before this sample
+    pending_frame: Option<Frame>, // Frame read after a gap, yielded once the gap is filled
+    prefetched: Option<ParallelFrames>, // Frames decoded ahead of time, read instead of the stream
+    silence: Range<u64>,          // Lost samples still to be filled with silence
+    md5: Md5,                     // MD5 of the samples yielded so far
+    check_md5: bool,              // Only a decode of the whole stream can check the MD5
+    at_end: bool,                 // Set once the stream has no more frames
+    finished: bool,               // Set once nothing more will be yielded
+}
+
+impl<'a> FrameDecoder<'a> {
+    /**
+     * Constructor method to start decoding a stream positioned at its first frame
+     * The decoder supplies the CRC and recovery settings, and collects the lost
+     * sample ranges and MD5 check result of this pass
+     * After a seek, decoding starts from the sample the decoder was seeked to
+     */
+    pub fn new(decoder: &'a mut Decoder, stream: &'a mut Stream, metadata: &'a Metadata) -> Self {
+        let resume_point = decoder.start_pass();
+        let (next_sample, start_sample) = match resume_point {
+            Some(ResumePoint { frame, sample }) => (frame.get_first_sample(), sample),
+            None => (0, 0),
+        };
+
+        Self {
+            decoder,
+            stream,
+            metadata,
+            block_strategy: None,
+            next_sample,
+            start_sample,
+            end_sample: u64::MAX,
+            pending_frame: None,
+            prefetched: None,
+            silence: 0..0,
+            md5: Md5::new(),
+            check_md5: resume_point.is_none(),
+            at_end: false,
+            finished: false,
+        }
+    }
+
+    /**
+     * Constructor method to go over frames that were already decoded in
+     * parallel, rather than reading them from the stream
+     */
+    pub fn with_frames(
+        decoder: &'a mut Decoder,
+        stream: &'a mut Stream,
+        metadata: &'a Metadata,
+        frames: ParallelFrames,
+    ) -> Self {
+        Self {
+            prefetched: Some(frames),
+            ..Self::new(decoder, stream, metadata)
+        }
+    }
+
+    /**
+     * Method to stop decoding right before a sample, cutting the block that
+     * holds it short
+     * The MD5 signature is only checked if decoding still reaches the end of
+     * the stream
+     */
+    pub fn until(mut self, end_sample: u64) -> Self {
+        self.end_sample = end_sample;
+        let reaches_end = end_sample == u64::MAX
+            || self
+                .metadata
+                .get_total_samples()
+                .is_some_and(|total_samples| total_samples != 0 && end_sample >= total_samples);
+        self.check_md5 &= reaches_end;
+        self
+    }
+
+    /**
+     * Read the next frame, skipping damaged data when recovery is enabled
+     * Returns None at the end of the stream
+     */
+    fn read_frame(&mut self) -> Result<Option<Frame>, AudioError> {
+        let recovery_mode = self.decoder.get_recovery_mode();
+        let block_strategy = &mut self.block_strategy;
+        if let Some(frames) = &mut self.prefetched {
+            return frames.next_frame(self.stream, self.metadata, recovery_mode, |frame| {
+                check_block_strategy(block_strategy, frame)
+            });
+        }
+
+        let (crc_policy, arithmetic_mode) = (
+            self.decoder.get_crc_policy(),
+            self.decoder.get_arithmetic_mode(),
+        );
+        self.decoder
+            .read_frame(self.stream, self.metadata, |stream| {
+                let frame =
+                    Frame::read_next_frame(stream, self.metadata, crc_policy, arithmetic_mode)?;
+                check_block_strategy(block_strategy, &frame)?;
+                Ok(frame)
+            })
+    }
+
+    /**
+     * Record a range of samples lost to damaged data, queueing it to be filled
+     * with silence when the recovery mode asks for it
+     */
+    fn lose_samples(&mut self, range: Range<u64>) {
+        let range = range.start..range.end.min(self.end_sample);
+        self.silence = self.decoder.lose_samples(range, self.start_sample);
+    }
+
+    // Take the next block of queued silence, if any
+    fn next_silence(&mut self) -> Option<DecodedBlock> {
+        if self.silence.is_empty() {
+            return None;
+        }
+
+        let block_size = (self.silence.end - self.silence.start).min(MAX_SILENCE_BLOCK_SIZE);
+        let first_sample = self.silence.start;
+        self.silence.start += block_size;
+
+        let num_channels = self.metadata.get_num_channels().unwrap_or(1) as usize;
+        let bit_depth = self.metadata.get_bit_depth().unwrap_or(8);
+        let bytes_per_sample = (bit_depth as usize).div_ceil(8);
+        self.md5.update(&vec![
+            0;
+            block_size as usize * num_channels * bytes_per_sample
+        ]);
+
+        Some(DecodedBlock::new(
+            self.metadata.get_sample_rate().unwrap_or(0),
+            bit_depth,
+            first_sample,
+            vec![vec![0; block_size as usize]; num_channels],
+        ))
+    }
+
+    /**
+     * Turn a decoded frame into a block, feeding it into the running MD5
+     * Samples before the start sample of a seek are dropped from the block
+     */
+    fn yield_frame(&mut self, frame: Frame) -> DecodedBlock {
+        if self.check_md5 {
+            self.update_md5(&frame);
+        }
+        self.next_sample = frame.get_first_sample() + frame.get_block_size() as u64;
+
+        let block_size = frame.get_block_size() as u64;
+        let skipped = self
+            .start_sample
+            .saturating_sub(frame.get_first_sample())
+            .min(block_size);
+        let kept = self
+            .end_sample
+            .saturating_sub(frame.get_first_sample())
+            .min(block_size);
+        let mut block = DecodedBlock::from(frame);
+        if skipped > 0 || kept < block_size {
+            block.trim(skipped.min(kept) as usize..kept as usize);
+        }
+        block
+    }
+
+    /**
+     * Feed the samples of a frame into the running MD5
+     * The signature covers the samples interleaved channel by channel, each
+     * stored little-endian in the fewest whole bytes that hold the bit depth
+     */
+    fn update_md5(&mut self, frame: &Frame) {
+        let bytes_per_sample = (frame.get_bit_depth() as usize).div_ceil(8);
+        let channels = frame.get_channels();
+        let mut bytes = Vec::with_capacity(frame.get_block_size() as usize * channels.len() * 4);
+
+        for i in 0..frame.get_block_size() as usize {
+            for channel in channels {
+                bytes.extend_from_slice(&channel[i].to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+
+        self.md5.update(&bytes);
+    }
+
+    // Compare the MD5 of everything yielded with the signature in STREAMINFO
+    fn finish(&mut self) {
+        self.finished = true;
+        if !self.check_md5 {
+            return;
+        }
+
+        let md5 = std::mem::take(&mut self.md5).finalize();
+        let md5_status = match self.metadata.get_md5_signature() {
+            Some(signature) if signature.iter().any(|&byte| byte != 0) => {
+                if signature[..] == md5 {
+                    Md5Status::Match
+                } else {
+                    Md5Status::Mismatch
+                }
+            }
+            _ => Md5Status::Unset,
+        };
+        self.decoder.set_md5_status(md5_status);
+    }
+}
+
+// A stream must not switch between fixed and variable block sizes
+fn check_block_strategy(
+    block_strategy: &mut Option<bool>,
+    frame: &Frame,
+) -> Result<(), AudioError> {
+    if *block_strategy.get_or_insert(frame.get_block_strategy()) != frame.get_block_strategy() {
+        return Err(AudioError::InvalidData(
+            "Blocking strategy changed within the stream".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl Iterator for FrameDecoder<'_> {
+    type Item = Result<DecodedBlock, AudioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(block) = self.next_silence() {
+                return Some(Ok(block));
+            }
+            if let Some(frame) = self.pending_frame.take() {
+                return Some(Ok(self.yield_frame(frame)));
+            }
+            if self.finished {
+                return None;
+            }
+            if self.at_end || self.next_sample >= self.end_sample {
+                self.finish();
+                return None;
+            }
+
+            let recovering = self.decoder.get_recovery_mode() != RecoveryMode::Disabled;
+            match self.read_frame() {
+                Ok(Some(frame)) => {
+                    // Samples between the previous frame and this one were lost to damaged data
+                    if recovering && frame.get_first_sample() > self.next_sample {
+                        self.lose_samples(self.next_sample..frame.get_first_sample());
+                    }
+                    if frame.get_first_sample() < self.end_sample {
+                        self.pending_frame = Some(frame);
+                    } else {
+                        self.next_sample = self.end_sample;
+                    }
+                }
+                Ok(None) => {
+                    // Samples missing from the end of the stream were lost as well
+                    if let Some(total_samples) = self.metadata.get_total_samples() {
+                        if recovering && total_samples > self.next_sample {
+                            self.lose_samples(self.next_sample..total_samples);
+                        }
+                    }
+                    self.at_end = true;
+                }
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+// src/codecs/flac/index.rs
+
+// This module indexes every frame of a FLAC stream, and caches the index next to the file
+
+use super::{
+    data::Metadata,
+    decoder::{CrcPolicy, Decoder},
+    frame::FrameHeader,
+    seek::FramePosition,
+};
+use crate::common::{errors::AudioError, stream::Stream};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+// Marker and version at the start of a sidecar file
+const SIDECAR_MAGIC: &[u8; 4] = b"FIDX";
+const SIDECAR_VERSION: u8 = 1;
+
+// Extension appended to the path of a FLAC file to name its sidecar
+pub const SIDECAR_EXTENSION: &str = "fidx";
+
+// Enum to represent whether seeks use a frame index, and where it is kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameIndexMode {
+    Disabled,   // Seek with the SEEKTABLE or by bisection
+    InMemory,   // Index the frames on the first seek and keep the index
+    Persistent, // Also load the index from a sidecar file, or save it there once built
+}
+
+// Struct to represent one frame of the index
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    offset: u64,       // Byte offset of the frame in the stream
+    first_sample: u64, // Index of the first sample in the frame
+    block_size: u32,   // Samples per channel in the frame
+}
+
+impl IndexEntry {
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_first_sample(&self) -> u64 {
+        self.first_sample
+    }
+
+    pub fn get_block_size(&self) -> u32 {
+        self.block_size
+    }
+}
+
+/**
+ * Identity of the file an index was built from
+ * A sidecar is only trusted if the file still has the same size, modification
+ * time and MD5 signature in STREAMINFO
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexKey {
+    file_size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    md5_signature: [u8; 16],
+}
+
+impl IndexKey {
+    // Constructor method to identify the file at a path holding the stream the metadata describes
+    pub fn new(path: &Path, metadata: &Metadata) -> Result<Self, AudioError> {
+        let file = fs::metadata(path)?;
+        let modified = file
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut md5_signature = [0; 16];
+        if let Some(signature) = metadata.get_md5_signature() {
+            md5_signature.copy_from_slice(&signature[..16]);
+        }
+
+        Ok(Self {
+            file_size: file.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            md5_signature,
+        })
+    }
+}
+
+// Index of every frame in a stream, in stream order
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl FrameIndex {
+    /**
+     * Constructor method to index a stream by reading every frame header
+     * Headers are found by scanning for sync codes followed by a valid CRC-8,
+     * so frames are found without being decoded, and damaged frames do not
+     * hide the frames after them
+     * The stream is left at an unspecified offset
+     */
+    pub fn build(stream: &mut Stream, metadata: &Metadata) -> Result<Self, AudioError> {
+        let mut from = metadata
+            .get_first_frame_offset()
+            .ok_or(AudioError::InvalidData(
+                "Cannot index frames before the metadata has been read".to_string(),
+            ))?;
+
+        let mut headers = Vec::new();
+        while let Some(offset) = Decoder::find_next_frame(stream, metadata, from)? {
+            let header = FrameHeader::read_next_header(stream, metadata, CrcPolicy::Error)?;
+            headers.push(IndexEntry {
+                offset,
+                first_sample: header.get_first_sample(),
+                block_size: header.get_block_size(),
+            });
+            from = offset + 1;
+        }
+
+        // Sync codes inside frame data can pass for headers, so after a gap a header
+        // is only kept if a later header follows on from it, or it ends the stream
+        let total_samples = metadata.get_total_samples().filter(|&total| total != 0);
+        let mut followed = vec![false; headers.len()];
+        let mut later_starts = HashSet::new();
+        for (i, header) in headers.iter().enumerate().rev() {
+            let end = header.first_sample + header.block_size as u64;
+            followed[i] = later_starts.contains(&end)
+                || total_samples.map_or(i + 1 == headers.len(), |total| end == total);
+            later_starts.insert(header.first_sample);
+        }
+
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        for (header, followed) in headers.into_iter().zip(followed) {
+            let expected = entries
+                .last()
+                .map_or(0, |entry| entry.first_sample + entry.block_size as u64);
+            if header.first_sample == expected || (header.first_sample > expected && followed) {
+                entries.push(header);
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn get_entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /**
+     * Method to find the frame holding a sample, by binary search
+     * Returns None if no indexed frame holds the sample
+     */
+    pub fn find(&self, sample: u64) -> Option<&IndexEntry> {
+        let index = self
+            .entries
+            .partition_point(|entry| entry.first_sample + entry.block_size as u64 <= sample);
+        self.entries
+            .get(index)
+            .filter(|entry| entry.first_sample <= sample)
+    }
+
+    // Method to find where the frame holding a sample starts, for seeking
+    pub fn find_frame_containing(&self, sample: u64) -> Result<FramePosition, AudioError> {
+        self.find(sample)
+            .map(|entry| FramePosition::new(entry.offset, entry.first_sample))
+            .ok_or(AudioError::InvalidData(format!(
+                "No frame holds sample {}",
+                sample
+            )))
+    }
+
+    /**
+     * Method to write the index to a sidecar file, along with the key of the
+     * file it indexes
+     */
+    pub fn save(&self, path: &Path, key: &IndexKey) -> Result<(), AudioError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(SIDECAR_MAGIC)?;
+        writer.write_u8(SIDECAR_VERSION)?;
+        writer.write_u64::<BigEndian>(key.file_size)?;
+        writer.write_u64::<BigEndian>(key.modified_secs)?;
+        writer.write_u32::<BigEndian>(key.modified_nanos)?;
+        writer.write_all(&key.md5_signature)?;
+
+        writer.write_u64::<BigEndian>(self.entries.len() as u64)?;
+        for entry in &self.entries {
+            writer.write_u64::<BigEndian>(entry.offset)?;
+            writer.write_u64::<BigEndian>(entry.first_sample)?;
+            writer.write_u32::<BigEndian>(entry.block_size)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /**
+     * Constructor method to read an index from a sidecar file
+     * Returns None if the sidecar is missing, unreadable or was built from a
+     * file with a different key
+     */
+    pub fn load(path: &Path, key: &IndexKey) -> Option<Self> {
+        let mut reader = BufReader::new(File::open(path).ok()?);
+        Self::read_sidecar(&mut reader, key).ok().flatten()
+    }
+
+    fn read_sidecar(reader: &mut impl Read, key: &IndexKey) -> std::io::Result<Option<Self>> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SIDECAR_MAGIC || reader.read_u8()? != SIDECAR_VERSION {
+            return Ok(None);
+        }
+
+        let mut sidecar_key = IndexKey {
+            file_size: reader.read_u64::<BigEndian>()?,
+            modified_secs: reader.read_u64::<BigEndian>()?,
+            modified_nanos: reader.read_u32::<BigEndian>()?,
+            md5_signature: [0; 16],
+        };
+        reader.read_exact(&mut sidecar_key.md5_signature)?;
+        if sidecar_key != *key {
+            return Ok(None);
+        }
+
+        // Entries are read one by one, so a bogus count fails at the end of the file
+        let num_entries = reader.read_u64::<BigEndian>()?;
+        let mut entries = Vec::new();
+        for _ in 0..num_entries {
+            entries.push(IndexEntry {
+                offset: reader.read_u64::<BigEndian>()?,
+                first_sample: reader.read_u64::<BigEndian>()?,
+                block_size: reader.read_u32::<BigEndian>()?,
+            });
+        }
+        Ok(Some(Self { entries }))
+    }
+}
+
+// Function to get the path of the sidecar holding the index of a FLAC file
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar)
+}
@@ -1,8 +1,18 @@
 // src/codecs/flac/mod.rs
+pub mod analysis;
 pub mod block;
+pub mod buffer;
 pub mod data;
 pub mod decoder;
+pub mod demuxer;
+#[allow(clippy::module_inception)]
 pub mod flac;
 
 pub mod frame;
+pub mod frame_decoder;
+pub mod index;
+pub mod packet_decoder;
+pub mod parallel;
+pub mod predictor;
+pub mod seek;
 pub mod subframe;
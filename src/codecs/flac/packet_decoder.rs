@@ -0,0 +1,79 @@
+// src/codecs/flac/packet_decoder.rs
+
+// This module decodes FLAC frames handed over as packets, whatever their container
+
+use super::{
+    data::Metadata,
+    decoder::{ArithmeticMode, CrcPolicy},
+    frame::Frame,
+};
+use crate::codecs::packet::{CodecId, Packet, PacketDecoder};
+use crate::common::{block::DecodedBlock, errors::AudioError, stream::Stream};
+
+/**
+ * Decoder turning packets holding one FLAC frame each into blocks of samples
+ * Frame headers may defer the sample rate and bit depth to STREAMINFO, which
+ * the metadata supplies
+ */
+pub struct FlacPacketDecoder {
+    metadata: Metadata,
+    crc_policy: CrcPolicy,
+    arithmetic_mode: ArithmeticMode,
+}
+
+impl FlacPacketDecoder {
+    // Constructor method to decode the frames of the stream the metadata describes
+    pub fn new(metadata: Metadata) -> Self {
+        Self {
+            metadata,
+            crc_policy: CrcPolicy::Error,
+            arithmetic_mode: ArithmeticMode::Fast,
+        }
+    }
+
+    /**
+     * Constructor method for frames carried without the native FLAC header,
+     * as in Ogg, Matroska and MP4, from the STREAMINFO in the codec private data
+     */
+    pub fn from_stream_info(stream_info: &[u8]) -> Result<Self, AudioError> {
+        Ok(Self::new(Metadata::from_stream_info(stream_info)?))
+    }
+
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    // Method to choose how frames with a CRC mismatch are handled
+    pub fn set_crc_policy(&mut self, crc_policy: CrcPolicy) {
+        self.crc_policy = crc_policy;
+    }
+
+    // Method to choose between fast predictions and checked arithmetic
+    pub fn set_arithmetic_mode(&mut self, arithmetic_mode: ArithmeticMode) {
+        self.arithmetic_mode = arithmetic_mode;
+    }
+
+    // Method to decode a buffer holding exactly one frame, from its sync code to its CRC-16
+    pub fn decode_frame(&mut self, frame: &[u8]) -> Result<DecodedBlock, AudioError> {
+        self.decode_stream(&mut Stream::from_bytes(frame))
+    }
+
+    fn decode_stream(&mut self, stream: &mut Stream) -> Result<DecodedBlock, AudioError> {
+        let frame = Frame::read_next_frame(
+            stream,
+            &self.metadata,
+            self.crc_policy,
+            self.arithmetic_mode,
+        )?;
+        Ok(DecodedBlock::from(frame))
+    }
+}
+
+impl PacketDecoder for FlacPacketDecoder {
+    fn decode(&mut self, packet: &Packet) -> Result<DecodedBlock, AudioError> {
+        if packet.get_codec_id() != CodecId::Flac {
+            return Err(AudioError::UnsupportedFileCodec);
+        }
+        self.decode_stream(&mut packet.to_stream())
+    }
+}
@@ -0,0 +1,263 @@
+// src/codecs/flac/parallel.rs
+
+// This module decodes the frames of a FLAC stream across several threads
+
+use super::{
+    data::Metadata,
+    decoder::{ArithmeticMode, CrcPolicy, Decoder, RecoveryMode},
+    frame::{Frame, FrameHeader},
+};
+use crate::common::{errors::AudioError, stream::Stream};
+use std::{collections::VecDeque, thread};
+
+// Candidate frames each thread decodes per batch, which bounds the frames held in memory
+const FRAMES_PER_THREAD: usize = 16;
+
+// Struct to represent the outcome of decoding from one candidate frame offset
+struct Candidate {
+    offset: u64,                              // Byte offset of the candidate frame header
+    header_crc_ok: bool,                      // Whether the header CRC-8 checks out
+    crc_mismatch: bool,                       // Whether a CRC mismatch went unreported
+    result: Result<(Frame, u64), AudioError>, // The frame and the offset just past it
+}
+
+/**
+ * Frames decoded ahead of time across several threads, handed out in
+ * stream order
+ * Frame boundaries are found by scanning for sync codes followed by a header,
+ * whose CRC-8 must check out unless the CRC policy keeps mismatched frames.
+ * Sync codes can also appear inside frame data, so candidates that start
+ * inside the previous frame are discarded, and candidates are decoded without
+ * printing CRC warnings until they are handed out
+ */
+pub struct ParallelFrames {
+    crc_policy: CrcPolicy,
+    arithmetic_mode: ArithmeticMode,
+    num_threads: usize,
+    candidates: VecDeque<Candidate>, // Decoded candidates not handed out yet
+    scan_from: Option<u64>,          // Where the scan for candidates resumes, None at the end
+    next_offset: u64,                // Byte offset just past the last frame handed out
+    resyncing: bool,                 // Set after damaged data, until a frame is handed out
+    end_offset: u64,                 // Length of the stream
+}
+
+impl ParallelFrames {
+    /**
+     * Constructor method to decode every frame from the current position of
+     * the stream to its end, split across num_threads threads
+     * Frames are decoded in batches as they are handed out, so only one batch
+     * is held in memory at a time
+     */
+    pub fn new(
+        stream: &mut Stream,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+        num_threads: usize,
+    ) -> Result<Self, AudioError> {
+        let start = stream.get_position()?;
+        Ok(Self {
+            crc_policy,
+            arithmetic_mode,
+            num_threads: num_threads.max(1),
+            candidates: VecDeque::new(),
+            scan_from: Some(start),
+            next_offset: start,
+            resyncing: false,
+            end_offset: stream.get_len()?,
+        })
+    }
+
+    // Decode the next batch of candidates, starting the scan at a byte offset
+    fn decode_batch(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+        from: u64,
+    ) -> Result<(), AudioError> {
+        // Locate the candidate frame headers first, as the scan is cheap next to decoding
+        let batch_size = self.num_threads * FRAMES_PER_THREAD;
+        let mut offsets = Vec::with_capacity(batch_size);
+        let mut scan_from = Some(from);
+        while let Some(from) = scan_from {
+            if offsets.len() == batch_size {
+                break;
+            }
+            scan_from = Decoder::find_next_frame_with(stream, metadata, from, self.crc_policy)?
+                .map(|offset| {
+                    offsets.push(offset);
+                    offset + 1
+                });
+        }
+        self.scan_from = scan_from;
+
+        // Each thread decodes a contiguous run of candidates through its own stream
+        let (crc_policy, arithmetic_mode) = (self.crc_policy, self.arithmetic_mode);
+        let source = &*stream;
+        let chunk_size = offsets.len().div_ceil(self.num_threads).max(1);
+        let results = thread::scope(|scope| {
+            let workers: Vec<_> = offsets
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || -> Result<Vec<Candidate>, AudioError> {
+                        let mut stream = source.reopen()?;
+                        Ok(chunk
+                            .iter()
+                            .map(|&offset| {
+                                Self::decode_candidate(
+                                    &mut stream,
+                                    metadata,
+                                    crc_policy,
+                                    arithmetic_mode,
+                                    offset,
+                                )
+                            })
+                            .collect())
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("frame decoding thread panicked"))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        self.candidates.extend(results.into_iter().flatten());
+        Ok(())
+    }
+
+    /**
+     * Decode the frame at a candidate offset without printing CRC warnings
+     * Under CrcPolicy::Warn, a frame with a mismatch is decoded again as if
+     * CRCs were ignored, and flagged so the warning is printed on hand out
+     */
+    fn decode_candidate(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+        offset: u64,
+    ) -> Candidate {
+        let header_crc_ok = crc_policy == CrcPolicy::Error
+            || (stream.seek_to(offset).is_ok()
+                && FrameHeader::read_next_header(stream, metadata, CrcPolicy::Error).is_ok());
+
+        let silent_policy = match crc_policy {
+            CrcPolicy::Warn => CrcPolicy::Error,
+            crc_policy => crc_policy,
+        };
+        let mut result =
+            Self::decode_frame(stream, metadata, silent_policy, arithmetic_mode, offset);
+        let crc_mismatch =
+            crc_policy == CrcPolicy::Warn && matches!(result, Err(AudioError::CrcMismatch(_)));
+        if crc_mismatch {
+            result =
+                Self::decode_frame(stream, metadata, CrcPolicy::Ignore, arithmetic_mode, offset);
+        }
+
+        Candidate {
+            offset,
+            header_crc_ok,
+            crc_mismatch,
+            result,
+        }
+    }
+
+    // Decode the frame at a byte offset, returning it with the offset just past it
+    fn decode_frame(
+        stream: &mut Stream,
+        metadata: &Metadata,
+        crc_policy: CrcPolicy,
+        arithmetic_mode: ArithmeticMode,
+        offset: u64,
+    ) -> Result<(Frame, u64), AudioError> {
+        stream.seek_to(offset)?;
+        let frame = Frame::read_next_frame(stream, metadata, crc_policy, arithmetic_mode)?;
+        Ok((frame, stream.get_position()?))
+    }
+
+    /**
+     * Method to hand out the next frame in stream order, decoding the next
+     * batch of frames from the stream when needed
+     * Frames are also checked by the validate function. When recovering,
+     * damaged frames and data between frames are skipped, and the next frame
+     * must have a header whose CRC-8 checks out; otherwise they fail decoding
+     * just as they would sequentially
+     * Returns None once every frame has been handed out, with the stream left
+     * at its end
+     */
+    pub fn next_frame(
+        &mut self,
+        stream: &mut Stream,
+        metadata: &Metadata,
+        recovery_mode: RecoveryMode,
+        mut validate: impl FnMut(&Frame) -> Result<(), AudioError>,
+    ) -> Result<Option<Frame>, AudioError> {
+        let recover = recovery_mode != RecoveryMode::Disabled;
+        loop {
+            let Some(candidate) = self.candidates.pop_front() else {
+                match self.scan_from {
+                    Some(from) => {
+                        self.decode_batch(stream, metadata, from)?;
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+
+            // A sync code inside the previous frame, not a frame of its own
+            if candidate.offset < self.next_offset {
+                continue;
+            }
+
+            if candidate.offset > self.next_offset && !recover {
+                return Err(Self::missing_frame());
+            }
+
+            // Past damaged data, frames are found by scanning for a valid header as sequentially
+            let resyncing = self.resyncing || candidate.offset > self.next_offset;
+            if resyncing && !candidate.header_crc_ok {
+                continue;
+            }
+
+            // Warnings are only printed for frames handed out, by reading them again
+            let mut result = candidate.result;
+            if candidate.crc_mismatch && result.is_ok() {
+                result = Self::decode_frame(
+                    stream,
+                    metadata,
+                    self.crc_policy,
+                    self.arithmetic_mode,
+                    candidate.offset,
+                );
+            }
+
+            match result.and_then(|(frame, end)| validate(&frame).map(|_| (frame, end))) {
+                Ok((frame, end)) => {
+                    self.next_offset = end;
+                    self.resyncing = false;
+                    return Ok(Some(frame));
+                }
+                Err(err) => {
+                    recovery_mode.skip(err)?;
+                    self.resyncing = true;
+                }
+            }
+        }
+
+        // Leave the stream at its end, as a sequential decode would
+        stream.seek_to(self.end_offset)?;
+
+        // Trailing data that holds no frame
+        if self.next_offset < self.end_offset && !recover {
+            self.next_offset = self.end_offset;
+            return Err(Self::missing_frame());
+        }
+
+        Ok(None)
+    }
+
+    fn missing_frame() -> AudioError {
+        AudioError::InvalidData("Expected frame header not found".to_string())
+    }
+}
@@ -0,0 +1,308 @@
+// src/codecs/flac/predictor.rs
+
+// This module restores subframe samples from FIXED and LPC predictions plus their residuals
+
+use super::decoder::ArithmeticMode;
+use crate::common::errors::AudioError;
+
+// Highest LPC order, and the number of zeros kept ahead of the samples in the 32-bit scratch
+pub const MAX_LPC_ORDER: usize = 32;
+
+/**
+ * Function to restore the samples of a FIXED subframe in place
+ * On entry the samples hold the warm-up samples followed by the residuals,
+ * and each residual is replaced by the sample it encodes
+ */
+pub fn restore_fixed(
+    samples: &mut [i64],
+    order: u32,
+    mode: ArithmeticMode,
+) -> Result<(), AudioError> {
+    if order > 4 {
+        return Err(AudioError::InvalidData(
+            "Invalid order in Fixed subframe".to_string(),
+        ));
+    }
+    if mode == ArithmeticMode::Checked {
+        return fixed_checked(samples, order as usize);
+    }
+
+    match order {
+        // The residuals are the samples themselves
+        0 => {}
+        1 => fixed_kernel::<1>(samples, [1]),
+        2 => fixed_kernel::<2>(samples, [-1, 2]),
+        3 => fixed_kernel::<3>(samples, [1, -3, 3]),
+        _ => fixed_kernel::<4>(samples, [-1, 4, -6, 4]),
+    }
+    Ok(())
+}
+
+/**
+ * Function to restore the samples of an LPC subframe in place
+ * On entry the samples hold the warm-up samples followed by the residuals,
+ * and each residual is replaced by the sample it encodes. Predictions are
+ * summed in 32 bits in the scratch buffer when the subframe bit depth and
+ * coefficient precision guarantee they cannot overflow, else in 64 bits
+ * The scratch buffer is only reallocated if it is too small for the block
+ */
+pub fn restore_lpc(
+    samples: &mut [i64],
+    coefficients: &[i32],
+    shift: u32,
+    bit_depth: u8,
+    precision: u8,
+    mode: ArithmeticMode,
+    scratch: &mut Vec<i32>,
+) -> Result<(), AudioError> {
+    let order = coefficients.len();
+    if order == 0 || order > MAX_LPC_ORDER || order > samples.len() {
+        return Err(AudioError::InvalidData(
+            "Invalid order in LPC subframe".to_string(),
+        ));
+    }
+
+    if mode == ArithmeticMode::Checked {
+        return lpc_checked(samples, coefficients, shift);
+    }
+
+    // Coefficients in sample order, oldest first, so each prediction is a plain dot product
+    let mut reversed = [0; MAX_LPC_ORDER];
+    for (target, &coefficient) in reversed.iter_mut().zip(coefficients.iter().rev()) {
+        *target = coefficient;
+    }
+    let reversed = &reversed[..order];
+
+    let sum_bits = bit_depth as u32 + precision as u32 + order.ilog2();
+    if sum_bits <= 32 {
+        // The scratch starts with zeros, so kernels can read whole vectors ahead of the samples
+        scratch.clear();
+        scratch.resize(MAX_LPC_ORDER, 0);
+        scratch.extend(samples.iter().map(|&sample| sample as i32));
+
+        lpc_narrow(scratch, reversed, shift);
+
+        for (sample, &restored) in samples[order..]
+            .iter_mut()
+            .zip(&scratch[MAX_LPC_ORDER + order..])
+        {
+            *sample = restored as i64;
+        }
+    } else {
+        lpc_wide(samples, reversed, shift);
+    }
+    Ok(())
+}
+
+// Generate a match dispatching every LPC order to a kernel specialized for it
+macro_rules! dispatch_order {
+    ($kernel:ident, $generic:ident, $samples:expr, $reversed:expr, $shift:expr,
+     $($order:literal)*) => {
+        match $reversed.len() {
+            $($order => $kernel::<$order>($samples, $reversed.try_into().unwrap(), $shift),)*
+            _ => $generic($samples, $reversed, $shift),
+        }
+    };
+}
+
+/**
+ * Restore LPC samples with 32-bit sums, using SIMD when the CPU has it
+ * The scratch holds MAX_LPC_ORDER zeros, the warm-up samples and the residuals
+ */
+fn lpc_narrow(scratch: &mut [i32], reversed: &[i32], shift: u32) {
+    #[cfg(target_arch = "x86_64")]
+    if reversed.len() >= 4 && std::arch::is_x86_feature_detected!("avx2") {
+        // Safety: the CPU supports AVX2, as checked above
+        unsafe { avx2::lpc_narrow(scratch, reversed, shift) };
+        return;
+    }
+
+    let samples = &mut scratch[MAX_LPC_ORDER..];
+    dispatch_order!(
+        lpc_narrow_kernel, lpc_narrow_generic, samples, reversed, shift,
+        1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+    )
+}
+
+// Restore LPC samples with 64-bit sums
+fn lpc_wide(samples: &mut [i64], reversed: &[i32], shift: u32) {
+    dispatch_order!(
+        lpc_wide_kernel, lpc_wide_generic, samples, reversed, shift,
+        1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+    )
+}
+
+/**
+ * Each kernel predicts from the last N samples, with the order known at
+ * compile time so the dot product is unrolled and vectorized
+ * Overflow wraps, as it can only happen on damaged data that fails its CRC
+ */
+fn fixed_kernel<const N: usize>(samples: &mut [i64], weights: [i64; N]) {
+    for i in N..samples.len() {
+        let history: &[i64; N] = samples[i - N..i].try_into().unwrap();
+        let prediction = history
+            .iter()
+            .zip(&weights)
+            .fold(0i64, |sum, (&sample, &weight)| {
+                sum.wrapping_add(sample.wrapping_mul(weight))
+            });
+        samples[i] = prediction.wrapping_add(samples[i]);
+    }
+}
+
+fn lpc_narrow_kernel<const N: usize>(samples: &mut [i32], reversed: &[i32; N], shift: u32) {
+    for i in N..samples.len() {
+        let history: &[i32; N] = samples[i - N..i].try_into().unwrap();
+        let mut prediction = 0i32;
+        for k in 0..N {
+            prediction = prediction.wrapping_add(history[k].wrapping_mul(reversed[k]));
+        }
+        samples[i] = (prediction >> shift).wrapping_add(samples[i]);
+    }
+}
+
+fn lpc_narrow_generic(samples: &mut [i32], reversed: &[i32], shift: u32) {
+    let order = reversed.len();
+    for i in order..samples.len() {
+        let mut prediction = 0i32;
+        for k in 0..order {
+            prediction = prediction.wrapping_add(samples[i - order + k].wrapping_mul(reversed[k]));
+        }
+        samples[i] = (prediction >> shift).wrapping_add(samples[i]);
+    }
+}
+
+fn lpc_wide_kernel<const N: usize>(samples: &mut [i64], reversed: &[i32; N], shift: u32) {
+    for i in N..samples.len() {
+        let history: &[i64; N] = samples[i - N..i].try_into().unwrap();
+        let mut prediction = 0i64;
+        for k in 0..N {
+            prediction = prediction.wrapping_add(history[k].wrapping_mul(reversed[k] as i64));
+        }
+        samples[i] = (prediction >> shift).wrapping_add(samples[i]);
+    }
+}
+
+fn lpc_wide_generic(samples: &mut [i64], reversed: &[i32], shift: u32) {
+    let order = reversed.len();
+    for i in order..samples.len() {
+        let mut prediction = 0i64;
+        for k in 0..order {
+            prediction =
+                prediction.wrapping_add(samples[i - order + k].wrapping_mul(reversed[k] as i64));
+        }
+        samples[i] = (prediction >> shift).wrapping_add(samples[i]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::MAX_LPC_ORDER;
+    use std::arch::x86_64::*;
+
+    /**
+     * Restore LPC samples with 32-bit sums, eight taps at a time
+     * The coefficients are padded with leading zeros to a multiple of eight,
+     * which then multiply the zeros ahead of the samples in the scratch
+     */
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn lpc_narrow(scratch: &mut [i32], reversed: &[i32], shift: u32) {
+        let order = reversed.len();
+        let padded_order = order.next_multiple_of(8);
+
+        let mut coefficients = [0i32; MAX_LPC_ORDER];
+        coefficients[padded_order - order..padded_order].copy_from_slice(reversed);
+        let coefficients = &coefficients[..padded_order];
+
+        let shift = _mm_cvtsi32_si128(shift as i32);
+        for i in MAX_LPC_ORDER + order..scratch.len() {
+            let history = &scratch[i - padded_order..i];
+            let mut sums = _mm256_setzero_si256();
+            for (taps, window) in coefficients.chunks_exact(8).zip(history.chunks_exact(8)) {
+                // Safety: each chunk holds exactly eight values, and unaligned loads are allowed
+                let taps = _mm256_loadu_si256(taps.as_ptr() as *const __m256i);
+                let window = _mm256_loadu_si256(window.as_ptr() as *const __m256i);
+                sums = _mm256_add_epi32(sums, _mm256_mullo_epi32(taps, window));
+            }
+
+            // Add the eight lanes together
+            let sums = _mm_add_epi32(
+                _mm256_castsi256_si128(sums),
+                _mm256_extracti128_si256::<1>(sums),
+            );
+            let sums = _mm_add_epi32(sums, _mm_shuffle_epi32::<0b01_00_11_10>(sums));
+            let sums = _mm_add_epi32(sums, _mm_shuffle_epi32::<0b10_11_00_01>(sums));
+            let prediction = _mm_cvtsi128_si32(_mm_sra_epi32(sums, shift));
+
+            scratch[i] = prediction.wrapping_add(scratch[i]);
+        }
+    }
+}
+
+/**
+ * Restore FIXED samples in 64 bits, failing if any step overflows
+ * Samples and predictions are computed in 64 bits, since the side channel
+ * of a 32-bit stream needs 33 bits and the predictions need a few more
+ */
+fn fixed_checked(samples: &mut [i64], order: usize) -> Result<(), AudioError> {
+    // Calculate the predicted samples
+    for i in order..samples.len() {
+        let predicted_sample = match order {
+            0 => Some(0),
+            1 => Some(samples[i - 1]),
+            // 2 * samples[i - 1] - samples[i - 2]
+            2 => samples[i - 1]
+                .checked_mul(2)
+                .and_then(|a| a.checked_sub(samples[i - 2])),
+            // 3 * samples[i - 1] - 3 * samples[i - 2] + samples[i - 3]
+            3 => samples[i - 1]
+                .checked_mul(3)
+                .zip(samples[i - 2].checked_mul(3))
+                .and_then(|(a, b)| a.checked_sub(b))
+                .and_then(|a| a.checked_add(samples[i - 3])),
+            // 4 * samples[i - 1] - 6 * samples[i - 2] + 4 * samples[i - 3] - samples[i - 4]
+            _ => samples[i - 1]
+                .checked_mul(4)
+                .zip(samples[i - 2].checked_mul(6))
+                .and_then(|(a, b)| a.checked_sub(b))
+                .zip(samples[i - 3].checked_mul(4))
+                .and_then(|(a, b)| a.checked_add(b))
+                .and_then(|a| a.checked_sub(samples[i - 4])),
+        };
+
+        // Add the residual to get the original sample
+        samples[i] = predicted_sample
+            .and_then(|prediction| prediction.checked_add(samples[i]))
+            .ok_or(AudioError::ArithmeticOverflow)?;
+    }
+    Ok(())
+}
+
+// Restore LPC samples in 64 bits, failing if any step overflows
+fn lpc_checked(samples: &mut [i64], coefficients: &[i32], shift: u32) -> Result<(), AudioError> {
+    let order = coefficients.len();
+
+    // Calculate the predicted samples
+    for i in order..samples.len() {
+        let mut predicted_sample: i64 = 0;
+        for (j, &coefficient) in coefficients.iter().enumerate() {
+            let temp = (coefficient as i64)
+                .checked_mul(samples[i - j - 1])
+                .ok_or(AudioError::ArithmeticOverflow)?;
+            predicted_sample = predicted_sample
+                .checked_add(temp)
+                .ok_or(AudioError::ArithmeticOverflow)?;
+        }
+
+        // Shift the prediction, with overflow protection
+        let predicted_sample = predicted_sample
+            .checked_shr(shift)
+            .ok_or(AudioError::ArithmeticOverflow)?;
+
+        // Add the residual to get the original sample, with overflow protection
+        samples[i] = predicted_sample
+            .checked_add(samples[i])
+            .ok_or(AudioError::ArithmeticOverflow)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+// src/codecs/flac/seek.rs
+
+// This module locates the frame holding a given sample in a FLAC stream
+
+use super::{
+    data::Metadata,
+    decoder::{ArithmeticMode, CrcPolicy, Decoder},
+    frame::{Frame, FrameHeader},
+};
+use crate::common::{errors::AudioError, stream::Stream};
+
+// Once the bisection narrows the search to this many bytes, frames are read one by one
+const LINEAR_SCAN_BYTES: u64 = 16 * 1024;
+
+// Struct to represent where a frame starts, in bytes and in samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FramePosition {
+    offset: u64,       // Byte offset of the frame in the stream
+    first_sample: u64, // Index of the first sample in the frame
+}
+
+impl FramePosition {
+    pub(crate) fn new(offset: u64, first_sample: u64) -> Self {
+        Self {
+            offset,
+            first_sample,
+        }
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_first_sample(&self) -> u64 {
+        self.first_sample
+    }
+}
+
+/**
+ * Function to find the frame holding a sample
+ * Starts from the closest SEEKTABLE point when one is usable, otherwise
+ * bisects the stream by scanning for frame headers, then reads frames one by
+ * one until the target is reached
+ * Returns the position of the frame, with the stream left at an unspecified offset
+ */
+pub fn find_frame_containing(
+    stream: &mut Stream,
+    metadata: &Metadata,
+    sample: u64,
+    crc_policy: CrcPolicy,
+    arithmetic_mode: ArithmeticMode,
+) -> Result<FramePosition, AudioError> {
+    if let Some(total_samples) = metadata.get_total_samples() {
+        // A total of zero means the length of the stream is unknown
+        if total_samples != 0 && sample >= total_samples {
+            return Err(AudioError::InvalidData(format!(
+                "Cannot seek to sample {} in a stream of {} samples",
+                sample, total_samples
+            )));
+        }
+    }
+
+    let first_frame = FramePosition {
+        offset: metadata
+            .get_first_frame_offset()
+            .ok_or(AudioError::InvalidData(
+                "Cannot seek before the metadata has been read".to_string(),
+            ))?,
+        first_sample: 0,
+    };
+
+    let start = match from_seek_table(stream, metadata, first_frame.offset, sample)? {
+        Some(position) => position,
+        None => bisect(stream, metadata, first_frame, sample)?,
+    };
+
+    scan(stream, metadata, start, sample, crc_policy, arithmetic_mode)
+}
+
+/**
+ * Use the last seek point at or before the sample, as long as it really
+ * points at a frame starting with that sample
+ */
+fn from_seek_table(
+    stream: &mut Stream,
+    metadata: &Metadata,
+    first_frame_offset: u64,
+    sample: u64,
+) -> Result<Option<FramePosition>, AudioError> {
+    let point = match metadata
+        .get_seek_points()
+        .iter()
+        .rev()
+        .find(|point| point.get_sample_number() <= sample)
+    {
+        Some(point) => point,
+        None => return Ok(None),
+    };
+
+    let offset = first_frame_offset + point.get_offset();
+    match read_header_at(stream, metadata, offset)? {
+        Some(header) if header.get_first_sample() == point.get_sample_number() => {
+            Ok(Some(FramePosition {
+                offset,
+                first_sample: header.get_first_sample(),
+            }))
+        }
+        _ => {
+            eprintln!("Warning: ignoring seek point that does not match a frame");
+            Ok(None)
+        }
+    }
+}
+
+/**
+ * Narrow down the byte range holding the sample by reading the frame header
+ * found after the middle of the range
+ * Returns a frame starting at or before the sample
+ */
+fn bisect(
+    stream: &mut Stream,
+    metadata: &Metadata,
+    first_frame: FramePosition,
+    sample: u64,
+) -> Result<FramePosition, AudioError> {
+    let mut low = first_frame;
+    let mut high = stream.get_len()?;
+
+    while high - low.offset > LINEAR_SCAN_BYTES {
+        let middle = low.offset + (high - low.offset) / 2;
+
+        let header = match Decoder::find_next_frame(stream, metadata, middle)? {
+            Some(offset) if offset < high => read_header_at(stream, metadata, offset)?
+                .map(|header| (offset, header.get_first_sample())),
+            _ => None,
+        };
+
+        match header {
+            Some((offset, first_sample)) if first_sample <= sample => {
+                low = FramePosition {
+                    offset,
+                    first_sample,
+                };
+            }
+            _ => high = middle,
+        }
+    }
+
+    Ok(low)
+}
+
+/**
+ * Read frames one by one from a frame starting at or before the sample until
+ * reaching the one that holds it
+ */
+fn scan(
+    stream: &mut Stream,
+    metadata: &Metadata,
+    start: FramePosition,
+    sample: u64,
+    crc_policy: CrcPolicy,
+    arithmetic_mode: ArithmeticMode,
+) -> Result<FramePosition, AudioError> {
+    stream.seek_to(start.offset)?;
+
+    loop {
+        let offset = stream.get_position()?;
+        let frame = match Frame::read_next_frame(stream, metadata, crc_policy, arithmetic_mode) {
+            Ok(frame) => frame,
+            Err(AudioError::EOF) => {
+                return Err(AudioError::InvalidData(format!(
+                    "Sample {} is past the end of the stream",
+                    sample
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        if frame.get_first_sample() + frame.get_block_size() as u64 > sample {
+            return Ok(FramePosition {
+                offset,
+                first_sample: frame.get_first_sample(),
+            });
+        }
+    }
+}
+
+// Read the frame header at a byte offset, or None if there is no valid header there
+fn read_header_at(
+    stream: &mut Stream,
+    metadata: &Metadata,
+    offset: u64,
+) -> Result<Option<FrameHeader>, AudioError> {
+    stream.seek_to(offset)?;
+    match FrameHeader::read_next_header(stream, metadata, CrcPolicy::Error) {
+        Ok(header) => Ok(Some(header)),
+        Err(AudioError::Io(err)) => Err(AudioError::Io(err)),
+        Err(_) => Ok(None),
+    }
+}
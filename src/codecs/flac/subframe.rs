@@ -1,274 +1,477 @@
 // src/codecs/flac/subframe.rs
 
+use super::{decoder::ArithmeticMode, predictor};
 use crate::common::{errors::AudioError, stream::Stream};
 
 // Enum to represent the type of FLAC subframe
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SubframeType {
     Constant,
     Fixed,
     LPC,
     Verbatim,
-    Unknown,
+}
+
+// Enum to represent the coding method of the residual section
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResidualCodingMethod {
+    Rice,  // 4-bit Rice parameters
+    Rice2, // 5-bit Rice parameters
+}
+
+/**
+ * Struct to hold how a subframe was coded, beyond what is needed to decode it
+ * Only collected on request, as when analyzing a stream
+ */
+#[derive(Debug, Clone, Default)]
+pub struct SubframeDetails {
+    bits: u64,                         // Size of the whole subframe in bits
+    precision: u8,                     // LPC coefficient precision, 0 for other subframes
+    shift: u8,                         // LPC prediction shift
+    coefficients: Vec<i32>,            // LPC coefficients
+    residual: Option<ResidualDetails>, // Residual section of predicted subframes
+}
+
+// Struct to hold how the residual section of a subframe was coded
+#[derive(Debug, Clone)]
+pub struct ResidualDetails {
+    coding_method: ResidualCodingMethod,
+    partition_order: u8,
+    partitions: Vec<PartitionDetails>,
+    bits: u64, // Size of the whole residual section in bits
+}
+
+// Struct to hold how one Rice partition was coded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartitionDetails {
+    rice_parameter: Option<u32>, // None if the partition is escaped
+    escape_bits: u8,             // Bits per residual of an escaped partition
+    num_samples: usize,          // Residuals in the partition
+    bits: u64,                   // Size of the partition in bits, parameter included
 }
 
 #[derive(Debug)]
 pub struct Subframe {
     subframe_type: SubframeType,
-    order: u32,     // Order of the predictor polynomial--if applicable
-    data: Vec<u32>, // This is the raw data contained in the subframe
+    order: u32,      // Order of the predictor polynomial--if applicable
+    wasted_bits: u8, // Number of wasted bits-per-sample
+    data: Vec<i64>,  // Decoded samples, wide enough for the 33-bit side channel
 }
 
 impl Subframe {
-    // Method to read and parse a subframe from the stream
+    /**
+     * Method to read and parse a subframe from the stream
+     * The arithmetic mode chooses between the fast predictor kernels and
+     * checked arithmetic that reports overflow
+     */
     pub fn read_next_subframe(
         stream: &mut Stream,
         bit_depth: u8,
         block_size: usize,
+        arithmetic_mode: ArithmeticMode,
     ) -> Result<Self, AudioError> {
-        let header_byte = stream.read_byte()?;
-        let mut order = 0;
-        let subframe_type = match header_byte >> 2 {
-            0 => SubframeType::Constant,
-            1 => SubframeType::Verbatim,
-            2..=9 => {
-                order = ((header_byte >> 1) - 1) as u32;
-                SubframeType::Fixed
-            }
-            10..=31 => {
-                order = ((header_byte & 0x1F) + 1) as u32;
-                SubframeType::LPC
+        let mut data = vec![0; block_size];
+        let mut subframe = Self::read_into(
+            stream,
+            bit_depth,
+            &mut data,
+            &mut Vec::new(),
+            arithmetic_mode,
+            None,
+        )?;
+        subframe.data = data;
+        Ok(subframe)
+    }
+
+    /**
+     * Method to read a subframe from the stream into a buffer holding one
+     * sample per sample of the block, without allocating
+     * The scratch buffer is working space for the predictors, and the
+     * details, when given, are filled in with how the subframe was coded
+     * Returns the subframe with its samples left in the buffer
+     */
+    pub fn read_into(
+        stream: &mut Stream,
+        bit_depth: u8,
+        samples: &mut [i64],
+        scratch: &mut Vec<i32>,
+        arithmetic_mode: ArithmeticMode,
+        mut details: Option<&mut SubframeDetails>,
+    ) -> Result<Self, AudioError> {
+        let block_size = samples.len();
+        let start_bits = stream.get_bits_read();
+
+        // The subframe header starts with a zero padding bit
+        if stream.read_bit()? != 0 {
+            return Err(AudioError::InvalidData(
+                "Invalid padding bit in subframe header".to_string(),
+            ));
+        }
+
+        // The next 6 bits hold the subframe type
+        let type_code = stream.read_bits(6)? as u8;
+
+        /*
+         * The last bit of the header is the wasted bits flag. If it is set,
+         * the number of wasted bits k follows, unary coded as k-1 zero bits
+         * and a terminating one bit
+         */
+        let wasted_bits = if stream.read_bit()? == 1 {
+            Self::read_wasted_bits(stream)?
+        } else {
+            0
+        };
+
+        if wasted_bits >= bit_depth {
+            return Err(AudioError::InvalidData(
+                "Wasted bits exceed the subframe bit depth".to_string(),
+            ));
+        }
+
+        // The subframe is coded with the wasted bits removed from every sample
+        let bit_depth = bit_depth - wasted_bits;
+
+        /*
+         * Map the type code to a subframe type and predictor order:
+         * 0 is CONSTANT, 1 is VERBATIM, 8-12 are FIXED of order 0-4 and
+         * 32-63 are LPC of order 1-32. All other codes are reserved
+         */
+        let (subframe_type, order) = match type_code {
+            0 => (SubframeType::Constant, 0),
+            1 => (SubframeType::Verbatim, 0),
+            8..=12 => (SubframeType::Fixed, (type_code - 8) as u32),
+            32..=63 => (SubframeType::LPC, ((type_code & 0x1F) + 1) as u32),
+            _ => {
+                return Err(AudioError::InvalidData(format!(
+                    "Reserved subframe type {}",
+                    type_code
+                )))
             }
-            _ => SubframeType::Unknown,
         };
 
-        println!("Subframe type: {:?}", subframe_type);
-        println!("Order: {:?}", order);
+        // The warm-up samples of a predictor cannot exceed the block
+        if order as usize > block_size {
+            return Err(AudioError::InvalidData(
+                "Predictor order exceeds block size".to_string(),
+            ));
+        }
 
-        let data = match subframe_type {
+        // Details left over from an earlier subframe would not describe this one
+        if let Some(details) = details.as_mut() {
+            details.precision = 0;
+            details.shift = 0;
+            details.coefficients.clear();
+            details.residual = None;
+        }
+
+        match subframe_type {
             SubframeType::Constant => {
-                let value = stream.read_bits(bit_depth)?;
-                vec![value; block_size] // Repeat the value for each sample in the block
+                // Repeat the value for each sample in the block
+                let value = stream.read_signed_bits_64(bit_depth)?;
+                samples.fill(value);
             }
             SubframeType::Verbatim => {
-                let mut values = Vec::new();
-                for _ in 0..block_size {
-                    let value = stream.read_bits(bit_depth)?;
-                    values.push(value);
+                for sample in samples.iter_mut() {
+                    *sample = stream.read_signed_bits_64(bit_depth)?;
                 }
-                values
             }
             SubframeType::Fixed => {
                 // Read warm-up samples
-                let mut warmup_samples = vec![0; order as usize];
-                for sample in warmup_samples.iter_mut() {
-                    *sample = stream.read_signed_bits(bit_depth)?;
+                for sample in samples[..order as usize].iter_mut() {
+                    *sample = stream.read_signed_bits_64(bit_depth)?;
                 }
 
-                // Read rice-encoded residuals
-                let residuals =
-                    Self::read_rice_encoded_residuals(stream, bit_depth, block_size, order)?;
+                // Read rice-encoded residuals, after the warm-up samples
+                let residual = details.as_mut().map(|details| &mut details.residual);
+                Self::read_rice_encoded_residuals(stream, samples, order, residual)?;
 
                 // apply predictor to calculate samples
-                let samples = Self::apply_fixed_predictor(warmup_samples, residuals, order)?;
-
-                samples.into_iter().map(|x| x as u32).collect()
+                predictor::restore_fixed(samples, order, arithmetic_mode)?;
             }
             SubframeType::LPC => {
                 // Read warm-up samples
-                let mut warmup_samples = vec![0; order as usize];
-                for sample in warmup_samples.iter_mut() {
-                    *sample = stream.read_signed_bits(bit_depth)?;
+                for sample in samples[..order as usize].iter_mut() {
+                    *sample = stream.read_signed_bits_64(bit_depth)?;
                 }
 
                 // read coefficients
-                let precision = (stream.read_bits(4)? + 1) as u8;
+                let precision_code = stream.read_bits(4)?;
+                if precision_code == 0b1111 {
+                    return Err(AudioError::InvalidData(
+                        "Invalid LPC coefficient precision".to_string(),
+                    ));
+                }
+                let precision = (precision_code + 1) as u8;
+
+                // The shift is stored as a signed value but must not be negative
                 let shift = stream.read_signed_bits(5)?;
-                let mut coefficients = vec![0; order as usize];
+                if shift < 0 {
+                    return Err(AudioError::InvalidData(
+                        "Negative LPC predictor shift".to_string(),
+                    ));
+                }
+
+                let mut coefficients = [0; predictor::MAX_LPC_ORDER];
+                let coefficients = &mut coefficients[..order as usize];
                 for coefficient in coefficients.iter_mut() {
                     *coefficient = stream.read_signed_bits(precision)?;
                 }
 
-                // read rice-encoded residuals
-                let residuals =
-                    Self::read_rice_encoded_residuals(stream, bit_depth, block_size, order)?;
+                if let Some(details) = details.as_mut() {
+                    details.precision = precision;
+                    details.shift = shift as u8;
+                    details.coefficients.clear();
+                    details.coefficients.extend_from_slice(coefficients);
+                }
+
+                // read rice-encoded residuals, after the warm-up samples
+                let residual = details.as_mut().map(|details| &mut details.residual);
+                Self::read_rice_encoded_residuals(stream, samples, order, residual)?;
 
                 // apply predictor to calculate samples
-                let samples =
-                    Self::apply_lpc_predictor(warmup_samples, coefficients, shift, residuals)?;
-
-                samples.into_iter().map(|x| x as u32).collect()
+                predictor::restore_lpc(
+                    samples,
+                    coefficients,
+                    shift as u32,
+                    bit_depth,
+                    precision,
+                    arithmetic_mode,
+                    scratch,
+                )?;
             }
-            SubframeType::Unknown => {
-                return Err(AudioError::InvalidData(String::from(
-                    "Invalid subframe type",
-                )));
+        }
+
+        // Shift the wasted bits back into every sample
+        if wasted_bits > 0 {
+            for sample in samples.iter_mut() {
+                *sample <<= wasted_bits;
             }
-        };
+        }
+
+        if let Some(details) = details {
+            details.bits = stream.get_bits_read() - start_bits;
+        }
 
         Ok(Subframe {
             subframe_type,
             order,
-            data,
+            wasted_bits,
+            data: Vec::new(),
         })
     }
 
+    // Attach samples decoded into a separate buffer
+    pub(crate) fn with_data(self, data: Vec<i64>) -> Self {
+        Self { data, ..self }
+    }
+
+    // Read the unary coded wasted bits count that follows a set wasted bits flag
+    fn read_wasted_bits(stream: &mut Stream) -> Result<u8, AudioError> {
+        let mut wasted_bits: u8 = 1;
+        while stream.read_bit()? == 0 {
+            wasted_bits = wasted_bits.checked_add(1).ok_or(AudioError::InvalidData(
+                "Invalid wasted bits count".to_string(),
+            ))?;
+        }
+        Ok(wasted_bits)
+    }
+
+    /**
+     * Read the residuals of a predicted subframe into the samples that follow
+     * the warm-up samples, recording how they were coded when asked to
+     */
     fn read_rice_encoded_residuals(
         stream: &mut Stream,
-        bit_depth: u8,
-        block_size: usize,
+        samples: &mut [i64],
         order: u32,
-    ) -> Result<Vec<i32>, AudioError> {
-        // The Rice partition order is encoded in the first 4 bits of the residual section
-        let partition_order = stream.read_bits(4)? as usize;
-        let mut partition_count = 0;
+        details: Option<&mut Option<ResidualDetails>>,
+    ) -> Result<(), AudioError> {
+        let block_size = samples.len();
+        let start_bits = stream.get_bits_read();
+        // The first 2 bits of the residual section select the coding method
+        let coding_method = match stream.read_bits(2)? {
+            0 => ResidualCodingMethod::Rice,
+            1 => ResidualCodingMethod::Rice2,
+            _ => {
+                return Err(AudioError::InvalidData(
+                    "Reserved residual coding method".to_string(),
+                ))
+            }
+        };
 
-        println!("Block size: {}", block_size);
-        println!("partition order {}", partition_order);
-        println!("order {}", order);
+        // The Rice partition order is encoded in the next 4 bits
+        let partition_order = stream.read_bits(4)? as usize;
 
         // Calcuate the number of partitions (2^order) and samples per partition
         let num_partitions = 1 << partition_order;
-        println!("num partitions {:?}", num_partitions);
-        let samples_per_partition: usize = if partition_count == num_partitions {
-            block_size / num_partitions - order as usize
-        } else {
-            block_size / num_partitions
+        let samples_per_partition = block_size >> partition_order;
+
+        /*
+         * The block size must divide evenly into the partitions, and the first
+         * partition must be large enough to hold the warm-up samples, since it
+         * is short by the predictor order
+         */
+        if samples_per_partition << partition_order != block_size
+            || samples_per_partition < order as usize
+        {
+            return Err(AudioError::InvalidData(
+                "Invalid Rice partition order for block size".to_string(),
+            ));
+        }
+
+        let (parameter_bits, escape_code) = match coding_method {
+            ResidualCodingMethod::Rice => (4, 0b1111),
+            ResidualCodingMethod::Rice2 => (5, 0b11111),
         };
 
-        // The remainder of the residuals are Rice-coded
-        let mut residuals = Vec::new();
-
-        for _ in 0..num_partitions {
-            partition_count += 1;
-            // The rice prefix is in the next bit
-            let rice_prefix = stream.read_bit()?;
-            let rice_parameter = match rice_prefix {
-                0 => stream.read_bits(4)?, // For Rice1, parameter is 4 bits
-                1 => stream.read_bits(5)?, // For Rice2, parameter is 5 bits
-                _ => {
-                    return Err(AudioError::InvalidData("Invalid Rice prefix".to_string()));
-                }
+        let mut residuals = samples[order as usize..].iter_mut();
+        let mut partitions = details.as_ref().map(|_| Vec::with_capacity(num_partitions));
+
+        for partition in 0..num_partitions {
+            let partition_start = stream.get_bits_read();
+            let partition_samples = if partition == 0 {
+                samples_per_partition - order as usize
+            } else {
+                samples_per_partition
             };
 
-            // Read the unary-encoded residuals
-            for _ in 0..samples_per_partition {
-                let mut unary = 0;
-                while stream.read_bit()? == 0 {
-                    unary += 1;
+            let rice_parameter = stream.read_bits(parameter_bits)?;
+
+            let escape_bits = if rice_parameter == escape_code {
+                // Escaped partitions hold unencoded residuals of a fixed bit width
+                let raw_bits = stream.read_bits(5)? as u8;
+                for residual in residuals.by_ref().take(partition_samples) {
+                    *residual = if raw_bits == 0 {
+                        0
+                    } else {
+                        stream.read_signed_bits(raw_bits)? as i64
+                    };
+                }
+                Some(raw_bits)
+            } else {
+                // Each residual is a unary coded quotient followed by the remainder
+                for residual in residuals.by_ref().take(partition_samples) {
+                    let mut quotient = 0;
+                    while stream.read_bit()? == 0 {
+                        quotient += 1;
+                    }
+                    let remainder = stream.read_bits(rice_parameter as u8)?;
+                    *residual = Self::decode_rice(quotient, remainder, rice_parameter)? as i64;
                 }
-                let remainder = stream.read_bits(rice_parameter as u8)?; // read the remainder
-                residuals.push(Self::decode_rice(unary, remainder));
+                None
+            };
+
+            if let Some(partitions) = partitions.as_mut() {
+                partitions.push(PartitionDetails {
+                    rice_parameter: escape_bits.is_none().then_some(rice_parameter),
+                    escape_bits: escape_bits.unwrap_or(0),
+                    num_samples: partition_samples,
+                    bits: stream.get_bits_read() - partition_start,
+                });
             }
         }
-        Ok(residuals)
+
+        if let (Some(details), Some(partitions)) = (details, partitions) {
+            *details = Some(ResidualDetails {
+                coding_method,
+                partition_order: partition_order as u8,
+                partitions,
+                bits: stream.get_bits_read() - start_bits,
+            });
+        }
+        Ok(())
     }
 
-    fn decode_rice(parameter: u32, remainder: u32) -> i32 {
-        // Take unary shifted by the remainder, plus the remainder itself
-        ((parameter << 1) | remainder) as i32
+    fn decode_rice(quotient: u32, remainder: u32, parameter: u32) -> Result<i32, AudioError> {
+        // Take the quotient shifted by the parameter, plus the remainder itself
+        let folded = ((quotient as u64) << parameter) | remainder as u64;
+
+        // Unfold the zig-zag encoding: even values are positive, odd values negative
+        let value = (folded >> 1) as i64 ^ -((folded & 1) as i64);
+        i32::try_from(value).map_err(|_| {
+            AudioError::InvalidData("Rice coded residual does not fit in 32 bits".to_string())
+        })
     }
 
-    fn apply_fixed_predictor(
-        warmup_samples: Vec<i32>,
-        residuals: Vec<i32>,
-        order: u32,
-    ) -> Result<Vec<i32>, AudioError> {
-        let block_size = warmup_samples.len() + residuals.len();
-        let mut samples = Vec::with_capacity(block_size);
-        samples.extend(warmup_samples);
-
-        // Calculate the predicted samples
-        for i in order as usize..block_size {
-            let predicted_sample = match order {
-                1 => samples[i - 1],
-                // 2 => 2 * samples[i - 1] - samples[i - 2],
-                2 => {
-                    let mul_result = samples[i - 1].checked_mul(2);
-                    match mul_result {
-                        Some(val) => val
-                            .checked_sub(samples[i - 2])
-                            .ok_or(AudioError::ArithmeticOverflow)?,
-                        None => return Err(AudioError::ArithmeticOverflow),
-                    }
-                }
-                // 3 => 3 * samples[i - 1] - 3 * samples[i - 2] + samples[i - 3],
-                // 4 => 4 * samples[i - 1] - 6 * samples[i - 2] + 4 * samples[i - 3] - samples[i - 4],
-                3 => {
-                    let mul_3_samples_1 = samples[i - 1].checked_mul(3);
-                    let mul_3_samples_2 = samples[i - 2].checked_mul(3);
-                    let result = mul_3_samples_1
-                        .and_then(|a| mul_3_samples_2.map(|b| a - b))
-                        .and_then(|a| a.checked_add(samples[i - 3]))
-                        .ok_or(AudioError::ArithmeticOverflow)?;
-                    result
-                }
-                4 => {
-                    let mul_4_samples_1 = samples[i - 1].checked_mul(4);
-                    let mul_6_samples_2 = samples[i - 2].checked_mul(6);
-                    let mul_4_samples_3 = samples[i - 3].checked_mul(4);
-                    let result = mul_4_samples_1
-                        .and_then(|a| mul_6_samples_2.map(|b| a - b))
-                        .and_then(|a| mul_4_samples_3.map(|b| a + b))
-                        .and_then(|a| a.checked_sub(samples[i - 4]))
-                        .ok_or(AudioError::ArithmeticOverflow)?;
-                    result
-                }
-                _ => {
-                    return Err(AudioError::InvalidData(
-                        "Invalid order in Fixed subframe".to_string(),
-                    ))
-                }
-            };
+    pub fn get_type(&self) -> &SubframeType {
+        &self.subframe_type
+    }
 
-            // Add the residual to get the original sample
-            let sample = predicted_sample
-                .checked_add(residuals[i - order as usize])
-                .ok_or(AudioError::ArithmeticOverflow)?;
-            samples.push(sample);
-        }
-        Ok(samples)
+    pub fn get_order(&self) -> u32 {
+        self.order
     }
 
-    fn apply_lpc_predictor(
-        warmup_samples: Vec<i32>,
-        coefficients: Vec<i32>,
-        shift: i32,
-        residuals: Vec<i32>,
-    ) -> Result<Vec<i32>, AudioError> {
-        let block_size = warmup_samples.len() + residuals.len();
-        let mut samples = Vec::with_capacity(block_size);
-        samples.extend(warmup_samples);
-
-        // Calculate the predicted samples
-        for i in coefficients.len()..block_size {
-            let mut predicted_sample: i64 = 0; // explicitly declare type as i64
-            for j in 0..coefficients.len() {
-                let temp = (coefficients[j] as i64)
-                    .checked_mul(samples[i - j - 1] as i64)
-                    .ok_or(AudioError::ArithmeticOverflow)?;
-                predicted_sample = predicted_sample
-                    .checked_add(temp)
-                    .ok_or(AudioError::ArithmeticOverflow)?;
-            }
+    pub fn get_wasted_bits(&self) -> u8 {
+        self.wasted_bits
+    }
+
+    // Method to get the decoded samples, which read_into leaves in the caller's buffer instead
+    pub fn get_data(&self) -> &[i64] {
+        &self.data
+    }
+}
 
-            // Shift the prediction, with overflow protection
-            let predicted_sample = predicted_sample
-                .checked_shr(shift as u32)
-                .ok_or(AudioError::ArithmeticOverflow)?;
+impl SubframeDetails {
+    // Method to get the size of the whole subframe in bits
+    pub fn get_bits(&self) -> u64 {
+        self.bits
+    }
 
-            // Add the residual to get the original sample, with overflow protection
-            let residual = residuals[i - coefficients.len()] as i64;
-            let sample = predicted_sample
-                .checked_add(residual)
-                .ok_or(AudioError::ArithmeticOverflow)?;
+    // Method to get the LPC coefficient precision, or 0 if the subframe is not LPC
+    pub fn get_precision(&self) -> u8 {
+        self.precision
+    }
 
-            samples.push(sample as i32); // cast back to i32 when pushing to samples
-        }
-        Ok(samples)
+    pub fn get_shift(&self) -> u8 {
+        self.shift
     }
 
-    pub fn get_data(&self) -> &[u32] {
-        &self.data
+    pub fn get_coefficients(&self) -> &[i32] {
+        &self.coefficients
+    }
+
+    // Method to get the residual section, which only FIXED and LPC subframes have
+    pub fn get_residual(&self) -> Option<&ResidualDetails> {
+        self.residual.as_ref()
+    }
+}
+
+impl ResidualDetails {
+    pub fn get_coding_method(&self) -> ResidualCodingMethod {
+        self.coding_method
+    }
+
+    pub fn get_partition_order(&self) -> u8 {
+        self.partition_order
+    }
+
+    pub fn get_partitions(&self) -> &[PartitionDetails] {
+        &self.partitions
+    }
+
+    // Method to get the size of the whole residual section in bits
+    pub fn get_bits(&self) -> u64 {
+        self.bits
+    }
+}
+
+impl PartitionDetails {
+    // Method to get the Rice parameter, or None if the partition is escaped
+    pub fn get_rice_parameter(&self) -> Option<u32> {
+        self.rice_parameter
+    }
+
+    // Method to get the bits per residual of an escaped partition
+    pub fn get_escape_bits(&self) -> u8 {
+        self.escape_bits
+    }
+
+    pub fn get_num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    // Method to get the size of the partition in bits, its parameter included
+    pub fn get_bits(&self) -> u64 {
+        self.bits
     }
 }
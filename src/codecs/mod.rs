@@ -1 +1,3 @@
 pub mod flac;
+pub mod packet;
+pub mod registry;
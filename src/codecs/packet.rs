@@ -0,0 +1,141 @@
+// src/codecs/packet.rs
+
+/*
+ * This module separates containers from codecs: a Demuxer splits a container
+ * into Packets of coded audio, and a PacketDecoder turns each Packet into
+ * samples, whichever container the Packet came from
+ */
+
+use crate::common::{block::DecodedBlock, errors::AudioError, stream::Stream};
+use std::sync::Arc;
+
+// Define the codecs whose packets can be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecId {
+    Flac,
+}
+
+// Struct to describe one audio track of a container
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    id: u32,            // Identifier of the track within its container
+    codec_id: CodecId,  // Codec of the packets of the track
+    sample_rate: u32,   // Sample rate in Hz
+    num_channels: u8,   // Number of channels
+    bit_depth: u8,      // Bit depth
+    total_samples: u64, // Samples per channel, or 0 if unknown
+}
+
+impl Track {
+    pub fn new(
+        id: u32,
+        codec_id: CodecId,
+        sample_rate: u32,
+        num_channels: u8,
+        bit_depth: u8,
+        total_samples: u64,
+    ) -> Self {
+        Self {
+            id,
+            codec_id,
+            sample_rate,
+            num_channels,
+            bit_depth,
+            total_samples,
+        }
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_codec_id(&self) -> CodecId {
+        self.codec_id
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_num_channels(&self) -> u8 {
+        self.num_channels
+    }
+
+    pub fn get_bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn get_total_samples(&self) -> u64 {
+        self.total_samples
+    }
+}
+
+// Struct to represent one unit of coded audio, as split out by a demuxer
+#[derive(Debug, Clone)]
+pub struct Packet {
+    codec_id: CodecId, // Codec the data is coded with
+    track_id: u32,     // Track the packet belongs to
+    timestamp: u64,    // Index of the first sample of the packet in its track
+    duration: u64,     // Number of samples per channel in the packet
+    data: Arc<[u8]>,   // Coded bytes, shared so decoding does not copy them
+}
+
+impl Packet {
+    pub fn new<B: Into<Arc<[u8]>>>(
+        codec_id: CodecId,
+        track_id: u32,
+        timestamp: u64,
+        duration: u64,
+        data: B,
+    ) -> Self {
+        Self {
+            codec_id,
+            track_id,
+            timestamp,
+            duration,
+            data: data.into(),
+        }
+    }
+
+    pub fn get_codec_id(&self) -> CodecId {
+        self.codec_id
+    }
+
+    pub fn get_track_id(&self) -> u32 {
+        self.track_id
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_duration(&self) -> u64 {
+        self.duration
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Method to open a stream over the coded bytes, without copying them
+    pub fn to_stream(&self) -> Stream {
+        Stream::from_bytes(self.data.clone())
+    }
+}
+
+// Splits a container into packets of coded audio
+pub trait Demuxer {
+    // Method to get the audio tracks of the container
+    fn get_tracks(&self) -> &[Track];
+
+    /**
+     * Method to read the next packet of any track
+     * Returns None once the container has no more packets
+     */
+    fn next_packet(&mut self, stream: &mut Stream) -> Result<Option<Packet>, AudioError>;
+}
+
+// Turns packets of one codec into decoded samples
+pub trait PacketDecoder {
+    fn decode(&mut self, packet: &Packet) -> Result<DecodedBlock, AudioError>;
+}
@@ -0,0 +1,126 @@
+// src/codecs/registry.rs
+
+// This module picks the format reader for a stream by probing its first bytes
+
+use super::flac::flac::FlacReader;
+use crate::common::{errors::AudioError, pcm::PCM, position::Position, stream::Stream};
+use std::io::Read;
+
+// Number of bytes at the start of a stream handed to each format reader to probe
+pub const PROBE_BYTES: u64 = 64;
+
+// Lowest score at which a format reader is trusted with a stream
+pub const MIN_SCORE: u8 = 25;
+
+// Score added when the file extension is one the format reader claims
+pub const EXTENSION_BONUS: u8 = 10;
+
+// Decoder for an opened stream, as returned by a FormatReader
+pub trait Codec {
+    // Method to describe the audio format of the stream, for display
+    fn describe(&self) -> String;
+
+    // Method to decode the rest of the stream
+    fn decode(&mut self, stream: &mut Stream) -> Result<PCM, AudioError>;
+
+    // Method to decode the samples from start up to but not including end
+    fn decode_range(
+        &mut self,
+        stream: &mut Stream,
+        start: Position,
+        end: Position,
+    ) -> Result<PCM, AudioError>;
+}
+
+// Recognizes one container format and opens streams holding it
+pub trait FormatReader {
+    fn get_name(&self) -> &'static str;
+
+    // Method to get the file extensions of the format, lowercase and without the dot
+    fn get_extensions(&self) -> &'static [&'static str];
+
+    /**
+     * Method to score how confident the reader is that a stream starting with
+     * these bytes holds its format, from 0 (certainly not) to 100
+     * The bytes are at most PROBE_BYTES long, and fewer for short streams
+     */
+    fn probe(&self, header: &[u8]) -> u8;
+
+    // Method to open a stream positioned at its start, ready to decode
+    fn open(&self, stream: &mut Stream) -> Result<Box<dyn Codec>, AudioError>;
+}
+
+/**
+ * The format readers known to the player
+ * Each stream goes to the reader scoring it highest, so formats are added by
+ * registering a reader rather than by editing the code that opens streams
+ */
+pub struct Registry {
+    readers: Vec<Box<dyn FormatReader>>,
+}
+
+impl Registry {
+    // Constructor method to create a registry without any format reader
+    pub fn new() -> Self {
+        Self {
+            readers: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, reader: Box<dyn FormatReader>) {
+        self.readers.push(reader);
+    }
+
+    /**
+     * Method to find the reader scoring the stream highest, leaving the stream
+     * where it was. The file extension breaks ties between readers that
+     * recognize the content, but never picks a reader on its own
+     * Returns the reader with its score, or UnsupportedFileCodec if no reader
+     * reaches MIN_SCORE
+     */
+    pub fn probe(&self, stream: &mut Stream) -> Result<(&dyn FormatReader, u8), AudioError> {
+        let start = stream.get_position()?;
+        let mut header = Vec::new();
+        stream.reader().take(PROBE_BYTES).read_to_end(&mut header)?;
+        stream.seek_to(start)?;
+
+        let extension = stream
+            .get_path()
+            .and_then(|path| path.extension())
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        let mut best: Option<(&dyn FormatReader, u8)> = None;
+        for reader in &self.readers {
+            let mut score = reader.probe(&header).min(100);
+            if score > 0 {
+                if let Some(extension) = &extension {
+                    if reader.get_extensions().contains(&extension.as_str()) {
+                        score = score.saturating_add(EXTENSION_BONUS).min(100);
+                    }
+                }
+            }
+
+            // The first reader registered wins a tie
+            if score >= MIN_SCORE && best.map_or(true, |(_, best_score)| score > best_score) {
+                best = Some((reader.as_ref(), score));
+            }
+        }
+
+        best.ok_or(AudioError::UnsupportedFileCodec)
+    }
+
+    // Method to open a stream with the reader scoring it highest
+    pub fn open(&self, stream: &mut Stream) -> Result<Box<dyn Codec>, AudioError> {
+        let (reader, _) = self.probe(stream)?;
+        reader.open(stream)
+    }
+}
+
+// The default registry holds every format the player supports
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(FlacReader));
+        registry
+    }
+}
@@ -0,0 +1,69 @@
+// src/common/block.rs
+
+// This module holds the blocks of samples that codecs decode, whichever codec produced them
+
+use std::ops::Range;
+
+// Struct to represent one block of decoded samples
+#[derive(Debug)]
+pub struct DecodedBlock {
+    channels: Vec<Vec<i32>>, // Samples of each channel
+    sample_rate: u32,        // Sample rate in Hz
+    bit_depth: u8,           // Bit depth
+    first_sample: u64,       // Index of the first sample in the stream
+}
+
+impl DecodedBlock {
+    pub fn new(
+        sample_rate: u32,
+        bit_depth: u8,
+        first_sample: u64,
+        channels: Vec<Vec<i32>>,
+    ) -> Self {
+        Self {
+            channels,
+            sample_rate,
+            bit_depth,
+            first_sample,
+        }
+    }
+
+    pub fn get_channels(&self) -> &[Vec<i32>] {
+        &self.channels
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    // Method to get the number of samples per channel in the block
+    pub fn get_block_size(&self) -> usize {
+        self.channels.first().map_or(0, |channel| channel.len())
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn get_first_sample(&self) -> u64 {
+        self.first_sample
+    }
+
+    // Method to take ownership of the samples of each channel
+    pub fn into_channels(self) -> Vec<Vec<i32>> {
+        self.channels
+    }
+
+    // Keep only the samples of each channel in the range, which is relative to the block
+    pub(crate) fn trim(&mut self, range: Range<usize>) {
+        for channel in &mut self.channels {
+            channel.truncate(range.end);
+            channel.drain(..range.start);
+        }
+        self.first_sample += range.start as u64;
+    }
+}
@@ -0,0 +1,256 @@
+// src/common/channels.rs
+
+// This module describes the channels a PCM buffer holds and the speakers they feed
+
+// Gain of a channel folded into two speakers, or of a speaker's signal moved off axis: -3 dB
+const MINUS_3_DB: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+// Enum to represent a speaker position, in the bit order of WAVEFORMATEXTENSIBLE channel masks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Speaker {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    LowFrequency,
+    BackLeft,
+    BackRight,
+    FrontLeftOfCenter,
+    FrontRightOfCenter,
+    BackCenter,
+    SideLeft,
+    SideRight,
+    TopCenter,
+    TopFrontLeft,
+    TopFrontCenter,
+    TopFrontRight,
+    TopBackLeft,
+    TopBackCenter,
+    TopBackRight,
+}
+
+impl Speaker {
+    // Every speaker position, in channel mask bit order
+    pub const ALL: [Speaker; 18] = [
+        Speaker::FrontLeft,
+        Speaker::FrontRight,
+        Speaker::FrontCenter,
+        Speaker::LowFrequency,
+        Speaker::BackLeft,
+        Speaker::BackRight,
+        Speaker::FrontLeftOfCenter,
+        Speaker::FrontRightOfCenter,
+        Speaker::BackCenter,
+        Speaker::SideLeft,
+        Speaker::SideRight,
+        Speaker::TopCenter,
+        Speaker::TopFrontLeft,
+        Speaker::TopFrontCenter,
+        Speaker::TopFrontRight,
+        Speaker::TopBackLeft,
+        Speaker::TopBackCenter,
+        Speaker::TopBackRight,
+    ];
+
+    // Method to get the bit of the speaker in a WAVEFORMATEXTENSIBLE channel mask
+    pub fn get_mask(self) -> u32 {
+        1 << self as u32
+    }
+
+    // Method to get the usual short name of the speaker, such as FL or LFE
+    pub fn get_abbreviation(self) -> &'static str {
+        match self {
+            Speaker::FrontLeft => "FL",
+            Speaker::FrontRight => "FR",
+            Speaker::FrontCenter => "FC",
+            Speaker::LowFrequency => "LFE",
+            Speaker::BackLeft => "BL",
+            Speaker::BackRight => "BR",
+            Speaker::FrontLeftOfCenter => "FLC",
+            Speaker::FrontRightOfCenter => "FRC",
+            Speaker::BackCenter => "BC",
+            Speaker::SideLeft => "SL",
+            Speaker::SideRight => "SR",
+            Speaker::TopCenter => "TC",
+            Speaker::TopFrontLeft => "TFL",
+            Speaker::TopFrontCenter => "TFC",
+            Speaker::TopFrontRight => "TFR",
+            Speaker::TopBackLeft => "TBL",
+            Speaker::TopBackCenter => "TBC",
+            Speaker::TopBackRight => "TBR",
+        }
+    }
+
+    /**
+     * Method to get where the signal of the speaker goes when a layout lacks
+     * it, as alternatives in order of preference: the speakers sharing the
+     * signal and the gain each gets
+     * The low frequency channel has no alternative and is dropped
+     */
+    pub(crate) fn get_fallbacks(self) -> &'static [(&'static [Speaker], f64)] {
+        use Speaker::*;
+        match self {
+            FrontLeft => &[(&[FrontCenter], MINUS_3_DB)],
+            FrontRight => &[(&[FrontCenter], MINUS_3_DB)],
+            FrontCenter => &[(&[FrontLeft, FrontRight], MINUS_3_DB)],
+            LowFrequency => &[],
+            BackLeft => &[(&[SideLeft], 1.0), (&[FrontLeft], MINUS_3_DB)],
+            BackRight => &[(&[SideRight], 1.0), (&[FrontRight], MINUS_3_DB)],
+            FrontLeftOfCenter => &[(&[FrontLeft, FrontCenter], MINUS_3_DB), (&[FrontLeft], 1.0)],
+            FrontRightOfCenter => &[
+                (&[FrontRight, FrontCenter], MINUS_3_DB),
+                (&[FrontRight], 1.0),
+            ],
+            BackCenter => &[
+                (&[BackLeft, BackRight], MINUS_3_DB),
+                (&[SideLeft, SideRight], MINUS_3_DB),
+                (&[FrontLeft, FrontRight], 0.5),
+            ],
+            SideLeft => &[(&[BackLeft], 1.0), (&[FrontLeft], MINUS_3_DB)],
+            SideRight => &[(&[BackRight], 1.0), (&[FrontRight], MINUS_3_DB)],
+            TopCenter => &[(&[FrontCenter], MINUS_3_DB)],
+            TopFrontLeft => &[(&[FrontLeft], MINUS_3_DB)],
+            TopFrontCenter => &[(&[FrontCenter], MINUS_3_DB)],
+            TopFrontRight => &[(&[FrontRight], MINUS_3_DB)],
+            TopBackLeft => &[(&[BackLeft], MINUS_3_DB)],
+            TopBackCenter => &[(&[BackCenter], MINUS_3_DB)],
+            TopBackRight => &[(&[BackRight], MINUS_3_DB)],
+        }
+    }
+}
+
+/**
+ * Struct to represent the channels of a PCM buffer, in storage order, with
+ * the speaker each channel feeds
+ * Channels without a speaker position, as beyond the bits of a channel mask,
+ * are unpositioned
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelLayout {
+    speakers: Vec<Option<Speaker>>,
+}
+
+impl ChannelLayout {
+    /**
+     * Constructor method for the default layout of a number of channels, in
+     * the order FLAC defines for 1 to 8 channels
+     * Channels past the eighth are unpositioned
+     */
+    pub fn new(num_channels: usize) -> Self {
+        use Speaker::*;
+        let speakers: &[Speaker] = match num_channels {
+            1 => &[FrontCenter],
+            2 => &[FrontLeft, FrontRight],
+            3 => &[FrontLeft, FrontRight, FrontCenter],
+            4 => &[FrontLeft, FrontRight, BackLeft, BackRight],
+            5 => &[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight],
+            6 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+            ],
+            7 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackCenter,
+                SideLeft,
+                SideRight,
+            ],
+            8 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+            ],
+            _ => &[],
+        };
+        let mut layout = Self::from_speakers(speakers);
+        layout.speakers.resize(num_channels, None);
+        layout
+    }
+
+    // Constructor method for channels feeding the given speakers, in order
+    pub fn from_speakers(speakers: &[Speaker]) -> Self {
+        Self {
+            speakers: speakers.iter().copied().map(Some).collect(),
+        }
+    }
+
+    // Constructor method for channels none of which has a speaker position
+    pub fn unpositioned(num_channels: usize) -> Self {
+        Self {
+            speakers: vec![None; num_channels],
+        }
+    }
+
+    /**
+     * Constructor method for a WAVEFORMATEXTENSIBLE channel mask, as in the
+     * WAVEFORMATEXTENSIBLE_CHANNEL_MASK Vorbis comment
+     * Channels take the speakers of the set bits from the lowest up, channels
+     * left over are unpositioned and bits left over are ignored
+     */
+    pub fn from_mask(mask: u32, num_channels: usize) -> Self {
+        let mut speakers: Vec<Option<Speaker>> = Speaker::ALL
+            .iter()
+            .filter(|speaker| mask & speaker.get_mask() != 0)
+            .map(|&speaker| Some(speaker))
+            .take(num_channels)
+            .collect();
+        speakers.resize(num_channels, None);
+        Self { speakers }
+    }
+
+    pub fn mono() -> Self {
+        Self::new(1)
+    }
+
+    pub fn stereo() -> Self {
+        Self::new(2)
+    }
+
+    // Constructor method for 5.1 surround, as FL FR FC LFE BL BR
+    pub fn surround_5_1() -> Self {
+        Self::new(6)
+    }
+
+    // Constructor method for 7.1 surround, as FL FR FC LFE BL BR SL SR
+    pub fn surround_7_1() -> Self {
+        Self::new(8)
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        self.speakers.len()
+    }
+
+    pub fn get_speakers(&self) -> &[Option<Speaker>] {
+        &self.speakers
+    }
+
+    // Method to get the speaker a channel feeds, or None if it is unpositioned or missing
+    pub fn get_speaker(&self, channel: usize) -> Option<Speaker> {
+        self.speakers.get(channel).copied().flatten()
+    }
+
+    // Method to find the channel feeding a speaker
+    pub fn find(&self, speaker: Speaker) -> Option<usize> {
+        self.speakers
+            .iter()
+            .position(|&position| position == Some(speaker))
+    }
+
+    // Method to get the WAVEFORMATEXTENSIBLE channel mask of the positioned channels
+    pub fn get_mask(&self) -> u32 {
+        self.speakers
+            .iter()
+            .flatten()
+            .fold(0, |mask, speaker| mask | speaker.get_mask())
+    }
+}
@@ -0,0 +1,209 @@
+// src/common/convert.rs
+
+// This module converts PCM samples between formats, with dither and noise shaping when reducing bit depth
+
+use super::{
+    errors::AudioError,
+    pcm::{Storage, PCM},
+    sample::{SampleData, SampleFormat, I24},
+};
+
+// Error feedback filter of the E-weighted noise shaping curve of Lipshitz et al.
+const LIPSHITZ_COEFFICIENTS: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+// Longest error feedback filter, which sets the error history kept per channel
+const MAX_SHAPING_ORDER: usize = 5;
+
+// Enum to represent the noise added before quantizing, to decorrelate the error from the signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,        // Round to the nearest value, leaving distortion correlated with the signal
+    Rectangular, // Uniform noise of 1 LSB peak to peak
+    Triangular,  // Sum of two uniform noises, 2 LSB peak to peak, removing noise modulation
+}
+
+// Enum to represent the filter moving quantization noise away from where hearing is most sensitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseShaping {
+    None,
+    FirstOrder, // Subtracts the previous error, pushing noise towards high frequencies
+    Lipshitz,   // Five-tap E-weighted filter, for 44.1 and 48 kHz
+}
+
+impl NoiseShaping {
+    fn get_coefficients(self) -> &'static [f64] {
+        match self {
+            NoiseShaping::None => &[],
+            NoiseShaping::FirstOrder => &[1.0],
+            NoiseShaping::Lipshitz => &LIPSHITZ_COEFFICIENTS,
+        }
+    }
+}
+
+/**
+ * Converter of PCM buffers to one sample format
+ * Integer samples are scaled so that full scale maps to full scale, and float
+ * samples are clipped to the range of integer formats. Dither and noise
+ * shaping only apply when precision is lost, and the converter keeps the
+ * error history of each channel so consecutive blocks are shaped as one stream
+ */
+#[derive(Debug, Clone)]
+pub struct Converter {
+    format: SampleFormat,
+    dither: Dither,
+    noise_shaping: NoiseShaping,
+    rng_state: u64,
+    errors: Vec<[f64; MAX_SHAPING_ORDER]>, // Latest quantization errors of each channel, newest first
+}
+
+impl Converter {
+    // Constructor method for a converter to a format, rounding without dither
+    pub fn new(format: SampleFormat) -> Self {
+        Self {
+            format,
+            dither: Dither::None,
+            noise_shaping: NoiseShaping::None,
+            rng_state: 0x9E37_79B9_7F4A_7C15,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn get_format(&self) -> SampleFormat {
+        self.format
+    }
+
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    pub fn set_noise_shaping(&mut self, noise_shaping: NoiseShaping) {
+        self.noise_shaping = noise_shaping;
+    }
+
+    // Method to seed the dither noise, which is otherwise the same on every run
+    pub fn set_seed(&mut self, seed: u64) {
+        // Xorshift gets stuck at zero
+        self.rng_state = seed.max(1);
+    }
+
+    // Method to forget the error history, as before converting a new stream
+    pub fn reset(&mut self) {
+        self.errors.clear();
+    }
+
+    // Method to convert the samples of a buffer, keeping its layout, sample rate and storage order
+    pub fn convert(&mut self, pcm: &PCM) -> Result<PCM, AudioError> {
+        let source = pcm.get_format();
+        let num_channels = pcm.get_num_channels();
+        let num_frames = pcm.get_num_frames();
+        let storage = pcm.get_storage();
+        if self.errors.len() != num_channels {
+            self.errors = vec![[0.0; MAX_SHAPING_ORDER]; num_channels];
+        }
+
+        let samples = normalize(pcm.get_data());
+        let channel_of = |index: usize| match storage {
+            Storage::Planar => index / num_frames.max(1),
+            Storage::Interleaved => index % num_channels,
+        };
+
+        // Integer samples fit exactly in a format at least as wide
+        let exact = !source.is_float() && source.get_bits() <= self.format.get_bits();
+        let data = match self.format {
+            SampleFormat::F32 => SampleData::F32(samples.iter().map(|&x| x as f32).collect()),
+            SampleFormat::F64 => SampleData::F64(samples),
+            format => {
+                let scale = (1u64 << (format.get_bits() - 1)) as f64;
+                let quantized = samples.iter().enumerate().map(|(i, &x)| {
+                    if exact {
+                        x * scale
+                    } else {
+                        self.quantize(x * scale, channel_of(i), scale)
+                    }
+                });
+                match format {
+                    SampleFormat::I8 => SampleData::I8(quantized.map(|x| x as i8).collect()),
+                    SampleFormat::I16 => SampleData::I16(quantized.map(|x| x as i16).collect()),
+                    SampleFormat::I24 => {
+                        SampleData::I24(quantized.map(|x| I24::saturating(x as i32)).collect())
+                    }
+                    _ => SampleData::I32(quantized.map(|x| x as i32).collect()),
+                }
+            }
+        };
+
+        // Samples keep their bit depth as far as the target format holds it, through float formats too
+        Ok(PCM::from_data(
+            data,
+            pcm.get_layout().clone(),
+            pcm.get_sample_rate(),
+            storage,
+        )?
+        .with_valid_bits(pcm.get_valid_bits()))
+    }
+
+    /**
+     * Round a sample counted in LSBs of the target format, after noise shaping
+     * and dither, and clip it to the range of the format
+     * Returns an integral value
+     */
+    fn quantize(&mut self, value: f64, channel: usize, scale: f64) -> f64 {
+        if value.is_nan() {
+            return 0.0;
+        }
+
+        let coefficients = self.noise_shaping.get_coefficients();
+        let errors = &self.errors[channel];
+        let shaped = value
+            - coefficients
+                .iter()
+                .zip(errors)
+                .map(|(coefficient, error)| coefficient * error)
+                .sum::<f64>();
+
+        let noise = match self.dither {
+            Dither::None => 0.0,
+            Dither::Rectangular => self.next_uniform(),
+            Dither::Triangular => self.next_uniform() + self.next_uniform(),
+        };
+        let rounded = (shaped + noise).round();
+
+        /*
+         * The error fed back is taken before clipping, so a clipped sample
+         * cannot make the filter run away
+         */
+        if !coefficients.is_empty() {
+            let errors = &mut self.errors[channel];
+            errors.copy_within(..MAX_SHAPING_ORDER - 1, 1);
+            errors[0] = rounded - shaped;
+        }
+        rounded.clamp(-scale, scale - 1.0)
+    }
+
+    // Draw uniform noise between -0.5 and 0.5 from a xorshift64* generator
+    fn next_uniform(&mut self) -> f64 {
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let bits = self.rng_state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        bits as f64 / (1u64 << 53) as f64 - 0.5
+    }
+}
+
+// Scale samples to floats where full scale is -1.0 to 1.0, which is exact for every integer format
+fn normalize(data: &SampleData) -> Vec<f64> {
+    match data {
+        SampleData::I8(samples) => samples.iter().map(|&x| x as f64 / 128.0).collect(),
+        SampleData::I16(samples) => samples.iter().map(|&x| x as f64 / 32768.0).collect(),
+        SampleData::I24(samples) => samples
+            .iter()
+            .map(|x| x.get() as f64 / 8_388_608.0)
+            .collect(),
+        SampleData::I32(samples) => samples
+            .iter()
+            .map(|&x| x as f64 / 2_147_483_648.0)
+            .collect(),
+        SampleData::F32(samples) => samples.iter().map(|&x| x as f64).collect(),
+        SampleData::F64(samples) => samples.clone(),
+    }
+}
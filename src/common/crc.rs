@@ -0,0 +1,62 @@
+// src/common/crc.rs
+
+// This module provides the table driven CRCs used to protect FLAC frames
+
+// CRC-8 with polynomial x^8 + x^2 + x^1 + x^0, used for frame headers
+const CRC8_POLYNOMIAL: u8 = 0x07;
+
+// CRC-16 with polynomial x^16 + x^15 + x^2 + x^0, used for whole frames
+const CRC16_POLYNOMIAL: u16 = 0x8005;
+
+const CRC8_TABLE: [u8; 256] = make_crc8_table();
+const CRC16_TABLE: [u16; 256] = make_crc16_table();
+
+const fn make_crc8_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ CRC8_POLYNOMIAL
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn make_crc16_table() -> [u16; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ CRC16_POLYNOMIAL
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// Update a running CRC-8 with one more byte
+pub fn crc8_update(crc: u8, byte: u8) -> u8 {
+    CRC8_TABLE[(crc ^ byte) as usize]
+}
+
+// Update a running CRC-16 with one more byte
+pub fn crc16_update(crc: u16, byte: u8) -> u16 {
+    (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize]
+}
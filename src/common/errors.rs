@@ -28,6 +28,10 @@ pub enum AudioError {
     #[error("Invalid data: {0}")]
     InvalidData(String),
 
+    // Represents a checksum that does not match the data it protects
+    #[error("CRC mismatch: {0}")]
+    CrcMismatch(String),
+
     #[error("Attempted to read more than 32 bits")]
     ExceededBitLimit,
 
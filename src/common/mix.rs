@@ -0,0 +1,225 @@
+// src/common/mix.rs
+
+// This module mixes the channels of PCM buffers into other channel layouts through gain matrices
+
+use super::{
+    channels::{ChannelLayout, Speaker},
+    errors::AudioError,
+    pcm::{Storage, PCM},
+    sample::SampleFormat,
+};
+
+// Longest chain of fallback speakers followed when routing a channel into a layout
+const MAX_FALLBACK_DEPTH: usize = 3;
+
+/**
+ * Matrix of the gain of each input channel in each output channel
+ * Each output sample is the sum of the input samples of the same instant
+ * times their gains
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixMatrix {
+    input: ChannelLayout,
+    output: ChannelLayout,
+    gains: Vec<Vec<f64>>, // One row of input gains per output channel
+}
+
+impl MixMatrix {
+    /**
+     * Constructor method for a custom matrix, with one row of input gains per
+     * output channel
+     * Fails if the gains do not match the number of channels of the layouts
+     */
+    pub fn new(
+        input: ChannelLayout,
+        output: ChannelLayout,
+        gains: Vec<Vec<f64>>,
+    ) -> Result<Self, AudioError> {
+        if gains.len() != output.get_num_channels()
+            || gains
+                .iter()
+                .any(|row| row.len() != input.get_num_channels())
+        {
+            return Err(AudioError::InvalidData(format!(
+                "Mix matrix must have {} rows of {} gains",
+                output.get_num_channels(),
+                input.get_num_channels()
+            )));
+        }
+        Ok(Self {
+            input,
+            output,
+            gains,
+        })
+    }
+
+    /**
+     * Constructor method for the standard matrix between two layouts
+     * Channels go to the output channel of the same speaker. Otherwise their
+     * signal is spread over nearby speakers at -3 dB, as in ITU-R BS.775, so
+     * 5.1 and 7.1 fold down into stereo and stereo into mono, and the low
+     * frequency channel is dropped. Unpositioned channels go to the
+     * unpositioned output channel of the same index, if any.
+     * Gains are scaled down if needed so that no output can clip
+     */
+    pub fn standard(input: &ChannelLayout, output: &ChannelLayout) -> Self {
+        let mut gains = vec![vec![0.0; input.get_num_channels()]; output.get_num_channels()];
+        for (channel, speaker) in input.get_speakers().iter().enumerate() {
+            match speaker {
+                Some(speaker) => Self::route(
+                    output,
+                    *speaker,
+                    1.0,
+                    MAX_FALLBACK_DEPTH,
+                    &mut |out, gain| gains[out][channel] += gain,
+                ),
+                None => {
+                    if channel < output.get_num_channels() && output.get_speaker(channel).is_none()
+                    {
+                        gains[channel][channel] = 1.0;
+                    }
+                }
+            }
+        }
+
+        let mut matrix = Self {
+            input: input.clone(),
+            output: output.clone(),
+            gains,
+        };
+        matrix.normalize();
+        matrix
+    }
+
+    // Send the signal of a speaker to the output channels standing in for it
+    fn route(
+        output: &ChannelLayout,
+        speaker: Speaker,
+        gain: f64,
+        depth: usize,
+        add: &mut impl FnMut(usize, f64),
+    ) {
+        if let Some(channel) = output.find(speaker) {
+            add(channel, gain);
+            return;
+        }
+        let fallbacks = speaker.get_fallbacks();
+        if depth == 0 || fallbacks.is_empty() {
+            return;
+        }
+
+        // Prefer speakers the output has, otherwise route through the first alternative
+        let (targets, target_gain) = fallbacks
+            .iter()
+            .find(|(targets, _)| targets.iter().all(|&target| output.find(target).is_some()))
+            .unwrap_or(&fallbacks[0]);
+        for &target in targets.iter() {
+            Self::route(output, target, gain * target_gain, depth - 1, add);
+        }
+    }
+
+    // Constructor method for a matrix passing every channel through unchanged
+    pub fn identity(layout: &ChannelLayout) -> Self {
+        let num_channels = layout.get_num_channels();
+        let gains = (0..num_channels)
+            .map(|out| {
+                (0..num_channels)
+                    .map(|channel| (channel == out) as u8 as f64)
+                    .collect()
+            })
+            .collect();
+        Self {
+            input: layout.clone(),
+            output: layout.clone(),
+            gains,
+        }
+    }
+
+    pub fn get_input(&self) -> &ChannelLayout {
+        &self.input
+    }
+
+    pub fn get_output(&self) -> &ChannelLayout {
+        &self.output
+    }
+
+    pub fn get_gains(&self) -> &[Vec<f64>] {
+        &self.gains
+    }
+
+    // Method to get the gain of an input channel in an output channel
+    pub fn get_gain(&self, output: usize, input: usize) -> f64 {
+        self.gains[output][input]
+    }
+
+    pub fn set_gain(&mut self, output: usize, input: usize, gain: f64) {
+        self.gains[output][input] = gain;
+    }
+
+    /**
+     * Method to scale every gain down so that no output channel sums to more
+     * than full scale, leaving matrices that cannot clip unchanged
+     */
+    pub fn normalize(&mut self) {
+        let largest = self
+            .gains
+            .iter()
+            .map(|row| row.iter().map(|gain| gain.abs()).sum::<f64>())
+            .fold(0.0, f64::max);
+        if largest > 1.0 {
+            self.gains
+                .iter_mut()
+                .flatten()
+                .for_each(|gain| *gain /= largest);
+        }
+    }
+
+    /**
+     * Method to mix the channels of a buffer into the output layout
+     * The result keeps the sample format, sample rate and storage order of the
+     * input, with integer outputs rounded and clipped
+     */
+    pub fn apply(&self, pcm: &PCM) -> Result<PCM, AudioError> {
+        if pcm.get_num_channels() != self.input.get_num_channels() {
+            return Err(AudioError::InvalidData(format!(
+                "Mix matrix expects {} channels, got {}",
+                self.input.get_num_channels(),
+                pcm.get_num_channels()
+            )));
+        }
+
+        let planar = pcm.to_format(SampleFormat::F64)?.into_planar();
+        let samples = planar.samples::<f64>().unwrap_or_default();
+        let num_frames = pcm.get_num_frames();
+
+        let mut mixed = vec![0.0; num_frames * self.output.get_num_channels()];
+        for (output, row) in mixed.chunks_mut(num_frames.max(1)).zip(&self.gains) {
+            for (input, &gain) in samples.chunks(num_frames.max(1)).zip(row) {
+                if gain == 0.0 {
+                    continue;
+                }
+                for (out, x) in output.iter_mut().zip(input) {
+                    *out += x * gain;
+                }
+            }
+        }
+
+        let mixed = PCM::new(
+            mixed,
+            self.output.clone(),
+            pcm.get_sample_rate(),
+            Storage::Planar,
+        )?;
+        Ok(mixed
+            .to_format(pcm.get_format())?
+            .into_storage(pcm.get_storage())
+            .with_valid_bits(pcm.get_valid_bits()))
+    }
+}
+
+impl PCM {
+    // Method to get a copy of the buffer mixed into another layout with the standard matrix
+    pub fn remix(&self, layout: &ChannelLayout) -> Result<PCM, AudioError> {
+        MixMatrix::standard(self.get_layout(), layout).apply(self)
+    }
+}
@@ -1,3 +1,12 @@
+pub mod block;
+pub mod channels;
+pub mod convert;
+pub mod crc;
 pub mod errors;
+pub mod md5;
+pub mod mix;
 pub mod pcm;
+pub mod position;
+pub mod resample;
+pub mod sample;
 pub mod stream;
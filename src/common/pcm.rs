@@ -1,12 +1,47 @@
 // src/common/pcm.rs
-use super::{errors::AudioError, stream::Stream};
-use crate::codecs::flac::flac::FLAC;
-use std::{io::Read, str};
+use super::{
+    channels::ChannelLayout,
+    convert::Converter,
+    errors::AudioError,
+    position::Position,
+    sample::{Sample, SampleData, SampleFormat, I24},
+    stream::Stream,
+};
+use crate::codecs::registry::Registry;
+use std::{fmt::Debug, time::Duration};
 
-// Define PCM structure holding audio data
+// Enum to represent the order samples are stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Planar,      // Every sample of the first channel, then of the next channel, and so on
+    Interleaved, // One sample of each channel in turn
+}
+
+/**
+ * Define PCM structure holding audio data
+ * Every channel holds the same number of samples, and integer samples use the
+ * full range of their format whatever the bit depth they were decoded at
+ * The valid bits keep that bit depth, counted from the most significant bit
+ */
+#[derive(Debug, Clone, PartialEq)]
 pub struct PCM {
-    // This should be the PCM data structure, Vec<u8> is a placeholder here
-    data: Vec<u32>,
+    data: SampleData,
+    layout: ChannelLayout,
+    sample_rate: u32,
+    storage: Storage,
+    valid_bits: u8,
+}
+
+/**
+ * Borrowed view of the samples of one channel, in either storage order
+ * Samples of interleaved buffers are reached by stepping over the other channels
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelView<'a, T> {
+    samples: &'a [T],
+    start: usize,
+    stride: usize,
+    len: usize,
 }
 
 impl PCM {
@@ -16,35 +51,320 @@ impl PCM {
      * Returns a Result that may contain a PCM or an AudioError
      */
     pub fn from_stream(stream: &mut Stream) -> Result<Self, AudioError> {
-        // Determine the file type
-        let mut buffer = [0; 4]; // Find the magic bytes
-        stream.reader().read_exact(&mut buffer)?;
+        Self::from_stream_with(stream, &Registry::default())
+    }
+
+    /**
+     * Costructor method to create a PCM from a Stream, choosing the decoder
+     * among the format readers of the registry
+     */
+    pub fn from_stream_with(stream: &mut Stream, registry: &Registry) -> Result<Self, AudioError> {
+        registry.open(stream)?.decode(stream)
+    }
 
-        let data = match str::from_utf8(&buffer) {
-            Ok("fLaC") => {
-                let mut flac = FLAC::new(stream)?;
+    /**
+     * Costructor method to create a PCM from the samples of a Stream from
+     * start up to but not including end
+     */
+    pub fn from_stream_range(
+        stream: &mut Stream,
+        start: Position,
+        end: Position,
+    ) -> Result<Self, AudioError> {
+        Self::from_stream_range_with(stream, &Registry::default(), start, end)
+    }
 
-                let metadata = flac.get_metadata();
-                println!("{:?}", metadata.get_short_format());
-                println!("{:?}", metadata.get_long_format());
+    /**
+     * Costructor method to create a PCM from the samples of a Stream from
+     * start up to but not including end, choosing the decoder among the
+     * format readers of the registry
+     */
+    pub fn from_stream_range_with(
+        stream: &mut Stream,
+        registry: &Registry,
+        start: Position,
+        end: Position,
+    ) -> Result<Self, AudioError> {
+        registry.open(stream)?.decode_range(stream, start, end)
+    }
 
-                let data = flac.decode(stream)?;
-                data
+    /**
+     * Constructor method for samples stored in the given order
+     * Fails if the samples do not divide evenly between the channels
+     */
+    pub fn new<T: Sample>(
+        samples: Vec<T>,
+        layout: ChannelLayout,
+        sample_rate: u32,
+        storage: Storage,
+    ) -> Result<Self, AudioError> {
+        Self::from_data(T::into_data(samples), layout, sample_rate, storage)
+    }
+
+    // Constructor method for samples of a format only known at runtime
+    pub fn from_data(
+        data: SampleData,
+        layout: ChannelLayout,
+        sample_rate: u32,
+        storage: Storage,
+    ) -> Result<Self, AudioError> {
+        let num_channels = layout.get_num_channels();
+        if num_channels == 0 {
+            return Err(AudioError::InvalidData(
+                "PCM buffers need at least one channel".to_string(),
+            ));
+        }
+        if data.len() % num_channels != 0 {
+            return Err(AudioError::InvalidData(format!(
+                "{} samples do not divide between {} channels",
+                data.len(),
+                num_channels
+            )));
+        }
+
+        Ok(Self {
+            valid_bits: data.get_format().get_bits(),
+            data,
+            layout,
+            sample_rate,
+            storage,
+        })
+    }
+
+    // Constructor method for planar samples handed over one channel at a time
+    pub fn from_channels<T: Sample>(
+        channels: Vec<Vec<T>>,
+        sample_rate: u32,
+    ) -> Result<Self, AudioError> {
+        let num_frames = channels.first().map_or(0, |channel| channel.len());
+        if channels.iter().any(|channel| channel.len() != num_frames) {
+            return Err(AudioError::InvalidData(
+                "Channels hold different numbers of samples".to_string(),
+            ));
+        }
+        let layout = ChannelLayout::new(channels.len());
+        Self::new(channels.concat(), layout, sample_rate, Storage::Planar)
+    }
+
+    /**
+     * Constructor method for decoded integer samples of a bit depth, as
+     * decoders produce them
+     * The samples are stored in the smallest format holding the bit depth,
+     * shifted up to use its full range, and the bit depth is kept as valid bits
+     */
+    pub fn from_integer_channels(
+        channels: Vec<Vec<i32>>,
+        bit_depth: u8,
+        sample_rate: u32,
+    ) -> Result<Self, AudioError> {
+        let format = SampleFormat::for_bit_depth(bit_depth).ok_or(AudioError::InvalidData(
+            format!("Unsupported bit depth {}", bit_depth),
+        ))?;
+        let shift = format.get_bits() - bit_depth;
+        let pcm = match format {
+            SampleFormat::I8 => {
+                Self::from_channels(map_channels(channels, |x| (x << shift) as i8), sample_rate)
+            }
+            SampleFormat::I16 => {
+                Self::from_channels(map_channels(channels, |x| (x << shift) as i16), sample_rate)
             }
-            // Ok("ID3\x03") => {
-            //     println!("mp3 file");
-            //     let mut mp3 = Mp3::new(stream)?;
-            //     mp3.decode()
-            // }
-            // Ok("OggS") => {
-            //     println!("ogg vorbis file");
-            //     let mut ogg = OGG::new(stream)?;
-            //     ogg.decode()
-            // }
-            _ => return Err(AudioError::UnsupportedFileCodec),
+            SampleFormat::I24 => Self::from_channels(
+                map_channels(channels, |x| I24::saturating(x << shift)),
+                sample_rate,
+            ),
+            _ => Self::from_channels(map_channels(channels, |x| x << shift), sample_rate),
+        }?;
+        Ok(pcm.with_valid_bits(bit_depth))
+    }
+
+    // Method to set how many of the most significant bits of each sample carry audio
+    pub(crate) fn with_valid_bits(self, valid_bits: u8) -> Self {
+        Self {
+            valid_bits: valid_bits.min(self.get_format().get_bits()),
+            ..self
+        }
+    }
+
+    pub fn get_format(&self) -> SampleFormat {
+        self.data.get_format()
+    }
+
+    pub fn get_layout(&self) -> &ChannelLayout {
+        &self.layout
+    }
+
+    // Method to relabel the speaker positions of the channels, which must be as many
+    pub fn set_layout(&mut self, layout: ChannelLayout) -> Result<(), AudioError> {
+        if layout.get_num_channels() != self.layout.get_num_channels() {
+            return Err(AudioError::InvalidData(format!(
+                "Layout of {} channels does not fit a buffer of {}",
+                layout.get_num_channels(),
+                self.layout.get_num_channels()
+            )));
+        }
+        self.layout = layout;
+        Ok(())
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        self.layout.get_num_channels()
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_storage(&self) -> Storage {
+        self.storage
+    }
+
+    /**
+     * Method to get the bit depth the samples were decoded at, kept through
+     * conversions as far as the sample format holds it
+     * Only that many of the most significant bits of decoded integer samples
+     * are meaningful, the rest are zero
+     */
+    pub fn get_valid_bits(&self) -> u8 {
+        self.valid_bits
+    }
+
+    // Method to get the number of samples per channel
+    pub fn get_num_frames(&self) -> usize {
+        self.data.len() / self.get_num_channels()
+    }
+
+    // Method to get the playing time of the samples
+    pub fn get_duration(&self) -> Duration {
+        if self.sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        let nanos = self.get_num_frames() as u128 * 1_000_000_000 / self.sample_rate as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    pub fn get_data(&self) -> &SampleData {
+        &self.data
+    }
+
+    // Method to take ownership of the samples, in storage order
+    pub fn into_data(self) -> SampleData {
+        self.data
+    }
+
+    // Method to borrow the samples in storage order, or None if they are not of type T
+    pub fn samples<T: Sample>(&self) -> Option<&[T]> {
+        T::slice(&self.data)
+    }
+
+    pub fn samples_mut<T: Sample>(&mut self) -> Option<&mut [T]> {
+        T::slice_mut(&mut self.data)
+    }
+
+    /**
+     * Method to view the samples of one channel without copying them
+     * Returns None if the channel does not exist or the samples are not of type T
+     */
+    pub fn channel<T: Sample>(&self, channel: usize) -> Option<ChannelView<'_, T>> {
+        let num_channels = self.get_num_channels();
+        if channel >= num_channels {
+            return None;
+        }
+        let len = self.get_num_frames();
+        let (start, stride) = match self.storage {
+            Storage::Planar => (channel * len, 1),
+            Storage::Interleaved => (channel, num_channels),
+        };
+        Some(ChannelView {
+            samples: self.samples()?,
+            start,
+            stride,
+            len,
+        })
+    }
+
+    // Method to get a copy of the buffer with its samples in another order
+    pub fn to_storage(&self, storage: Storage) -> Self {
+        self.clone().into_storage(storage)
+    }
+
+    // Method to reorder the samples, which is free if they are already in that order
+    pub fn into_storage(self, storage: Storage) -> Self {
+        if storage == self.storage || self.get_num_channels() == 1 {
+            return Self { storage, ..self };
+        }
+
+        // Planar samples are a channels x frames matrix and interleaved ones its transpose
+        let (rows, cols) = match self.storage {
+            Storage::Planar => (self.get_num_channels(), self.get_num_frames()),
+            Storage::Interleaved => (self.get_num_frames(), self.get_num_channels()),
         };
+        let data = match &self.data {
+            SampleData::I8(samples) => SampleData::I8(transpose(samples, rows, cols)),
+            SampleData::I16(samples) => SampleData::I16(transpose(samples, rows, cols)),
+            SampleData::I24(samples) => SampleData::I24(transpose(samples, rows, cols)),
+            SampleData::I32(samples) => SampleData::I32(transpose(samples, rows, cols)),
+            SampleData::F32(samples) => SampleData::F32(transpose(samples, rows, cols)),
+            SampleData::F64(samples) => SampleData::F64(transpose(samples, rows, cols)),
+        };
+        Self {
+            data,
+            storage,
+            ..self
+        }
+    }
+
+    /**
+     * Method to add the samples of another buffer after those of this one
+     * Both buffers must hold the same format, channels and sample rate, and
+     * the result keeps the valid bits of the more precise one
+     */
+    pub fn append(&mut self, other: &PCM) -> Result<(), AudioError> {
+        if other.get_format() != self.get_format()
+            || other.layout != self.layout
+            || other.sample_rate != self.sample_rate
+        {
+            return Err(AudioError::InvalidData(
+                "Cannot append samples of a different format".to_string(),
+            ));
+        }
+
+        self.valid_bits = self.valid_bits.max(other.valid_bits);
+        let storage = self.storage;
+        let num_channels = self.get_num_channels();
+        let num_frames = self.get_num_frames();
+        let other = other.to_storage(storage).into_data();
+        let shape = (storage, num_channels, num_frames);
+        match (&mut self.data, other) {
+            (SampleData::I8(samples), SampleData::I8(other)) => append(samples, other, shape),
+            (SampleData::I16(samples), SampleData::I16(other)) => append(samples, other, shape),
+            (SampleData::I24(samples), SampleData::I24(other)) => append(samples, other, shape),
+            (SampleData::I32(samples), SampleData::I32(other)) => append(samples, other, shape),
+            (SampleData::F32(samples), SampleData::F32(other)) => append(samples, other, shape),
+            (SampleData::F64(samples), SampleData::F64(other)) => append(samples, other, shape),
+            _ => unreachable!("formats were checked to match"),
+        }
+        Ok(())
+    }
+
+    /**
+     * Method to get a copy of the buffer in another sample format, rounding
+     * to the nearest value without dither when precision is lost
+     */
+    pub fn to_format(&self, format: SampleFormat) -> Result<Self, AudioError> {
+        Converter::new(format).convert(self)
+    }
 
-        Ok(Self { data })
+    // Method to get a copy of the buffer in the format of a converter, with its dither and noise shaping
+    pub fn convert(&self, converter: &mut Converter) -> Result<Self, AudioError> {
+        converter.convert(self)
+    }
+
+    pub fn into_interleaved(self) -> Self {
+        self.into_storage(Storage::Interleaved)
+    }
+
+    pub fn into_planar(self) -> Self {
+        self.into_storage(Storage::Planar)
     }
 
     /**
@@ -54,10 +374,90 @@ impl PCM {
     pub fn play(&self) -> Result<(), AudioError> {
         // Send PCM data to the audio hardware
         // test for now
-        for sample in self.data.iter().take(10) {
-            println!("{:?}", sample)
+        match &self.data {
+            SampleData::I8(samples) => print_samples(samples),
+            SampleData::I16(samples) => print_samples(samples),
+            SampleData::I24(samples) => print_samples(samples),
+            SampleData::I32(samples) => print_samples(samples),
+            SampleData::F32(samples) => print_samples(samples),
+            SampleData::F64(samples) => print_samples(samples),
         }
 
         Ok(())
     }
 }
+
+impl<'a, T: Copy> ChannelView<'a, T> {
+    // Method to get the number of samples in the channel
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        (index < self.len).then(|| self.samples[self.start + index * self.stride])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + 'a {
+        self.samples[self.start..]
+            .iter()
+            .step_by(self.stride)
+            .take(self.len)
+            .copied()
+    }
+
+    // Method to borrow the channel as a slice, which only planar buffers allow
+    pub fn as_slice(&self) -> Option<&'a [T]> {
+        (self.stride == 1).then(|| &self.samples[self.start..self.start + self.len])
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+// Convert every sample of every channel
+fn map_channels<T>(channels: Vec<Vec<i32>>, convert: impl Fn(i32) -> T) -> Vec<Vec<T>> {
+    channels
+        .into_iter()
+        .map(|channel| channel.into_iter().map(&convert).collect())
+        .collect()
+}
+
+// Add samples after those of a buffer of a storage order, channel count and length
+fn append<T: Copy>(samples: &mut Vec<T>, other: Vec<T>, shape: (Storage, usize, usize)) {
+    let (storage, num_channels, num_frames) = shape;
+    match storage {
+        Storage::Interleaved => samples.extend(other),
+        Storage::Planar => {
+            let other_frames = other.len() / num_channels;
+            let mut joined = Vec::with_capacity(samples.len() + other.len());
+            for channel in 0..num_channels {
+                joined
+                    .extend_from_slice(&samples[channel * num_frames..(channel + 1) * num_frames]);
+                joined.extend_from_slice(
+                    &other[channel * other_frames..(channel + 1) * other_frames],
+                );
+            }
+            *samples = joined;
+        }
+    }
+}
+
+// Transpose a matrix of rows x cols samples stored row after row
+fn transpose<T: Copy>(samples: &[T], rows: usize, cols: usize) -> Vec<T> {
+    let mut transposed = Vec::with_capacity(samples.len());
+    for col in 0..cols {
+        transposed.extend((0..rows).map(|row| samples[row * cols + col]));
+    }
+    transposed
+}
+
+fn print_samples<T: Debug>(samples: &[T]) {
+    for sample in samples.iter().take(10) {
+        println!("{:?}", sample)
+    }
+}
@@ -0,0 +1,40 @@
+// src/common/position.rs
+
+// This module expresses positions in a stream either as sample indices or as times
+
+use std::time::Duration;
+
+// Define a position in a stream, counted in samples per channel or in time from the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Sample(u64),
+    Time(Duration),
+}
+
+impl Position {
+    /**
+     * Method to get the index of the sample at the position
+     * Times fall on the sample playing at that time, rounding down
+     */
+    pub fn to_sample(self, sample_rate: u32) -> u64 {
+        match self {
+            Position::Sample(sample) => sample,
+            Position::Time(time) => {
+                let sample = time.as_nanos() * sample_rate as u128 / 1_000_000_000;
+                sample.min(u64::MAX as u128) as u64
+            }
+        }
+    }
+}
+
+impl From<u64> for Position {
+    fn from(sample: u64) -> Self {
+        Position::Sample(sample)
+    }
+}
+
+impl From<Duration> for Position {
+    fn from(time: Duration) -> Self {
+        Position::Time(time)
+    }
+}
@@ -0,0 +1,318 @@
+// src/common/resample.rs
+
+// This module changes the sample rate of PCM buffers with a band-limited windowed-sinc filter
+
+use super::{
+    channels::ChannelLayout,
+    errors::AudioError,
+    pcm::{Storage, PCM},
+    sample::SampleFormat,
+};
+
+// Enum to represent the trade-off between the speed and the accuracy of a resampler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleQuality {
+    Fast,   // Short filter with a wide transition band, for previews
+    Medium, // Good enough for most listening
+    Best,   // Long filter keeping the passband flat close to Nyquist, for mastering
+}
+
+impl ResampleQuality {
+    /**
+     * Method to get the filter design of the preset: zero crossings on each
+     * side of the sinc, Kaiser window beta, cutoff as a fraction of the lower
+     * Nyquist frequency, and table entries per zero crossing
+     */
+    fn get_design(self) -> (usize, f64, f64, usize) {
+        match self {
+            ResampleQuality::Fast => (8, 6.0, 0.85, 128),
+            ResampleQuality::Medium => (16, 8.0, 0.92, 256),
+            ResampleQuality::Best => (32, 10.0, 0.95, 512),
+        }
+    }
+}
+
+/**
+ * Streaming sample-rate converter
+ * Blocks of any size go in through process, each returning the output samples
+ * that can already be computed, and flush returns the rest once the input
+ * ends. Together they yield the input duration at the output rate, so
+ * ceil(input samples * output rate / input rate) samples per channel.
+ * Output is aligned with the input, without added delay, and in the format
+ * and storage order of the input
+ */
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    num_channels: usize,
+    step_num: u64, // Input samples per output sample, as the fraction step_num / step_den
+    step_den: u64,
+    scale: f64,             // Filter bandwidth relative to the input Nyquist frequency
+    half_len: usize,        // Zero crossings on each side of the sinc
+    phases: usize,          // Table entries per zero crossing
+    table: Vec<f64>,        // One side of the windowed sinc, from 0 to half_len zero crossings
+    history: Vec<Vec<f64>>, // Input samples of each channel still needed
+    history_start: u64,     // Index of the first sample of history in the input
+    input_len: u64,         // Input samples received per channel
+    output_len: u64,        // Output samples produced per channel
+    format: Option<(SampleFormat, Storage, u8)>, // Format, storage and valid bits of the input blocks
+    layout: ChannelLayout,
+}
+
+impl Resampler {
+    // Constructor method for a resampler of a number of channels between two rates
+    pub fn new(
+        input_rate: u32,
+        output_rate: u32,
+        num_channels: usize,
+        quality: ResampleQuality,
+    ) -> Result<Self, AudioError> {
+        if input_rate == 0 || output_rate == 0 || num_channels == 0 {
+            return Err(AudioError::InvalidData(
+                "Resampling needs nonzero sample rates and channels".to_string(),
+            ));
+        }
+
+        let divisor = gcd(input_rate as u64, output_rate as u64);
+        let (half_len, beta, rolloff, phases) = quality.get_design();
+        Ok(Self {
+            input_rate,
+            output_rate,
+            num_channels,
+            step_num: input_rate as u64 / divisor,
+            step_den: output_rate as u64 / divisor,
+            // Downsampling lowers the cutoff to the output Nyquist frequency
+            scale: (output_rate as f64 / input_rate as f64).min(1.0),
+            half_len,
+            phases,
+            table: build_table(half_len, beta, rolloff, phases),
+            history: vec![Vec::new(); num_channels],
+            history_start: 0,
+            input_len: 0,
+            output_len: 0,
+            format: None,
+            layout: ChannelLayout::new(num_channels),
+        })
+    }
+
+    pub fn get_input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn get_output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    // Method to get the number of input samples per channel the filter reaches on each side
+    pub fn get_filter_span(&self) -> usize {
+        (self.half_len as f64 / self.scale).ceil() as usize
+    }
+
+    // Method to forget the input so far, ready for a new stream
+    pub fn reset(&mut self) {
+        self.history.iter_mut().for_each(Vec::clear);
+        self.history_start = 0;
+        self.input_len = 0;
+        self.output_len = 0;
+        self.format = None;
+    }
+
+    /**
+     * Method to resample the next block of the stream
+     * Returns the output samples whose filter window the input now covers,
+     * which may be none for small blocks
+     */
+    pub fn process(&mut self, pcm: &PCM) -> Result<PCM, AudioError> {
+        if pcm.get_sample_rate() != self.input_rate || pcm.get_num_channels() != self.num_channels {
+            return Err(AudioError::InvalidData(format!(
+                "Resampler expects {} channels at {} Hz, got {} channels at {} Hz",
+                self.num_channels,
+                self.input_rate,
+                pcm.get_num_channels(),
+                pcm.get_sample_rate()
+            )));
+        }
+        self.format = Some((pcm.get_format(), pcm.get_storage(), pcm.get_valid_bits()));
+        self.layout = pcm.get_layout().clone();
+
+        // Equal rates need no filtering
+        if self.step_num == self.step_den {
+            self.input_len += pcm.get_num_frames() as u64;
+            self.output_len = self.input_len;
+            self.history_start = self.input_len;
+            return Ok(pcm.clone());
+        }
+
+        let planar = pcm.to_format(SampleFormat::F64)?.into_planar();
+        let samples = planar.samples::<f64>().unwrap_or_default();
+        let num_frames = pcm.get_num_frames();
+        for (history, channel) in self
+            .history
+            .iter_mut()
+            .zip(samples.chunks(num_frames.max(1)))
+        {
+            history.extend_from_slice(channel);
+        }
+        self.input_len += num_frames as u64;
+
+        // An output sample is ready once the input reaches past the end of its window
+        let span = self.get_filter_span() as u64;
+        let end = if self.input_len > span {
+            self.outputs_before(self.input_len - span)
+        } else {
+            0
+        };
+        self.produce(end)
+    }
+
+    /**
+     * Method to end the stream, returning the output samples left, computed
+     * as if silence followed the input
+     */
+    pub fn flush(&mut self) -> Result<PCM, AudioError> {
+        let end = self.outputs_before(self.input_len);
+        let output = self.produce(end);
+        self.reset();
+        output
+    }
+
+    // Number of output samples falling before an input position, which is ceil(position / step)
+    fn outputs_before(&self, position: u64) -> u64 {
+        (position as u128 * self.step_den as u128).div_ceil(self.step_num as u128) as u64
+    }
+
+    // Compute the output samples up to but not including end, then drop the input no longer needed
+    fn produce(&mut self, end: u64) -> Result<PCM, AudioError> {
+        let (format, storage, valid_bits) = self.format.unwrap_or((
+            SampleFormat::F64,
+            Storage::Planar,
+            SampleFormat::F64.get_bits(),
+        ));
+        let start = self.output_len.min(end);
+        let num_outputs = (end - start) as usize;
+        let mut outputs = vec![0.0; num_outputs * self.num_channels];
+
+        let span = self.get_filter_span() as i64;
+        for n in start..end {
+            // Input position of the output sample, as a whole and a fractional part
+            let position = n as u128 * self.step_num as u128;
+            let whole = (position / self.step_den as u128) as i64;
+            let fraction = (position % self.step_den as u128) as f64 / self.step_den as f64;
+
+            // Samples further than the span fall outside the window
+            let first = (whole - span + 1).max(self.history_start as i64);
+            let last = (whole + span).min(self.input_len as i64 - 1);
+            for j in first..=last {
+                let weight = self.kernel((whole - j) as f64 + fraction);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = (j as u64 - self.history_start) as usize;
+                for (channel, history) in self.history.iter().enumerate() {
+                    outputs[channel * num_outputs + (n - start) as usize] +=
+                        history[index] * weight;
+                }
+            }
+        }
+        self.output_len = end;
+
+        // Keep the input the window of the next output sample starts at
+        let next = (end as u128 * self.step_num as u128 / self.step_den as u128) as i64;
+        let keep_from = ((next - span).max(0) as u64).clamp(self.history_start, self.input_len);
+        let drop = (keep_from - self.history_start) as usize;
+        for history in &mut self.history {
+            history.drain(..drop);
+        }
+        self.history_start = keep_from;
+
+        let pcm = PCM::new(
+            outputs,
+            self.layout.clone(),
+            self.output_rate,
+            Storage::Planar,
+        )?;
+        Ok(pcm
+            .to_format(format)?
+            .into_storage(storage)
+            .with_valid_bits(valid_bits))
+    }
+
+    // Weight of an input sample at a distance from the output position, counted in input samples
+    fn kernel(&self, distance: f64) -> f64 {
+        let position = distance.abs() * self.scale * self.phases as f64;
+        let index = position as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let fraction = position - index as f64;
+        let value = self.table[index] + (self.table[index + 1] - self.table[index]) * fraction;
+        value * self.scale
+    }
+}
+
+impl PCM {
+    // Method to get a copy of the buffer at another sample rate
+    pub fn resample(&self, output_rate: u32, quality: ResampleQuality) -> Result<PCM, AudioError> {
+        if output_rate == self.get_sample_rate() {
+            return Ok(self.clone());
+        }
+        let mut resampler = Resampler::new(
+            self.get_sample_rate(),
+            output_rate,
+            self.get_num_channels(),
+            quality,
+        )?;
+        let mut output = resampler.process(self)?;
+        output.append(&resampler.flush()?)?;
+        Ok(output)
+    }
+}
+
+// Sample one side of a Kaiser-windowed sinc, with a spare zero entry for interpolation
+fn build_table(half_len: usize, beta: f64, rolloff: f64, phases: usize) -> Vec<f64> {
+    let len = half_len * phases;
+    let window_scale = bessel_i0(beta);
+    let mut table: Vec<f64> = (0..len)
+        .map(|i| {
+            let x = i as f64 / phases as f64;
+            let ratio = x / half_len as f64;
+            let window = bessel_i0(beta * (1.0 - ratio * ratio).sqrt()) / window_scale;
+            rolloff * sinc(rolloff * x) * window
+        })
+        .collect();
+    table.extend([0.0, 0.0]);
+    table
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+// Zeroth order modified Bessel function of the first kind, from its power series
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..64 {
+        term *= half / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-17 {
+            break;
+        }
+    }
+    sum
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
@@ -0,0 +1,173 @@
+// src/common/sample.rs
+
+// This module defines the sample formats a PCM buffer can hold
+
+use std::fmt::Debug;
+
+// Enum to represent the format of each sample in a PCM buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    I8,
+    I16,
+    I24,
+    I32,
+    F32, // Full scale is -1.0 to 1.0
+    F64, // Full scale is -1.0 to 1.0
+}
+
+impl SampleFormat {
+    // Method to get the number of significant bits in a sample
+    pub fn get_bits(self) -> u8 {
+        match self {
+            SampleFormat::I8 => 8,
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, SampleFormat::F32 | SampleFormat::F64)
+    }
+
+    /**
+     * Method to get the smallest integer format holding samples of a bit depth
+     * Returns None for bit depths of 0 or above 32
+     */
+    pub fn for_bit_depth(bit_depth: u8) -> Option<Self> {
+        match bit_depth {
+            1..=8 => Some(SampleFormat::I8),
+            9..=16 => Some(SampleFormat::I16),
+            17..=24 => Some(SampleFormat::I24),
+            25..=32 => Some(SampleFormat::I32),
+            _ => None,
+        }
+    }
+}
+
+// A signed 24-bit sample, held in the low bits of an i32
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct I24(i32);
+
+impl I24 {
+    pub const MIN: I24 = I24(-(1 << 23));
+    pub const MAX: I24 = I24((1 << 23) - 1);
+
+    // Constructor method for a value in range, or None if it does not fit in 24 bits
+    pub fn new(value: i32) -> Option<Self> {
+        (Self::MIN.0..=Self::MAX.0)
+            .contains(&value)
+            .then_some(Self(value))
+    }
+
+    // Constructor method clamping the value to the 24-bit range
+    pub fn saturating(value: i32) -> Self {
+        Self(value.clamp(Self::MIN.0, Self::MAX.0))
+    }
+
+    pub fn get(self) -> i32 {
+        self.0
+    }
+}
+
+/**
+ * Samples of one format, as stored in a PCM buffer
+ * The order of the samples, planar or interleaved, is kept by the buffer
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum SampleData {
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I24(Vec<I24>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+impl SampleData {
+    pub fn get_format(&self) -> SampleFormat {
+        match self {
+            SampleData::I8(_) => SampleFormat::I8,
+            SampleData::I16(_) => SampleFormat::I16,
+            SampleData::I24(_) => SampleFormat::I24,
+            SampleData::I32(_) => SampleFormat::I32,
+            SampleData::F32(_) => SampleFormat::F32,
+            SampleData::F64(_) => SampleFormat::F64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            SampleData::I8(samples) => samples.len(),
+            SampleData::I16(samples) => samples.len(),
+            SampleData::I24(samples) => samples.len(),
+            SampleData::I32(samples) => samples.len(),
+            SampleData::F32(samples) => samples.len(),
+            SampleData::F64(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/**
+ * A type samples can be held as, tying each Rust type to its SampleFormat
+ * so buffers can hand out typed slices of their samples
+ */
+pub trait Sample: Copy + Default + Debug + PartialEq + Send + Sync + 'static {
+    const FORMAT: SampleFormat;
+
+    // Method to borrow the samples if they are of this type
+    fn slice(data: &SampleData) -> Option<&[Self]>;
+
+    fn slice_mut(data: &mut SampleData) -> Option<&mut [Self]>;
+
+    // Method to take the samples if they are of this type
+    fn into_vec(data: SampleData) -> Result<Vec<Self>, SampleData>;
+
+    fn into_data(samples: Vec<Self>) -> SampleData;
+}
+
+// Implement Sample for a type held by one variant of SampleData
+macro_rules! impl_sample {
+    ($type:ty, $variant:ident) => {
+        impl Sample for $type {
+            const FORMAT: SampleFormat = SampleFormat::$variant;
+
+            fn slice(data: &SampleData) -> Option<&[Self]> {
+                match data {
+                    SampleData::$variant(samples) => Some(samples),
+                    _ => None,
+                }
+            }
+
+            fn slice_mut(data: &mut SampleData) -> Option<&mut [Self]> {
+                match data {
+                    SampleData::$variant(samples) => Some(samples),
+                    _ => None,
+                }
+            }
+
+            fn into_vec(data: SampleData) -> Result<Vec<Self>, SampleData> {
+                match data {
+                    SampleData::$variant(samples) => Ok(samples),
+                    data => Err(data),
+                }
+            }
+
+            fn into_data(samples: Vec<Self>) -> SampleData {
+                SampleData::$variant(samples)
+            }
+        }
+    };
+}
+
+impl_sample!(i8, I8);
+impl_sample!(i16, I16);
+impl_sample!(I24, I24);
+impl_sample!(i32, I32);
+impl_sample!(f32, F32);
+impl_sample!(f64, F64);
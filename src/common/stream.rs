@@ -1,17 +1,69 @@
 // src/common/stream.rs
 use std::{
     fs::File,
-    io::{self, BufReader, Read, Seek, SeekFrom},
-    path::Path,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use byteorder::{BigEndian, ReadBytesExt};
 
-use super::errors::AudioError;
+use super::{
+    crc::{crc16_update, crc8_update},
+    errors::AudioError,
+};
+
+// Define the sources a Stream can read from: a file, or bytes already in memory
+pub enum Source {
+    File(BufReader<File>),
+    Memory(Cursor<Arc<[u8]>>),
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::File(reader) => reader.read(buf),
+            Source::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for Source {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Source::File(reader) => reader.seek(pos),
+            Source::Memory(cursor) => cursor.seek(pos),
+        }
+    }
 
-// Define a Stream struct which holds a BufReader to a File
+    // Report the position without seeking, which would throw away the buffered bytes of a file
+    fn stream_position(&mut self) -> io::Result<u64> {
+        match self {
+            Source::File(reader) => reader.stream_position(),
+            Source::Memory(cursor) => cursor.stream_position(),
+        }
+    }
+}
+
+impl Source {
+    // Get the bytes already buffered ahead of the position, filling the buffer if it is empty
+    fn buffered(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Source::File(reader) => reader.fill_buf(),
+            Source::Memory(cursor) => cursor.fill_buf(),
+        }
+    }
+}
+
+// Define a Stream struct which holds a Source to read bits and bytes from
 pub struct Stream {
-    reader: BufReader<File>,
+    path: Option<PathBuf>, // The file the stream reads from, if any
+    reader: Source,
+    bit_pos: u8,     // Number of unread bits left in the cache
+    cache: u8,       // The byte currently being read bit by bit
+    crc8: u8,        // Running CRC-8 of the bytes read since the last reset
+    crc16: u16,      // Running CRC-16 of the bytes read since the last reset
+    bytes_read: u64, // Bytes read through read_byte, bit by bit or whole
 }
 
 impl Stream {
@@ -20,13 +72,50 @@ impl Stream {
      * Result that may contain a Stream or an AudioError
      */
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, AudioError> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        Ok(Self { reader })
+        let file = File::open(&path)?;
+        let reader = Source::File(BufReader::new(file));
+        Ok(Self::with_source(Some(path.as_ref().to_path_buf()), reader))
     }
 
-    // Method to access the mutable reference to the BufReader
-    pub fn reader(&mut self) -> &mut BufReader<File> {
+    // Constructor method to create a Stream reading bytes already in memory
+    pub fn from_bytes<B: Into<Arc<[u8]>>>(bytes: B) -> Self {
+        Self::with_source(None, Source::Memory(Cursor::new(bytes.into())))
+    }
+
+    fn with_source(path: Option<PathBuf>, reader: Source) -> Self {
+        Self {
+            path,
+            reader,
+            bit_pos: 0,
+            cache: 0,
+            crc8: 0,
+            crc16: 0,
+            bytes_read: 0,
+        }
+    }
+
+    /**
+     * Method to open another Stream on the same file or bytes, positioned at
+     * its start
+     * The new Stream has its own position, so both can be read independently
+     */
+    pub fn reopen(&self) -> Result<Self, AudioError> {
+        match (&self.reader, &self.path) {
+            (Source::Memory(cursor), _) => Ok(Self::from_bytes(cursor.get_ref().clone())),
+            (Source::File(_), Some(path)) => Self::new(path),
+            (Source::File(_), None) => Err(AudioError::InvalidData(
+                "Cannot reopen a file stream without its path".to_string(),
+            )),
+        }
+    }
+
+    // Method to get the path of the file the stream reads from, if it reads from a file
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Method to access the mutable reference to the Source
+    pub fn reader(&mut self) -> &mut Source {
         &mut self.reader
     }
 
@@ -46,16 +135,17 @@ impl Stream {
 
     // Method to read a single bit from the stream
     pub fn read_bit(&mut self) -> Result<u32, AudioError> {
-        static mut BIT_POS: u8 = 0;
-        static mut CACHE: u8 = 0;
-        unsafe {
-            if BIT_POS == 0 {
-                CACHE = self.read_byte()?;
-                BIT_POS = 8;
-            }
-            BIT_POS -= 1;
-            Ok(((CACHE >> BIT_POS) & 1) as u32)
+        if self.bit_pos == 0 {
+            self.cache = self.read_byte()?;
+            self.bit_pos = 8;
         }
+        self.bit_pos -= 1;
+        Ok(((self.cache >> self.bit_pos) & 1) as u32)
+    }
+
+    // Method to discard the unread bits of a partially read byte
+    pub fn align_to_byte(&mut self) {
+        self.bit_pos = 0;
     }
 
     // Method to read a specified number of bits from the stream and interpret them as a signed integer
@@ -64,80 +154,151 @@ impl Stream {
             return Err(AudioError::ExceededBitLimit);
         }
 
-        let mut value = self.read_bits(num)? as i32; // Read the bits as an unsigned integer
+        Ok(self.read_signed_bits_64(num)? as i32)
+    }
 
-        // If the number is negative (most significant bit is 1), adjust the value
-        if value & (1 << (num - 1)) != 0 {
-            value -= 1 << num; // Subtract 2^num to get the correct negative value
+    /**
+     * Method to read up to 64 bits from the stream and interpret them as a
+     * signed integer, for values wider than 32 bits such as the side channel
+     * of a 32-bit stream
+     */
+    pub fn read_signed_bits_64(&mut self, num: u8) -> Result<i64, AudioError> {
+        if num > 64 {
+            return Err(AudioError::ExceededBitLimit);
         }
-        Ok(value)
+        if num == 0 {
+            return Ok(0);
+        }
+
+        // Read the bits as an unsigned integer, at most 32 bits at a time
+        let high_bits = num.saturating_sub(32);
+        let low_bits = num - high_bits;
+        let value =
+            ((self.read_bits(high_bits)? as u64) << low_bits) | self.read_bits(low_bits)? as u64;
+
+        // Move the sign bit to the top and shift back to sign extend the value
+        let unused_bits = 64 - num as u32;
+        Ok(((value << unused_bits) as i64) >> unused_bits)
     }
 
     /**
      * Metod to read one byte from the stream
+     * Every byte read this way, including those read bit by bit, is added
+     * to the running CRCs
      * Returns a Result that may contain a u8 or an AudioError
      */
     pub fn read_byte(&mut self) -> Result<u8, AudioError> {
         let mut buffer = [0; 1];
-        self.reader.read_exact(&mut buffer).map_err(|err| {
-            if err.kind() == io::ErrorKind::UnexpectedEof {
-                AudioError::EOF
-            } else {
-                AudioError::from(err)
-            }
-        })?;
+        self.reader.read_exact(&mut buffer).map_err(Self::map_eof)?;
+        self.crc8 = crc8_update(self.crc8, buffer[0]);
+        self.crc16 = crc16_update(self.crc16, buffer[0]);
+        self.bytes_read += 1;
         Ok(buffer[0])
     }
 
+    /**
+     * Method to count the bits read so far with read_bit, read_bits and
+     * read_byte, which is how frames are read
+     * The difference between two counts is the size of what was read between them
+     */
+    pub fn get_bits_read(&self) -> u64 {
+        self.bytes_read * 8 - self.bit_pos as u64
+    }
+
+    // Method to restart the running CRCs, typically at the start of a frame
+    pub fn reset_crc(&mut self) {
+        self.crc8 = 0;
+        self.crc16 = 0;
+    }
+
+    // Method to get the CRC-8 of the bytes read since the last reset
+    pub fn get_crc8(&self) -> u8 {
+        self.crc8
+    }
+
+    // Method to get the CRC-16 of the bytes read since the last reset
+    pub fn get_crc16(&self) -> u16 {
+        self.crc16
+    }
+
     /**
      * Method to read a specified number of bytes from the stream
      * Returns a Result that may contain a Vec<u8> or an AudioError
      */
     pub fn read_bytes(&mut self, num: usize) -> Result<Vec<u8>, AudioError> {
         let mut buffer = vec![0; num];
-        self.reader.read_exact(&mut buffer).map_err(|err| {
-            if err.kind() == io::ErrorKind::UnexpectedEof {
-                AudioError::EOF
-            } else {
-                AudioError::from(err)
-            }
-        })?;
+        self.reader.read_exact(&mut buffer).map_err(Self::map_eof)?;
         Ok(buffer)
     }
 
     // Method to read a 16-bit unsigned integer from the stream
     pub fn read_u16(&mut self) -> Result<u16, AudioError> {
-        self.reader
-            .read_u16::<BigEndian>()
-            .map_err(AudioError::from)
+        self.reader.read_u16::<BigEndian>().map_err(Self::map_eof)
     }
 
     // Method to read a 32-bit unsigned integer from the stream
     pub fn read_u32(&mut self) -> Result<u32, AudioError> {
-        self.reader
-            .read_u32::<BigEndian>()
-            .map_err(AudioError::from)
+        self.reader.read_u32::<BigEndian>().map_err(Self::map_eof)
     }
 
     // Method to read a 64-bit unsigned integer from the stream
     pub fn read_u64(&mut self) -> Result<u64, AudioError> {
-        self.reader
-            .read_u64::<BigEndian>()
-            .map_err(AudioError::from)
+        self.reader.read_u64::<BigEndian>().map_err(Self::map_eof)
     }
 
     pub fn peek_u16(&mut self) -> Result<u16, AudioError> {
-        let original_position = self.reader.stream_position()?;
-        let result = self.read_u16();
-        self.reader.seek(SeekFrom::Start(original_position))?;
-        result
+        self.peek_bytes().map(u16::from_be_bytes)
     }
 
     pub fn peek_u32(&mut self) -> Result<u32, AudioError> {
+        self.peek_bytes().map(u32::from_be_bytes)
+    }
+
+    /**
+     * Look at the next bytes without consuming them
+     * They are usually taken from the bytes already buffered, and only when
+     * they straddle the end of the buffer are they read and seeked back over
+     */
+    fn peek_bytes<const N: usize>(&mut self) -> Result<[u8; N], AudioError> {
+        let mut bytes = [0; N];
+        if let Some(buffered) = self.reader.buffered()?.get(..N) {
+            bytes.copy_from_slice(buffered);
+            return Ok(bytes);
+        }
+
         let original_position = self.reader.stream_position()?;
-        let result = self.read_u32();
+        let result = self.reader.read_exact(&mut bytes).map_err(Self::map_eof);
         self.reader.seek(SeekFrom::Start(original_position))?;
-        result
+        result.map(|_| bytes)
+    }
+
+    // Method to get the byte offset of the next unread byte in the stream
+    pub fn get_position(&mut self) -> Result<u64, AudioError> {
+        self.reader.stream_position().map_err(AudioError::from)
+    }
+
+    // Method to get the length of the whole stream in bytes
+    pub fn get_len(&mut self) -> Result<u64, AudioError> {
+        match &self.reader {
+            Source::File(reader) => Ok(reader.get_ref().metadata()?.len()),
+            Source::Memory(cursor) => Ok(cursor.get_ref().len() as u64),
+        }
+    }
+
+    // Method to move to a byte offset in the stream, discarding any partially read byte
+    pub fn seek_to(&mut self, position: u64) -> Result<(), AudioError> {
+        self.bit_pos = 0;
+        self.reader.seek(SeekFrom::Start(position))?;
+        Ok(())
+    }
+
+    // Map running out of input to AudioError::EOF so callers can detect the end of the stream
+    fn map_eof(err: io::Error) -> AudioError {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            AudioError::EOF
+        } else {
+            AudioError::from(err)
+        }
     }
 
     // Method to skip a certain number of bytes in the stream
@@ -0,0 +1,5 @@
+// src/lib.rs
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod codecs;
+pub mod common;
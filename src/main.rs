@@ -1,30 +1,137 @@
 // src/main.rs
-mod codecs;
-mod common;
-
-use common::{errors::AudioError, pcm::PCM, stream::Stream};
+use playback::codecs::{
+    flac::{
+        decoder::{Md5Status, RecoveryMode},
+        flac::FLAC,
+    },
+    registry::Registry,
+};
+use playback::common::{errors::AudioError, position::Position, stream::Stream};
+use std::{io::Read, time::Duration};
 
 fn main() -> Result<(), AudioError> {
     // Take the path from the command line
-    let args: Vec<String> = std::env::args().collect();
-    // Check if the filename was provided
-    if args.len() != 2 {
-        eprintln!("Usage: cargo run <filename>");
-        std::process::exit(1);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [mode, path] if mode == "verify" => return verify(path),
+        [mode, path] if mode == "analyze" => return analyze(path, false),
+        [mode, option, path] if mode == "analyze" && option == "--json" => {
+            return analyze(path, true)
+        }
+        _ => {}
+    }
+
+    // Check if the filename was provided, optionally followed by the range to play
+    let mut path = None;
+    let mut start = None;
+    let mut end = None;
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--start" => start = Some(parse_time(options.next())),
+            "--end" => end = Some(parse_time(options.next())),
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
     }
-    let path = &args[1];
+    let path = path.unwrap_or_else(|| usage());
 
     // Create a Stream with the file at the provided path
     let mut stream = Stream::new(path)?;
 
     /*
      *  Determine the file type and dispatch to the appropriate decoder,
-     *  and return a PCM
+     *  describe the audio format and decode it into a PCM
      */
-    let pcm = PCM::from_stream(&mut stream)?;
+    let mut codec = Registry::default().open(&mut stream)?;
+    println!("{}", codec.describe());
+    let pcm = if start.is_some() || end.is_some() {
+        codec.decode_range(
+            &mut stream,
+            start.unwrap_or(Position::Sample(0)),
+            end.unwrap_or(Position::Sample(u64::MAX)),
+        )?
+    } else {
+        codec.decode(&mut stream)?
+    };
 
     // Play the PCM data
     pcm.play()?;
 
     Ok(())
 }
+
+fn usage() -> ! {
+    eprintln!("Usage: cargo run [verify] <filename> [--start <time>] [--end <time>]");
+    eprintln!("       cargo run analyze [--json] <filename>");
+    eprintln!("Times are in seconds, optionally as [hours:]minutes:seconds");
+    std::process::exit(1);
+}
+
+// Parse a time given in seconds, as minutes:seconds or as hours:minutes:seconds
+fn parse_time(arg: Option<&String>) -> Position {
+    let arg = arg.unwrap_or_else(|| usage());
+    let mut seconds = 0.0;
+    for (i, part) in arg.rsplit(':').enumerate() {
+        let value = match part.parse::<f64>() {
+            Ok(value) if i < 3 && value >= 0.0 && (i == 0 || value.fract() == 0.0) => value,
+            _ => usage(),
+        };
+        seconds += value * 60f64.powi(i as i32);
+    }
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(time) => Position::Time(time),
+        Err(_) => usage(),
+    }
+}
+
+// Open a FLAC file, after checking its stream marker
+fn open_flac(path: &str) -> Result<(Stream, FLAC), AudioError> {
+    let mut stream = Stream::new(path)?;
+
+    let mut buffer = [0; 4];
+    stream.reader().read_exact(&mut buffer)?;
+    if &buffer != b"fLaC" {
+        return Err(AudioError::UnsupportedFileCodec);
+    }
+
+    let flac = FLAC::new(&mut stream)?;
+    Ok((stream, flac))
+}
+
+/**
+ * Decode the whole file and check it against the MD5 signature in STREAMINFO
+ * Exits with a non-zero status if the decoded audio does not match
+ */
+fn verify(path: &str) -> Result<(), AudioError> {
+    let (mut stream, mut flac) = open_flac(path)?;
+
+    // Decode the frames on every available core
+    flac.set_num_threads(std::thread::available_parallelism().map_or(1, |n| n.get()));
+    match flac.verify(&mut stream)? {
+        Md5Status::Match => println!("{}: ok", path),
+        Md5Status::Unset => println!("{}: ok (no MD5 signature to check)", path),
+        Md5Status::Mismatch => {
+            println!("{}: MD5 signature mismatch", path);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Print how every frame and subframe of the file was coded, as text or JSON
+ * Damaged frames are listed in the report rather than ending the analysis
+ */
+fn analyze(path: &str, json: bool) -> Result<(), AudioError> {
+    let (mut stream, mut flac) = open_flac(path)?;
+    flac.set_recovery_mode(RecoveryMode::Skip);
+    let analysis = flac.analyze(&mut stream)?;
+    if json {
+        println!("{}", analysis.to_json());
+    } else {
+        print!("{}", analysis.to_text());
+    }
+    Ok(())
+}
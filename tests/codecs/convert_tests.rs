@@ -0,0 +1,260 @@
+// tests/codecs/convert_tests.rs
+
+// Tests converting PCM buffers between sample formats
+
+use crate::common::corpus_file;
+use playback::common::{
+    channels::ChannelLayout,
+    convert::{Converter, Dither, NoiseShaping},
+    pcm::{Storage, PCM},
+    resample::ResampleQuality,
+    sample::{SampleFormat, I24},
+    stream::Stream,
+};
+use std::f64::consts::PI;
+
+fn mono<T: playback::common::sample::Sample>(samples: Vec<T>) -> PCM {
+    PCM::new(samples, ChannelLayout::mono(), 44100, Storage::Planar).unwrap()
+}
+
+#[test]
+fn float_to_integer_rounds_and_clips() {
+    let pcm = mono(vec![
+        0.0f32,
+        1.0,
+        -1.0,
+        0.5,
+        2.0,
+        -3.0,
+        f32::NAN,
+        1.5 / 32768.0,
+        -1.5 / 32768.0,
+    ]);
+    let converted = pcm.to_format(SampleFormat::I16).unwrap();
+    assert_eq!(
+        converted.samples::<i16>().unwrap(),
+        &[0, 32767, -32768, 16384, 32767, -32768, 0, 2, -2]
+    );
+
+    let converted = pcm.to_format(SampleFormat::I24).unwrap();
+    assert_eq!(converted.samples::<I24>().unwrap()[1], I24::MAX);
+    assert_eq!(converted.samples::<I24>().unwrap()[2], I24::MIN);
+}
+
+#[test]
+fn integer_conversions_keep_full_scale() {
+    let pcm = mono(vec![i16::MIN, -1, 0, 1, 12345, i16::MAX]);
+
+    // Widening is exact and survives the round trip
+    for format in [
+        SampleFormat::I24,
+        SampleFormat::I32,
+        SampleFormat::F32,
+        SampleFormat::F64,
+    ] {
+        let widened = pcm.to_format(format).unwrap();
+        assert_eq!(widened.get_format(), format);
+        assert_eq!(widened.to_format(SampleFormat::I16).unwrap(), pcm);
+    }
+    let widened = pcm.to_format(SampleFormat::I24).unwrap();
+    let expected: Vec<I24> = [i16::MIN, -1, 0, 1, 12345, i16::MAX]
+        .iter()
+        .map(|&x| I24::new((x as i32) << 8).unwrap())
+        .collect();
+    assert_eq!(widened.samples::<I24>().unwrap(), &expected[..]);
+    assert_eq!(
+        pcm.to_format(SampleFormat::F32)
+            .unwrap()
+            .samples::<f32>()
+            .unwrap()[0],
+        -1.0
+    );
+
+    // Narrowing rounds halves away from zero
+    let pcm = mono(vec![127i16 << 8 | 0x7F, 128, -128, 383, -32768]);
+    assert_eq!(
+        pcm.to_format(SampleFormat::I8)
+            .unwrap()
+            .samples::<i8>()
+            .unwrap(),
+        &[127, 1, -1, 1, -128]
+    );
+}
+
+#[test]
+fn dither_stays_within_its_amplitude() {
+    let values: Vec<i32> = (0..4096).map(|i| (i * 7919) % 65536 - 32768).collect();
+    let pcm = mono(
+        values
+            .iter()
+            .map(|&x| I24::new(x * 97).unwrap())
+            .collect::<Vec<_>>(),
+    );
+
+    for (dither, bound) in [
+        (Dither::None, 0.5),
+        (Dither::Rectangular, 1.0),
+        (Dither::Triangular, 1.5),
+    ] {
+        let mut converter = Converter::new(SampleFormat::I16);
+        converter.set_dither(dither);
+        let converted = pcm.convert(&mut converter).unwrap();
+
+        let mut total_error = 0.0;
+        for (&x, &y) in values.iter().zip(converted.samples::<i16>().unwrap()) {
+            let error = y as f64 - (x * 97) as f64 / 256.0;
+            assert!(error.abs() <= bound, "{:?} error {}", dither, error);
+            total_error += error;
+        }
+        assert!(
+            (total_error / values.len() as f64).abs() < 0.05,
+            "{:?}",
+            dither
+        );
+    }
+}
+
+#[test]
+fn dither_is_reproducible_with_a_seed() {
+    let pcm = mono(
+        (0..1000)
+            .map(|i| (i as f64 / 100.0).sin() * 0.3)
+            .collect::<Vec<f64>>(),
+    );
+    let convert = |seed| {
+        let mut converter = Converter::new(SampleFormat::I8);
+        converter.set_dither(Dither::Triangular);
+        converter.set_seed(seed);
+        pcm.convert(&mut converter).unwrap()
+    };
+    assert_eq!(convert(1), convert(1));
+    assert_ne!(convert(1), convert(2));
+}
+
+// Power of the quantization error between 1 and 4 kHz, where hearing is most sensitive
+fn midband_noise(noise_shaping: NoiseShaping) -> f64 {
+    let input: Vec<f64> = (0..8192)
+        .map(|i| (2.0 * PI * 440.0 * i as f64 / 44100.0).sin() * 0.01)
+        .collect();
+    let mut converter = Converter::new(SampleFormat::I8);
+    converter.set_dither(Dither::Triangular);
+    converter.set_noise_shaping(noise_shaping);
+
+    // Convert in blocks, as a streaming decoder would
+    let mut errors = Vec::new();
+    for block in input.chunks(1000) {
+        let converted = mono(block.to_vec()).convert(&mut converter).unwrap();
+        let output = converted.samples::<i8>().unwrap();
+        errors.extend(
+            block
+                .iter()
+                .zip(output)
+                .map(|(&x, &y)| y as f64 - x * 128.0),
+        );
+    }
+
+    // Sum the DFT bins of the band
+    (1000..4000)
+        .step_by(50)
+        .map(|frequency| {
+            let omega = 2.0 * PI * frequency as f64 / 44100.0;
+            let (re, im) = errors
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, e)| {
+                    (
+                        re + e * (omega * n as f64).cos(),
+                        im - e * (omega * n as f64).sin(),
+                    )
+                });
+            re * re + im * im
+        })
+        .sum()
+}
+
+#[test]
+fn noise_shaping_moves_noise_out_of_the_midband() {
+    let flat = midband_noise(NoiseShaping::None);
+    assert!(midband_noise(NoiseShaping::FirstOrder) < flat / 2.0);
+    assert!(midband_noise(NoiseShaping::Lipshitz) < flat / 4.0);
+}
+
+#[test]
+fn integer_conversions_keep_the_valid_bits() {
+    let pcm = PCM::from_integer_channels(vec![vec![-2048, 0, 2047]], 12, 8000).unwrap();
+    assert_eq!(
+        pcm.to_format(SampleFormat::I32).unwrap().get_valid_bits(),
+        12
+    );
+    assert_eq!(pcm.to_format(SampleFormat::I8).unwrap().get_valid_bits(), 8);
+
+    // Float samples converted from integers keep the bit depth, other float samples have none
+    let float = pcm.to_format(SampleFormat::F32).unwrap();
+    assert_eq!(float.get_valid_bits(), 12);
+    assert_eq!(
+        float.to_format(SampleFormat::I16).unwrap().get_valid_bits(),
+        12
+    );
+    assert_eq!(
+        mono(vec![0.5f32])
+            .to_format(SampleFormat::I16)
+            .unwrap()
+            .get_valid_bits(),
+        16
+    );
+}
+
+#[test]
+fn float_round_trips_keep_the_valid_bits() {
+    // 20-bit samples, held in I24
+    let channels = vec![
+        (0..64).map(|i| (i * 8191) % 1048576 - 524288).collect(),
+        (0..64).map(|i| 524287 - (i * 4099) % 1048576).collect(),
+    ];
+    let pcm = PCM::from_integer_channels(channels, 20, 48000).unwrap();
+    assert_eq!(pcm.get_format(), SampleFormat::I24);
+    assert_eq!(pcm.get_valid_bits(), 20);
+
+    let round_trip = pcm
+        .to_format(SampleFormat::F64)
+        .unwrap()
+        .to_format(SampleFormat::I24)
+        .unwrap();
+    assert_eq!(round_trip.get_valid_bits(), 20);
+    assert_eq!(round_trip.get_data(), pcm.get_data());
+
+    let resampled = pcm.resample(44100, ResampleQuality::Fast).unwrap();
+    assert_eq!(resampled.get_format(), SampleFormat::I24);
+    assert_eq!(resampled.get_valid_bits(), 20);
+
+    let remixed = pcm.remix(&ChannelLayout::mono()).unwrap();
+    assert_eq!(remixed.get_format(), SampleFormat::I24);
+    assert_eq!(remixed.get_valid_bits(), 20);
+}
+
+#[test]
+fn converts_decoded_24_bit_audio_to_16_bit() {
+    let pcm =
+        PCM::from_stream(&mut Stream::new(corpus_file("bit_depth_24.flac")).unwrap()).unwrap();
+    assert_eq!(pcm.get_format(), SampleFormat::I24);
+
+    let mut converter = Converter::new(SampleFormat::I16);
+    converter.set_dither(Dither::Triangular);
+    converter.set_noise_shaping(NoiseShaping::Lipshitz);
+    let converted = pcm
+        .to_storage(Storage::Interleaved)
+        .convert(&mut converter)
+        .unwrap();
+    assert_eq!(converted.get_storage(), Storage::Interleaved);
+    assert_eq!(converted.get_num_channels(), pcm.get_num_channels());
+    assert_eq!(converted.get_num_frames(), pcm.get_num_frames());
+    assert_eq!(converted.get_sample_rate(), pcm.get_sample_rate());
+
+    for channel in 0..pcm.get_num_channels() {
+        let source = pcm.channel::<I24>(channel).unwrap();
+        let target = converted.channel::<i16>(channel).unwrap();
+        for (x, y) in source.iter().zip(target.iter()) {
+            assert!((x.get() as f64 / 256.0 - y as f64).abs() < 16.0);
+        }
+    }
+}
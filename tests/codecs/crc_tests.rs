@@ -0,0 +1,43 @@
+// tests/codecs/crc_tests.rs
+
+// Tests the CRCs protecting FLAC frames against published check values
+
+use playback::common::crc::{crc16_update, crc8_update};
+
+const CHECK_INPUT: &[u8] = b"123456789";
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| crc8_update(crc, byte))
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| crc16_update(crc, byte))
+}
+
+#[test]
+fn crc8_matches_check_value() {
+    // CRC-8 with polynomial 0x07, no reflection and a zero initial value
+    assert_eq!(crc8(CHECK_INPUT), 0xF4);
+    assert_eq!(crc8(&[]), 0);
+    assert_eq!(crc8(&[0x01]), 0x07);
+}
+
+#[test]
+fn crc16_matches_check_value() {
+    // CRC-16/UMTS: polynomial 0x8005, no reflection and a zero initial value
+    assert_eq!(crc16(CHECK_INPUT), 0xFEE8);
+    assert_eq!(crc16(&[]), 0);
+    assert_eq!(crc16(&[0x01]), 0x8005);
+}
+
+#[test]
+fn appended_crc_leaves_zero_remainder() {
+    // Running a CRC over data followed by its own big-endian CRC gives zero
+    let mut bytes = CHECK_INPUT.to_vec();
+    bytes.push(crc8(CHECK_INPUT));
+    assert_eq!(crc8(&bytes), 0);
+
+    let mut bytes = CHECK_INPUT.to_vec();
+    bytes.extend(crc16(CHECK_INPUT).to_be_bytes());
+    assert_eq!(crc16(&bytes), 0);
+}
@@ -0,0 +1,59 @@
+// tests/codecs/md5_tests.rs
+
+// Tests the MD5 digest used to verify decoded audio against the RFC 1321 test suite
+
+use playback::common::md5::Md5;
+
+fn to_hex(digest: [u8; 16]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    let mut md5 = Md5::new();
+    md5.update(bytes);
+    to_hex(md5.finalize())
+}
+
+#[test]
+fn digests_match_rfc_1321_test_suite() {
+    let suite: [(&[u8], &str); 7] = [
+        (b"", "d41d8cd98f00b204e9800998ecf8427e"),
+        (b"a", "0cc175b9c0f1b6a831c399e269772661"),
+        (b"abc", "900150983cd24fb0d6963f7d28e17f72"),
+        (b"message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+        (
+            b"abcdefghijklmnopqrstuvwxyz",
+            "c3fcd3d76192e4007dfb496cca67e13b",
+        ),
+        (
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+            "d174ab98d277d9f5a5611c2c9f419d9f",
+        ),
+        (
+            b"12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+            "57edf4a22be3c955ac49da2e2107b67a",
+        ),
+    ];
+    for (message, digest) in suite {
+        assert_eq!(
+            hex_digest(message),
+            digest,
+            "{}",
+            String::from_utf8_lossy(message)
+        );
+    }
+}
+
+#[test]
+fn digest_does_not_depend_on_how_input_is_split() {
+    // Splits around the 64-byte block and the 56-byte padding boundary
+    let message: Vec<u8> = (0..200u8).collect();
+    let expected = hex_digest(&message);
+    for split in [1, 55, 56, 63, 64, 65, 128] {
+        let mut md5 = Md5::new();
+        for chunk in message.chunks(split) {
+            md5.update(chunk);
+        }
+        assert_eq!(to_hex(md5.finalize()), expected, "chunks of {}", split);
+    }
+}
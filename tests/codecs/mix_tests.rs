@@ -0,0 +1,212 @@
+// tests/codecs/mix_tests.rs
+
+// Tests channel layouts and mixing PCM buffers between them
+
+use crate::common::{corpus_file, open_flac, temp_file};
+use playback::common::{
+    channels::{ChannelLayout, Speaker},
+    errors::AudioError,
+    mix::MixMatrix,
+    pcm::{Storage, PCM},
+    stream::Stream,
+};
+use std::{f64::consts::FRAC_1_SQRT_2, fs};
+
+fn abbreviations(layout: &ChannelLayout) -> Vec<&'static str> {
+    layout
+        .get_speakers()
+        .iter()
+        .map(|speaker| speaker.map_or("-", Speaker::get_abbreviation))
+        .collect()
+}
+
+fn assert_close(actual: &[f64], expected: &[f64]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn default_layouts_follow_flac_channel_order() {
+    let expected = [
+        "FC",
+        "FL FR",
+        "FL FR FC",
+        "FL FR BL BR",
+        "FL FR FC BL BR",
+        "FL FR FC LFE BL BR",
+        "FL FR FC LFE BC SL SR",
+        "FL FR FC LFE BL BR SL SR",
+    ];
+    for (i, speakers) in expected.iter().enumerate() {
+        let layout = ChannelLayout::new(i + 1);
+        assert_eq!(abbreviations(&layout).join(" "), *speakers);
+    }
+    assert_eq!(ChannelLayout::surround_5_1().get_mask(), 0x3F);
+    assert_eq!(ChannelLayout::surround_7_1().get_mask(), 0x63F);
+    assert_eq!(abbreviations(&ChannelLayout::new(9))[8], "-");
+}
+
+#[test]
+fn channel_masks_assign_speakers_from_the_lowest_bit() {
+    let layout = ChannelLayout::from_mask(0x60F, 6);
+    assert_eq!(
+        abbreviations(&layout),
+        ["FL", "FR", "FC", "LFE", "SL", "SR"]
+    );
+    assert_eq!(layout.get_mask(), 0x60F);
+    assert_eq!(layout.find(Speaker::SideLeft), Some(4));
+
+    // Channels beyond the set bits are unpositioned, and bits beyond the channels ignored
+    assert_eq!(
+        abbreviations(&ChannelLayout::from_mask(0x4, 2)),
+        ["FC", "-"]
+    );
+    assert_eq!(ChannelLayout::from_mask(0x3F, 2), ChannelLayout::stereo());
+}
+
+#[test]
+fn vorbis_comments_are_parsed() {
+    let (_, flac) = open_flac(&corpus_file("metadata_all_blocks.flac")).unwrap();
+    let metadata = flac.get_metadata();
+    assert_eq!(metadata.get_vendor(), Some("playback test corpus"));
+    assert_eq!(metadata.get_comment("title"), Some("Conformance"));
+    assert_eq!(metadata.get_channel_mask(), Some(0x3));
+    assert_eq!(metadata.get_channel_layout(), Some(ChannelLayout::stereo()));
+}
+
+#[test]
+fn channel_mask_tag_overrides_default_layout() {
+    let mut bytes = fs::read(corpus_file("metadata_all_blocks.flac")).unwrap();
+    let tag = b"CHANNEL_MASK=0x0003";
+    let at = bytes
+        .windows(tag.len())
+        .position(|window| window == tag)
+        .unwrap();
+    bytes[at..at + tag.len()].copy_from_slice(b"CHANNEL_MASK=0x0600");
+    let path = temp_file("side_channels.flac", &bytes);
+
+    let pcm = PCM::from_stream(&mut Stream::new(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(abbreviations(pcm.get_layout()), ["SL", "SR"]);
+}
+
+#[test]
+fn decoded_surround_uses_default_layout() {
+    let path = corpus_file("channels_6.flac");
+    let pcm = PCM::from_stream(&mut Stream::new(&path).unwrap()).unwrap();
+    assert_eq!(pcm.get_layout(), &ChannelLayout::surround_5_1());
+}
+
+#[test]
+fn surround_downmixes_to_stereo() {
+    let matrix = MixMatrix::standard(&ChannelLayout::surround_5_1(), &ChannelLayout::stereo());
+    let scale = 1.0 / (1.0 + 2.0 * FRAC_1_SQRT_2);
+    let (center, surround) = (FRAC_1_SQRT_2 * scale, FRAC_1_SQRT_2 * scale);
+    assert_close(
+        &matrix.get_gains()[0],
+        &[scale, 0.0, center, 0.0, surround, 0.0],
+    );
+    assert_close(
+        &matrix.get_gains()[1],
+        &[0.0, scale, center, 0.0, 0.0, surround],
+    );
+
+    let matrix = MixMatrix::standard(&ChannelLayout::surround_7_1(), &ChannelLayout::stereo());
+    let scale = 1.0 / (1.0 + 3.0 * FRAC_1_SQRT_2);
+    let side = FRAC_1_SQRT_2 * scale;
+    assert_close(
+        &matrix.get_gains()[0],
+        &[scale, 0.0, side, 0.0, side, 0.0, side, 0.0],
+    );
+    assert_close(
+        &matrix.get_gains()[1],
+        &[0.0, scale, side, 0.0, 0.0, side, 0.0, side],
+    );
+}
+
+#[test]
+fn stereo_downmixes_to_mono_and_back() {
+    let down = MixMatrix::standard(&ChannelLayout::stereo(), &ChannelLayout::mono());
+    assert_close(&down.get_gains()[0], &[0.5, 0.5]);
+
+    // Upmixing spreads the center at -3 dB, which cannot clip
+    let up = MixMatrix::standard(&ChannelLayout::mono(), &ChannelLayout::stereo());
+    assert_close(&up.get_gains().concat(), &[FRAC_1_SQRT_2, FRAC_1_SQRT_2]);
+
+    let stereo = PCM::new(
+        vec![1000i16, -1000, 3000, 1000],
+        ChannelLayout::stereo(),
+        44100,
+        Storage::Interleaved,
+    )
+    .unwrap();
+    let mono = stereo.remix(&ChannelLayout::mono()).unwrap();
+    assert_eq!(mono.get_layout(), &ChannelLayout::mono());
+    assert_eq!(mono.samples::<i16>().unwrap(), [0, 2000]);
+}
+
+#[test]
+fn identical_layouts_mix_through() {
+    let layout = ChannelLayout::surround_5_1();
+    assert_eq!(
+        MixMatrix::standard(&layout, &layout),
+        MixMatrix::identity(&layout)
+    );
+
+    let pcm = PCM::from_channels(vec![vec![1i32, 2], vec![3, 4]], 8000).unwrap();
+    assert_eq!(pcm.remix(&ChannelLayout::stereo()).unwrap(), pcm);
+}
+
+#[test]
+fn custom_matrices_are_applied_as_given() {
+    let matrix = MixMatrix::new(
+        ChannelLayout::stereo(),
+        ChannelLayout::stereo(),
+        vec![vec![0.0, 1.0], vec![1.0, 1.0]],
+    )
+    .unwrap();
+    let pcm = PCM::new(
+        vec![0.25f32, 0.5, 0.75, 0.5],
+        ChannelLayout::stereo(),
+        48000,
+        Storage::Interleaved,
+    )
+    .unwrap();
+    let mixed = matrix.apply(&pcm).unwrap();
+    assert_eq!(mixed.get_storage(), Storage::Interleaved);
+    assert_eq!(mixed.samples::<f32>().unwrap(), [0.5, 0.75, 0.5, 1.25]);
+
+    // Integer outputs clip instead of wrapping
+    let pcm = PCM::from_channels(vec![vec![30000i16], vec![30000]], 8000).unwrap();
+    assert_eq!(
+        matrix.apply(&pcm).unwrap().samples::<i16>().unwrap(),
+        [30000, 32767]
+    );
+}
+
+#[test]
+fn mismatched_matrices_are_rejected() {
+    assert!(matches!(
+        MixMatrix::new(
+            ChannelLayout::stereo(),
+            ChannelLayout::mono(),
+            vec![vec![1.0]],
+        ),
+        Err(AudioError::InvalidData(_))
+    ));
+
+    let matrix = MixMatrix::standard(&ChannelLayout::stereo(), &ChannelLayout::mono());
+    let pcm = PCM::from_channels(vec![vec![0i16; 4]], 8000).unwrap();
+    assert!(matches!(
+        matrix.apply(&pcm),
+        Err(AudioError::InvalidData(_))
+    ));
+
+    let mut pcm = pcm;
+    assert!(pcm.set_layout(ChannelLayout::stereo()).is_err());
+    assert!(pcm
+        .set_layout(ChannelLayout::from_speakers(&[Speaker::BackCenter]))
+        .is_ok());
+}
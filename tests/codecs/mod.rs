@@ -0,0 +1,8 @@
+mod convert_tests;
+mod crc_tests;
+mod md5_tests;
+mod mix_tests;
+mod pcm_tests;
+mod registry_tests;
+mod resample_tests;
+mod stream_tests;
@@ -0,0 +1,154 @@
+// tests/codecs/pcm_tests.rs
+
+use crate::common::{corpus_file, read_wav};
+use playback::common::{
+    channels::ChannelLayout,
+    errors::AudioError,
+    pcm::{Storage, PCM},
+    sample::{SampleFormat, I24},
+    stream::Stream,
+};
+
+#[test]
+fn decodes_flac_files() {
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    assert!(PCM::from_stream(&mut stream).is_ok());
+}
+
+#[test]
+fn rejects_unknown_formats() {
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.wav")).unwrap();
+    assert!(matches!(
+        PCM::from_stream(&mut stream),
+        Err(AudioError::UnsupportedFileCodec)
+    ));
+}
+
+// Samples of one channel widened to i32, whatever integer format holds them
+fn channel_samples(pcm: &PCM, channel: usize) -> Vec<i32> {
+    match pcm.get_format() {
+        SampleFormat::I8 => pcm
+            .channel::<i8>(channel)
+            .unwrap()
+            .iter()
+            .map(i32::from)
+            .collect(),
+        SampleFormat::I16 => pcm
+            .channel::<i16>(channel)
+            .unwrap()
+            .iter()
+            .map(i32::from)
+            .collect(),
+        SampleFormat::I24 => pcm
+            .channel::<I24>(channel)
+            .unwrap()
+            .iter()
+            .map(I24::get)
+            .collect(),
+        SampleFormat::I32 => pcm.channel::<i32>(channel).unwrap().to_vec(),
+        format => panic!("{:?} is not an integer format", format),
+    }
+}
+
+#[test]
+fn decoded_pcm_uses_the_full_range_of_its_format() {
+    let names = [
+        "bit_depth_4",
+        "bit_depth_12",
+        "bit_depth_20",
+        "bit_depth_24",
+        "bit_depth_32",
+        "channels_6",
+    ];
+    for name in names {
+        let path = corpus_file(&format!("{}.flac", name));
+        let wav = read_wav(&path.with_extension("wav"));
+        let pcm = PCM::from_stream(&mut Stream::new(&path).unwrap()).unwrap();
+
+        let format = SampleFormat::for_bit_depth(wav.bit_depth as u8).unwrap();
+        assert_eq!(pcm.get_format(), format, "{}", name);
+        assert_eq!(pcm.get_num_channels(), wav.num_channels as usize);
+        assert_eq!(pcm.get_sample_rate(), wav.sample_rate);
+        assert_eq!(pcm.get_storage(), Storage::Planar);
+        assert_eq!(pcm.get_valid_bits(), wav.bit_depth as u8, "{}", name);
+
+        let num_channels = pcm.get_num_channels();
+        let shift = format.get_bits() - wav.bit_depth as u8;
+        for channel in 0..num_channels {
+            let expected: Vec<i32> = wav
+                .samples
+                .iter()
+                .skip(channel)
+                .step_by(num_channels)
+                .map(|&sample| sample << shift)
+                .collect();
+            assert_eq!(channel_samples(&pcm, channel), expected, "{}", name);
+        }
+    }
+}
+
+#[test]
+fn converts_between_planar_and_interleaved_storage() {
+    let planar = PCM::from_channels(
+        vec![vec![1.0f32, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]],
+        48000,
+    )
+    .unwrap();
+    assert_eq!(planar.get_num_frames(), 2);
+    assert_eq!(
+        planar.channel::<f32>(1).unwrap().as_slice(),
+        Some(&[3.0, 4.0][..])
+    );
+
+    let interleaved = planar.to_storage(Storage::Interleaved);
+    assert_eq!(interleaved.get_storage(), Storage::Interleaved);
+    assert_eq!(
+        interleaved.samples::<f32>(),
+        Some(&[1.0, 3.0, 5.0, 2.0, 4.0, 6.0][..])
+    );
+
+    // Views of interleaved channels step over the other channels instead of copying
+    let view = interleaved.channel::<f32>(2).unwrap();
+    assert_eq!(view.len(), 2);
+    assert_eq!(view.to_vec(), vec![5.0, 6.0]);
+    assert_eq!(view.get(2), None);
+    assert_eq!(view.as_slice(), None);
+
+    assert!(interleaved.channel::<f32>(3).is_none());
+    assert!(interleaved.channel::<i16>(0).is_none());
+    assert_eq!(interleaved.into_planar(), planar);
+}
+
+#[test]
+fn rejects_samples_that_do_not_fill_every_channel() {
+    let samples = vec![0i16; 5];
+    assert!(matches!(
+        PCM::new(
+            samples,
+            ChannelLayout::stereo(),
+            44100,
+            Storage::Interleaved
+        ),
+        Err(AudioError::InvalidData(_))
+    ));
+    assert!(PCM::from_channels(vec![vec![0i8; 3], vec![0i8; 2]], 44100).is_err());
+}
+
+#[test]
+fn integer_channels_keep_their_bit_depth() {
+    let pcm = PCM::from_integer_channels(vec![vec![-2048, 0, 2047]], 12, 8000).unwrap();
+    assert_eq!(pcm.get_format(), SampleFormat::I16);
+    assert_eq!(pcm.get_valid_bits(), 12);
+    assert_eq!(pcm.samples::<i16>().unwrap(), &[i16::MIN, 0, 2047 << 4]);
+
+    // Buffers built from samples of a format use all its bits
+    let full = PCM::from_channels(vec![vec![1i16]], 8000).unwrap();
+    assert_eq!(full.get_valid_bits(), 16);
+
+    // Appending keeps the bit depth of the more precise buffer
+    let mut appended = pcm.clone();
+    appended.append(&full).unwrap();
+    assert_eq!(appended.get_valid_bits(), 16);
+    assert_eq!(appended.get_num_frames(), 4);
+    assert_eq!(pcm.into_interleaved().get_valid_bits(), 12);
+}
@@ -0,0 +1,147 @@
+// tests/codecs/registry_tests.rs
+
+use crate::common::{corpus_file, temp_file};
+use playback::codecs::{
+    flac::flac::FlacReader,
+    registry::{Codec, FormatReader, Registry, EXTENSION_BONUS},
+};
+use playback::common::{errors::AudioError, pcm::PCM, position::Position, stream::Stream};
+use std::fs;
+
+// Codec standing in for a real decoder, yielding a fixed set of samples
+struct FakeCodec;
+
+impl Codec for FakeCodec {
+    fn describe(&self) -> String {
+        "fake".to_string()
+    }
+
+    fn decode(&mut self, _stream: &mut Stream) -> Result<PCM, AudioError> {
+        PCM::from_channels(vec![vec![1i16, 2, 3]], 8000)
+    }
+
+    fn decode_range(
+        &mut self,
+        _stream: &mut Stream,
+        _start: Position,
+        _end: Position,
+    ) -> Result<PCM, AudioError> {
+        PCM::from_channels(vec![vec![2i16]], 8000)
+    }
+}
+
+// Format reader recognizing RIFF files with a fixed score
+struct FakeReader {
+    name: &'static str,
+    extensions: &'static [&'static str],
+    score: u8,
+}
+
+impl FormatReader for FakeReader {
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn probe(&self, header: &[u8]) -> u8 {
+        if header.starts_with(b"RIFF") {
+            self.score
+        } else {
+            0
+        }
+    }
+
+    fn open(&self, _stream: &mut Stream) -> Result<Box<dyn Codec>, AudioError> {
+        Ok(Box::new(FakeCodec))
+    }
+}
+
+#[test]
+fn probes_flac_by_content() {
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    let registry = Registry::default();
+    let (reader, score) = registry.probe(&mut stream).unwrap();
+    assert_eq!(reader.get_name(), "FLAC");
+    assert_eq!(score, 100);
+
+    // Probing leaves the stream where it was
+    assert_eq!(stream.get_position().unwrap(), 0);
+
+    // The content is recognized whatever the file is called
+    let bytes = fs::read(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    let path = temp_file("registry_misnamed.bin", &bytes);
+    let (reader, score) = registry.probe(&mut Stream::new(&path).unwrap()).unwrap();
+    assert_eq!(reader.get_name(), "FLAC");
+    assert_eq!(score, 100);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn scores_flac_by_header_depth() {
+    let bytes = fs::read(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    assert_eq!(FlacReader.probe(&bytes[..42]), 100);
+    assert_eq!(FlacReader.probe(&bytes[..8]), 90);
+    assert_eq!(FlacReader.probe(&bytes[..4]), 60);
+    assert_eq!(FlacReader.probe(b"fLaC\x01\x00\x00\x22"), 60);
+    assert_eq!(FlacReader.probe(b"RIFF"), 0);
+}
+
+#[test]
+fn extension_breaks_ties_but_never_picks_alone() {
+    let mut registry = Registry::new();
+    registry.register(Box::new(FakeReader {
+        name: "first",
+        extensions: &["riff"],
+        score: 50,
+    }));
+    registry.register(Box::new(FakeReader {
+        name: "second",
+        extensions: &["wav"],
+        score: 50,
+    }));
+
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.wav")).unwrap();
+    let (reader, score) = registry.probe(&mut stream).unwrap();
+    assert_eq!(reader.get_name(), "second");
+    assert_eq!(score, 50 + EXTENSION_BONUS);
+
+    // A FLAC file named .wav is still not a RIFF file
+    let bytes = fs::read(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    let path = temp_file("registry_flac.wav", &bytes);
+    assert!(matches!(
+        registry.probe(&mut Stream::new(&path).unwrap()),
+        Err(AudioError::UnsupportedFileCodec)
+    ));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn registered_formats_decode_through_pcm() {
+    let mut registry = Registry::default();
+    registry.register(Box::new(FakeReader {
+        name: "riff",
+        extensions: &["wav"],
+        score: 80,
+    }));
+
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.wav")).unwrap();
+    let pcm = PCM::from_stream_with(&mut stream, &registry).unwrap();
+    assert_eq!(pcm.samples::<i16>(), Some(&[1, 2, 3][..]));
+
+    // Ranges are decoded by the registered format as well
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.wav")).unwrap();
+    let pcm = PCM::from_stream_range_with(
+        &mut stream,
+        &registry,
+        Position::Sample(1),
+        Position::Sample(2),
+    )
+    .unwrap();
+    assert_eq!(pcm.samples::<i16>(), Some(&[2][..]));
+
+    let mut stream = Stream::new(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    assert!(PCM::from_stream_with(&mut stream, &registry).is_ok());
+}
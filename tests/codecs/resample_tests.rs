@@ -0,0 +1,186 @@
+// tests/codecs/resample_tests.rs
+
+// Tests changing the sample rate of PCM buffers
+
+use crate::common::{corpus_file, open_flac};
+use playback::common::{
+    channels::ChannelLayout,
+    pcm::{Storage, PCM},
+    resample::{ResampleQuality, Resampler},
+    sample::SampleFormat,
+    stream::Stream,
+};
+use std::f64::consts::PI;
+
+fn sine(frequency: f64, sample_rate: u32, len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin() * 0.5)
+        .collect()
+}
+
+fn mono(samples: Vec<f64>, sample_rate: u32) -> PCM {
+    PCM::new(samples, ChannelLayout::mono(), sample_rate, Storage::Planar).unwrap()
+}
+
+fn stereo(interleaved: &[f64]) -> PCM {
+    PCM::new(
+        interleaved.to_vec(),
+        ChannelLayout::stereo(),
+        44100,
+        Storage::Interleaved,
+    )
+    .unwrap()
+}
+
+// Largest difference from the ideal sine, away from the ends of the stream
+fn max_error(output: &PCM, frequency: f64, margin: usize) -> f64 {
+    let samples = output.samples::<f64>().unwrap();
+    let expected = sine(frequency, output.get_sample_rate(), samples.len());
+    samples[margin..samples.len() - margin]
+        .iter()
+        .zip(&expected[margin..])
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn resamples_sines_accurately() {
+    let cases = [
+        (ResampleQuality::Fast, 1e-2),
+        (ResampleQuality::Medium, 1e-3),
+        (ResampleQuality::Best, 1e-4),
+    ];
+    for (input_rate, output_rate) in [
+        (44100, 48000),
+        (48000, 44100),
+        (8000, 44100),
+        (96000, 44100),
+    ] {
+        let input = mono(sine(1000.0, input_rate, 10000), input_rate);
+        for (quality, tolerance) in cases {
+            let output = input.resample(output_rate, quality).unwrap();
+            assert_eq!(output.get_sample_rate(), output_rate);
+            let error = max_error(&output, 1000.0, 200);
+            assert!(
+                error < tolerance,
+                "{} -> {} at {:?}: {}",
+                input_rate,
+                output_rate,
+                quality,
+                error
+            );
+        }
+    }
+}
+
+#[test]
+fn output_covers_the_whole_input() {
+    for (input_rate, output_rate) in [
+        (44100, 48000),
+        (48000, 44100),
+        (44100, 22050),
+        (7, 3),
+        (44100, 44101),
+    ] {
+        for len in [0, 1, 5, 1000, 4097] {
+            let input = mono(vec![0.25; len], input_rate);
+            let output = input
+                .resample(output_rate, ResampleQuality::Medium)
+                .unwrap();
+            let expected = (len as u64 * output_rate as u64).div_ceil(input_rate as u64);
+            assert_eq!(
+                output.get_num_frames() as u64,
+                expected,
+                "{} -> {}, {}",
+                input_rate,
+                output_rate,
+                len
+            );
+        }
+    }
+
+    // Constant input stays constant until the filter reaches the end of the stream
+    let output = mono(vec![0.25; 4410], 44100)
+        .resample(48000, ResampleQuality::Best)
+        .unwrap();
+    let samples = output.samples::<f64>().unwrap();
+    for &x in &samples[100..samples.len() - 100] {
+        assert!((x - 0.25).abs() < 1e-3, "{}", x);
+    }
+}
+
+#[test]
+fn streaming_matches_resampling_at_once() {
+    let left = sine(440.0, 44100, 5000);
+    let right = sine(3000.0, 44100, 5000);
+    let input = PCM::from_channels(vec![left, right], 44100)
+        .unwrap()
+        .into_interleaved();
+    let expected = input.resample(48000, ResampleQuality::Medium).unwrap();
+    assert_eq!(expected.get_storage(), Storage::Interleaved);
+
+    // Feed blocks of uneven sizes, some too short to produce any output
+    let samples = input.samples::<f64>().unwrap();
+    let mut resampler = Resampler::new(44100, 48000, 2, ResampleQuality::Medium).unwrap();
+    let mut output = resampler.process(&stereo(&samples[..2])).unwrap();
+    let mut pos = 1;
+    for len in [7, 1000, 0, 2500, 1492] {
+        let block = stereo(&samples[2 * pos..2 * (pos + len)]);
+        output.append(&resampler.process(&block).unwrap()).unwrap();
+        pos += len;
+    }
+    assert_eq!(pos, 5000);
+    output.append(&resampler.flush().unwrap()).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn downsampling_removes_frequencies_above_the_new_nyquist() {
+    let input = mono(sine(12000.0, 48000, 20000), 48000);
+    let output = input.resample(16000, ResampleQuality::Best).unwrap();
+    let samples = output.samples::<f64>().unwrap();
+    let peak = samples[100..samples.len() - 100]
+        .iter()
+        .fold(0.0f64, |peak, x| peak.max(x.abs()));
+    assert!(peak < 1e-3, "{}", peak);
+}
+
+#[test]
+fn resamples_decoder_blocks_as_they_arrive() {
+    let path = corpus_file("fixed_block_size_many_frames.flac");
+    let expected = PCM::from_stream(&mut Stream::new(&path).unwrap())
+        .unwrap()
+        .resample(48000, ResampleQuality::Fast)
+        .unwrap();
+
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    let metadata = flac.get_metadata().clone();
+    let sample_rate = metadata.get_sample_rate().unwrap();
+    let num_channels = metadata.get_num_channels().unwrap() as usize;
+    let mut resampler =
+        Resampler::new(sample_rate, 48000, num_channels, ResampleQuality::Fast).unwrap();
+
+    let mut output: Option<PCM> = None;
+    for block in flac.frames(&mut stream) {
+        let block = block.unwrap();
+        let pcm = PCM::from_integer_channels(
+            block.into_channels(),
+            metadata.get_bit_depth().unwrap(),
+            sample_rate,
+        )
+        .unwrap();
+        let resampled = resampler.process(&pcm).unwrap();
+        match output.as_mut() {
+            Some(output) => output.append(&resampled).unwrap(),
+            None => output = Some(resampled),
+        }
+    }
+    let mut output = output.unwrap();
+    output.append(&resampler.flush().unwrap()).unwrap();
+
+    assert_eq!(
+        output.get_format(),
+        SampleFormat::for_bit_depth(metadata.get_bit_depth().unwrap()).unwrap()
+    );
+    assert_eq!(output, expected);
+}
@@ -0,0 +1,121 @@
+// tests/codecs/stream_tests.rs
+
+use crate::common::temp_file;
+use playback::common::{errors::AudioError, stream::Stream};
+use std::fs;
+
+#[test]
+fn reads_bits_across_byte_boundaries() {
+    let path = temp_file("stream_bits", &[0b1010_1100, 0b0101_0011, 0xFF]);
+    let mut stream = Stream::new(&path).unwrap();
+
+    assert_eq!(stream.read_bits(3).unwrap(), 0b101);
+    assert_eq!(stream.read_bits(7).unwrap(), 0b0110001);
+    assert_eq!(stream.read_bit().unwrap(), 0);
+    assert_eq!(stream.read_bits(5).unwrap(), 0b10011);
+    assert_eq!(stream.read_bits(8).unwrap(), 0xFF);
+    assert!(stream.read_bit().unwrap_err().is_eof());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn reads_signed_bits_up_to_64_bits() {
+    let bytes = [0xFF, 0xFF, 0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00, 0x7F, 0xF8];
+    let path = temp_file("stream_signed_bits", &bytes);
+    let mut stream = Stream::new(&path).unwrap();
+
+    assert_eq!(stream.read_signed_bits(32).unwrap(), -1);
+    assert_eq!(stream.read_signed_bits(32).unwrap(), i32::MIN);
+    assert_eq!(stream.read_signed_bits(4).unwrap(), 7);
+    assert_eq!(stream.read_signed_bits(4).unwrap(), -1);
+    assert_eq!(stream.read_signed_bits_64(0).unwrap(), 0);
+    assert!(matches!(
+        stream.read_signed_bits(33),
+        Err(AudioError::ExceededBitLimit)
+    ));
+
+    let bytes = [0x80, 0, 0, 0, 0x40];
+    let path_33 = temp_file("stream_signed_bits_33", &bytes);
+    let mut stream = Stream::new(&path_33).unwrap();
+    assert_eq!(stream.read_signed_bits_64(33).unwrap(), -(1 << 32));
+
+    // The largest side channel sample of 32-bit stereo
+    let mut stream = Stream::from_bytes(vec![0x7F, 0xFF, 0xFF, 0xFF, 0x80]);
+    assert_eq!(stream.read_signed_bits_64(33).unwrap(), (1 << 32) - 1);
+
+    fs::remove_file(path).unwrap();
+    fs::remove_file(path_33).unwrap();
+}
+
+#[test]
+fn aligns_to_the_next_byte() {
+    let path = temp_file("stream_align", &[0b1000_0000, 0x12, 0x34]);
+    let mut stream = Stream::new(&path).unwrap();
+
+    assert_eq!(stream.read_bit().unwrap(), 1);
+    stream.align_to_byte();
+    assert_eq!(stream.peek_u16().unwrap(), 0x1234);
+    assert_eq!(stream.read_bits(8).unwrap(), 0x12);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn tracks_crcs_of_bytes_read() {
+    // Check values of the CRC-8 (poly 0x07) and CRC-16 (poly 0x8005) catalogues
+    let path = temp_file("stream_crc", b"123456789");
+    let mut stream = Stream::new(&path).unwrap();
+
+    stream.reset_crc();
+    for _ in 0..9 {
+        stream.read_byte().unwrap();
+    }
+    assert_eq!(stream.get_crc8(), 0xF4);
+    assert_eq!(stream.get_crc16(), 0xFEE8);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn reads_bytes_held_in_memory() {
+    let mut stream = Stream::from_bytes(vec![0x12, 0x34, 0x56]);
+    assert!(stream.get_path().is_none());
+    assert_eq!(stream.get_len().unwrap(), 3);
+    assert_eq!(stream.read_u16().unwrap(), 0x1234);
+
+    // A reopened stream starts over at the first byte
+    let mut other = stream.reopen().unwrap();
+    assert_eq!(other.read_bits(4).unwrap(), 0x1);
+    assert_eq!(stream.read_byte().unwrap(), 0x56);
+    assert!(stream.read_byte().unwrap_err().is_eof());
+}
+
+#[test]
+fn peeks_without_moving_across_buffer_boundaries() {
+    // Files are read through an 8 KiB buffer, so these peeks straddle its end
+    let bytes: Vec<u8> = (0..8200u32).map(|i| i as u8).collect();
+    let path = temp_file("stream_peek", &bytes);
+    let mut stream = Stream::new(&path).unwrap();
+
+    for _ in 0..8190 {
+        stream.read_byte().unwrap();
+    }
+    for position in 8190..8196 {
+        let expected = u16::from_be_bytes([bytes[position], bytes[position + 1]]);
+        assert_eq!(stream.peek_u16().unwrap(), expected);
+        assert_eq!(stream.get_position().unwrap(), position as u64);
+        assert_eq!(stream.read_byte().unwrap(), bytes[position]);
+    }
+    assert_eq!(
+        stream.peek_u32().unwrap(),
+        u32::from_be_bytes(bytes[8196..8200].try_into().unwrap())
+    );
+
+    // A peek past the last byte runs out of input without consuming it
+    stream.seek_to(8199).unwrap();
+    assert!(matches!(stream.peek_u16(), Err(AudioError::EOF)));
+    assert_eq!(stream.read_byte().unwrap(), bytes[8199]);
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,290 @@
+// tests/common/analysis_tests.rs
+
+// Tests reporting how FLAC streams were coded
+
+use super::{corpus_cases, corpus_file, frame_offsets, open_flac, temp_file};
+use playback::codecs::flac::{
+    analysis::{type_name, Analysis},
+    decoder::{CrcPolicy, RecoveryMode},
+    subframe::{ResidualCodingMethod, SubframeType},
+};
+use playback::common::errors::AudioError;
+use std::{fs, path::Path};
+
+fn analyze(path: &Path) -> Analysis {
+    let (mut stream, flac) = open_flac(path).unwrap();
+    flac.analyze(&mut stream).unwrap()
+}
+
+#[test]
+fn analysis_reports_how_subframes_were_coded() {
+    for order in 0..=4 {
+        let analysis = analyze(&corpus_file(&format!(
+            "subframe_fixed_order_{}.flac",
+            order
+        )));
+        assert!(analysis
+            .get_histograms()
+            .get_fixed_orders()
+            .contains_key(&order));
+    }
+    for order in [1, 2, 8, 12, 32] {
+        let analysis = analyze(&corpus_file(&format!("subframe_lpc_order_{}.flac", order)));
+        let subframe = &analysis.get_frames()[0].get_subframes()[0];
+        assert_eq!(subframe.get_type(), SubframeType::LPC);
+        assert_eq!(subframe.get_order(), order);
+        assert_eq!(
+            subframe.get_details().get_coefficients().len(),
+            order as usize
+        );
+    }
+
+    let analysis = analyze(&corpus_file("subframe_lpc_precision_15.flac"));
+    assert!(analysis
+        .get_histograms()
+        .get_lpc_precisions()
+        .contains_key(&15));
+
+    let analysis = analyze(&corpus_file("subframe_constant.flac"));
+    assert!(analysis
+        .get_histograms()
+        .get_subframe_types()
+        .contains_key("CONSTANT"));
+    let analysis = analyze(&corpus_file("subframe_verbatim.flac"));
+    assert!(analysis
+        .get_histograms()
+        .get_subframe_types()
+        .contains_key("VERBATIM"));
+
+    let analysis = analyze(&corpus_file("subframe_wasted_bits.flac"));
+    assert!(analysis
+        .get_histograms()
+        .get_wasted_bits()
+        .keys()
+        .any(|&bits| bits > 0));
+}
+
+#[test]
+fn analysis_reports_residual_coding() {
+    let analysis = analyze(&corpus_file("residual_partition_order_8.flac"));
+    assert!(analysis
+        .get_histograms()
+        .get_partition_orders()
+        .contains_key(&8));
+
+    let analysis = analyze(&corpus_file("residual_escape.flac"));
+    assert!(analysis.get_histograms().get_escaped_partitions() > 0);
+
+    let analysis = analyze(&corpus_file("residual_rice2.flac"));
+    let rice2 = analysis.get_frames().iter().any(|frame| {
+        frame.get_subframes().iter().any(|subframe| {
+            subframe
+                .get_details()
+                .get_residual()
+                .is_some_and(|residual| residual.get_coding_method() == ResidualCodingMethod::Rice2)
+        })
+    });
+    assert!(rice2);
+}
+
+#[test]
+fn analysis_sizes_add_up() {
+    for path in corpus_cases("") {
+        let analysis = analyze(&path);
+        let frames = analysis.get_frames();
+        let len = fs::metadata(&path).unwrap().len();
+
+        // Frames follow each other up to the end of the file
+        for pair in frames.windows(2) {
+            assert_eq!(
+                pair[0].get_offset() + pair[0].get_size(),
+                pair[1].get_offset()
+            );
+        }
+        let last = frames.last().unwrap();
+        assert_eq!(
+            last.get_offset() + last.get_size(),
+            len,
+            "{}",
+            path.display()
+        );
+
+        for frame in frames {
+            let subframes = frame.get_subframes();
+            assert_eq!(subframes.len(), frame.get_header().get_num_channels());
+            let subframe_bits: u64 = subframes
+                .iter()
+                .map(|subframe| subframe.get_details().get_bits())
+                .sum();
+            assert!(subframe_bits <= frame.get_size() * 8);
+
+            // Partitions follow the coding method and partition order fields
+            for subframe in subframes {
+                if let Some(residual) = subframe.get_details().get_residual() {
+                    let partition_bits: u64 =
+                        residual.get_partitions().iter().map(|p| p.get_bits()).sum();
+                    assert_eq!(
+                        partition_bits + 6,
+                        residual.get_bits(),
+                        "{}",
+                        path.display()
+                    );
+                    assert_eq!(
+                        residual.get_partitions().len(),
+                        1 << residual.get_partition_order()
+                    );
+                    assert!(subframe.get_residual_bits() < subframe.get_details().get_bits());
+                }
+            }
+        }
+
+        // Every frame and subframe is counted once
+        let histograms = analysis.get_histograms();
+        assert_eq!(
+            histograms.get_block_sizes().values().sum::<u64>(),
+            frames.len() as u64
+        );
+        let num_subframes: usize = frames.iter().map(|frame| frame.get_subframes().len()).sum();
+        assert_eq!(
+            histograms.get_subframe_types().values().sum::<u64>(),
+            num_subframes as u64
+        );
+    }
+}
+
+#[test]
+fn analysis_writes_text_and_json() {
+    let analysis = analyze(&corpus_file("subframe_lpc_order_8.flac"));
+    let subframe = &analysis.get_frames()[0].get_subframes()[0];
+
+    let text = analysis.to_text();
+    assert!(text.starts_with("frame=0\toffset="));
+    assert!(text.contains("type=LPC\torder=8"));
+    assert!(text.contains(&format!(
+        "qlp_coeff_precision={}",
+        subframe.get_details().get_precision()
+    )));
+    assert!(text.contains("lpc_orders=8:1"));
+
+    for path in corpus_cases("") {
+        let json = analyze(&path).to_json();
+        assert_eq!(skip_json_value(json.as_bytes(), 0), Some(json.len()));
+    }
+    let json = analysis.to_json();
+    assert!(json.starts_with("{\"frames\":[{\"offset\":"));
+    assert!(json.contains(&format!(
+        "\"type\":\"{}\",\"order\":8",
+        type_name(SubframeType::LPC)
+    )));
+    assert!(json.contains("\"lpc_orders\":{\"8\":1}"));
+}
+
+#[test]
+fn analysis_reports_damaged_frames() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let mut bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "analysis_offsets.flac");
+    assert_eq!(offsets.len(), 5);
+
+    // Damage the middle of the second frame
+    let middle = ((offsets[1] + offsets[2]) / 2) as usize;
+    bytes[middle] ^= 0xFF;
+    let path = temp_file("analysis_damaged.flac", &bytes);
+
+    let analyze_with = |crc_policy, recovery_mode| {
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        flac.set_crc_policy(crc_policy);
+        flac.set_recovery_mode(recovery_mode);
+        flac.analyze(&mut stream)
+    };
+
+    let result = analyze_with(CrcPolicy::Error, RecoveryMode::Disabled);
+    assert!(matches!(result, Err(AudioError::CrcMismatch(_))));
+
+    // The frames after the damaged one are still reported
+    let analysis = analyze_with(CrcPolicy::Error, RecoveryMode::Skip).unwrap();
+    let frame_offsets: Vec<u64> = analysis
+        .get_frames()
+        .iter()
+        .map(|frame| frame.get_offset())
+        .collect();
+    assert_eq!(
+        frame_offsets,
+        vec![offsets[0], offsets[2], offsets[3], offsets[4]]
+    );
+    let damaged = analysis.get_damaged_frames();
+    assert_eq!(damaged.len(), 1);
+    assert_eq!(damaged[0].get_offset(), offsets[1]);
+    assert!(damaged[0].get_error().starts_with("CRC mismatch"));
+
+    let text = analysis.to_text();
+    assert!(text.contains(&format!(
+        "damaged_frame\toffset={}\terror=CRC mismatch",
+        offsets[1]
+    )));
+    assert!(text.contains("\nframes=4\ndamaged_frames=1\n"));
+    let json = analysis.to_json();
+    assert_eq!(skip_json_value(json.as_bytes(), 0), Some(json.len()));
+    assert!(json.contains(&format!(
+        "\"damaged_frames\":[{{\"offset\":{},\"error\":\"CRC mismatch",
+        offsets[1]
+    )));
+
+    // Frames whose CRC mismatch is ignored are analyzed like any other
+    let analysis = analyze_with(CrcPolicy::Ignore, RecoveryMode::Disabled).unwrap();
+    assert_eq!(analysis.get_frames().len(), 5);
+    assert!(analysis.get_damaged_frames().is_empty());
+
+    fs::remove_file(path).unwrap();
+}
+
+/**
+ * Check the JSON value starting at a position, returning the position after it
+ * The reports hold no escapes or whitespace, so neither is accepted
+ */
+fn skip_json_value(json: &[u8], pos: usize) -> Option<usize> {
+    match json.get(pos)? {
+        b'{' | b'[' => {
+            let (object, close) = (
+                json[pos] == b'{',
+                if json[pos] == b'{' { b'}' } else { b']' },
+            );
+            let mut pos = pos + 1;
+            if json.get(pos) == Some(&close) {
+                return Some(pos + 1);
+            }
+            loop {
+                if object {
+                    pos = skip_json_string(json, pos)?;
+                    if json.get(pos) != Some(&b':') {
+                        return None;
+                    }
+                    pos += 1;
+                }
+                pos = skip_json_value(json, pos)?;
+                match json.get(pos)? {
+                    b',' => pos += 1,
+                    &c if c == close => return Some(pos + 1),
+                    _ => return None,
+                }
+            }
+        }
+        b'"' => skip_json_string(json, pos),
+        b'-' | b'0'..=b'9' => {
+            let digits = json[pos + 1..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            Some(pos + 1 + digits)
+        }
+        _ => None,
+    }
+}
+
+fn skip_json_string(json: &[u8], pos: usize) -> Option<usize> {
+    if json.get(pos) != Some(&b'"') {
+        return None;
+    }
+    let len = json[pos + 1..].iter().position(|&c| c == b'"')?;
+    Some(pos + len + 2)
+}
@@ -0,0 +1,197 @@
+// tests/common/buffer_tests.rs
+
+// Tests decoding into reusable caller-owned frame buffers
+
+use super::{
+    corpus_cases, corpus_file, frame_offsets, interleave, open_flac, read_frames, read_wav,
+    temp_file,
+};
+use playback::codecs::flac::{buffer::FrameBuffer, decoder::RecoveryMode};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fs,
+    ops::Range,
+    path::Path,
+};
+
+/**
+ * Allocator counting the allocations made by each thread, so a test can
+ * check that a stretch of code allocates nothing without seeing the
+ * allocations of tests running alongside it
+ */
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+// Decode a file through one frame buffer, returning the interleaved samples
+fn decode_into_buffer(path: &Path) -> Vec<i32> {
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    let mut buffer = FrameBuffer::from_metadata(flac.get_metadata()).unwrap();
+
+    let mut samples = Vec::new();
+    let mut next_sample = 0;
+    while flac.decode_into(&mut stream, &mut buffer).unwrap() {
+        assert_eq!(buffer.get_first_sample(), next_sample);
+        next_sample += buffer.get_block_size() as u64;
+        for i in 0..buffer.get_block_size() {
+            samples.extend((0..buffer.get_num_channels()).map(|c| buffer.get_channel(c)[i]));
+        }
+    }
+    samples
+}
+
+#[test]
+fn decode_into_matches_frame_decoding() {
+    for path in corpus_cases("") {
+        let expected = interleave(&read_frames(&path).unwrap());
+        assert_eq!(decode_into_buffer(&path), expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn decode_into_does_not_allocate_per_frame() {
+    for name in [
+        "subframe_lpc_order_32.flac",
+        "bit_depth_32_side_channel.flac",
+        "seek_table.flac",
+    ] {
+        let (mut stream, mut flac) = open_flac(&corpus_file(name)).unwrap();
+        let mut buffer = FrameBuffer::from_metadata(flac.get_metadata()).unwrap();
+
+        let before = allocations();
+        let mut num_frames = 0;
+        while flac.decode_into(&mut stream, &mut buffer).unwrap() {
+            num_frames += 1;
+        }
+        assert!(num_frames > 0, "{}", name);
+        assert_eq!(allocations(), before, "{} allocated while decoding", name);
+    }
+}
+
+#[test]
+fn decode_into_starts_at_seek_target() {
+    let path = corpus_file("seek_table.flac");
+    let wav = read_wav(&path.with_extension("wav"));
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    let mut buffer = FrameBuffer::from_metadata(flac.get_metadata()).unwrap();
+
+    for sample in [12345, 1151, 0] {
+        flac.seek_to_sample(&mut stream, sample).unwrap();
+        assert!(flac.decode_into(&mut stream, &mut buffer).unwrap());
+        assert_eq!(buffer.get_first_sample(), sample);
+
+        let start = sample as usize * 2;
+        let expected = &wav.samples[start..start + buffer.get_block_size() * 2];
+        assert_eq!(
+            buffer.get_channel(0),
+            expected.iter().step_by(2).copied().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            buffer.get_channel(1),
+            expected
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .copied()
+                .collect::<Vec<_>>()
+        );
+    }
+}
+
+/**
+ * Decode a damaged copy of a corpus file into a buffer with the given
+ * recovery mode, returning the first sample of each block, the samples of
+ * the first channel and the lost sample ranges
+ */
+fn decode_damaged_into(
+    bytes: &[u8],
+    name: &str,
+    recovery_mode: RecoveryMode,
+) -> (Vec<u64>, Vec<i32>, Vec<Range<u64>>) {
+    let path = temp_file(name, bytes);
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    flac.set_recovery_mode(recovery_mode);
+    let mut buffer = FrameBuffer::from_metadata(flac.get_metadata()).unwrap();
+
+    let mut first_samples = Vec::new();
+    let mut samples = Vec::new();
+    while flac.decode_into(&mut stream, &mut buffer).unwrap() {
+        first_samples.push(buffer.get_first_sample());
+        samples.extend_from_slice(buffer.get_channel(0));
+    }
+    fs::remove_file(path).unwrap();
+    (first_samples, samples, flac.get_lost_ranges().to_vec())
+}
+
+#[test]
+fn decode_into_fills_lost_samples_with_silence() {
+    // Mono, four frames of 1152 samples and a last one of 392
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let reference = read_wav(&path.with_extension("wav")).samples;
+    let mut bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "decode_into_offsets.flac");
+    bytes[((offsets[1] + offsets[2]) / 2) as usize] ^= 0xFF;
+
+    let (first_samples, samples, lost_ranges) = decode_damaged_into(
+        &bytes,
+        "decode_into_silence.flac",
+        RecoveryMode::FillSilence,
+    );
+    assert_eq!(lost_ranges, vec![1152..2304]);
+    assert_eq!(first_samples, [0, 1152, 2304, 3456, 4608]);
+    assert!(samples[1152..2304].iter().all(|&sample| sample == 0));
+    assert_eq!(samples[..1152], reference[..1152]);
+    assert_eq!(samples[2304..], reference[2304..]);
+
+    // Skipping records the same loss without filling it
+    let (first_samples, samples, lost_ranges) =
+        decode_damaged_into(&bytes, "decode_into_skip.flac", RecoveryMode::Skip);
+    assert_eq!(lost_ranges, vec![1152..2304]);
+    assert_eq!(first_samples, [0, 2304, 3456, 4608]);
+    assert_eq!(samples[1152..], reference[2304..]);
+}
+
+#[test]
+fn decode_into_fills_truncated_end_with_silence() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let reference = read_wav(&path.with_extension("wav")).samples;
+    let bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "decode_into_truncated_offsets.flac");
+    let cut = ((offsets[3] + offsets[4]) / 2) as usize;
+
+    let (_, samples, lost_ranges) = decode_damaged_into(
+        &bytes[..cut],
+        "decode_into_truncated.flac",
+        RecoveryMode::FillSilence,
+    );
+    assert_eq!(lost_ranges, vec![3456..5000]);
+    assert_eq!(samples.len(), reference.len());
+    assert_eq!(samples[..3456], reference[..3456]);
+    assert!(samples[3456..].iter().all(|&sample| sample == 0));
+}
@@ -0,0 +1,946 @@
+// tests/common/flac_tests.rs
+
+// Bit-exact conformance tests running the FLAC decoder over the corpus in tests/data
+
+use super::{
+    corpus_cases, corpus_file, flacenc_cases, frame_offsets, interleave, open_flac, read_frames,
+    read_wav, rfc_9639_example, temp_file,
+};
+use playback::codecs::flac::{
+    data::Metadata,
+    decoder::{CrcPolicy, Md5Status, RecoveryMode},
+    frame::FrameHeader,
+    index::{self, FrameIndex, FrameIndexMode, IndexKey},
+    subframe::SubframeType,
+};
+use playback::common::{
+    block::DecodedBlock, errors::AudioError, position::Position, stream::Stream,
+};
+use std::{fs, ops::Range, path::Path, time::Duration};
+
+// Decode a corpus file and compare it sample by sample with its reference WAV
+fn assert_matches_reference(path: &Path) {
+    let name = path.file_name().unwrap().to_string_lossy();
+    let frames =
+        read_frames(path).unwrap_or_else(|err| panic!("{} failed to decode: {}", name, err));
+    let wav = read_wav(&path.with_extension("wav"));
+
+    let (_, flac) = open_flac(path).unwrap();
+    let metadata = flac.get_metadata();
+    assert_eq!(
+        metadata.get_sample_rate(),
+        Some(wav.sample_rate),
+        "{}",
+        name
+    );
+    assert_eq!(
+        metadata.get_num_channels(),
+        Some(wav.num_channels as u8),
+        "{}",
+        name
+    );
+    assert_eq!(
+        metadata.get_bit_depth(),
+        Some(wav.bit_depth as u8),
+        "{}",
+        name
+    );
+    assert_eq!(
+        metadata.get_total_samples(),
+        Some((wav.samples.len() / wav.num_channels as usize) as u64),
+        "{}",
+        name
+    );
+
+    for frame in &frames {
+        assert_eq!(frame.get_sample_rate(), wav.sample_rate, "{}", name);
+        assert_eq!(frame.get_bit_depth() as u16, wav.bit_depth, "{}", name);
+        assert_eq!(
+            frame.get_num_channels(),
+            wav.num_channels as usize,
+            "{}",
+            name
+        );
+    }
+
+    let samples = interleave(&frames);
+    assert_eq!(samples.len(), wav.samples.len(), "{}: sample count", name);
+    if let Some(pos) = samples.iter().zip(&wav.samples).position(|(a, b)| a != b) {
+        panic!(
+            "{}: sample {} decoded as {}, expected {}",
+            name, pos, samples[pos], wav.samples[pos]
+        );
+    }
+}
+
+// Decode a corpus file through FLAC::verify and return the MD5 check result
+fn verify(path: &Path) -> Md5Status {
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    flac.verify(&mut stream).unwrap()
+}
+
+fn assert_group_matches_reference(prefix: &str) {
+    for path in corpus_cases(prefix) {
+        assert_matches_reference(&path);
+        assert_eq!(verify(&path), Md5Status::Match, "{}", path.display());
+    }
+}
+
+#[test]
+fn subframe_types_match_reference() {
+    assert_group_matches_reference("subframe_");
+}
+
+#[test]
+fn residual_codings_match_reference() {
+    assert_group_matches_reference("residual_");
+}
+
+#[test]
+fn channel_assignments_match_reference() {
+    assert_group_matches_reference("channels_");
+}
+
+#[test]
+fn bit_depths_match_reference() {
+    assert_group_matches_reference("bit_depth_");
+}
+
+#[test]
+fn block_size_codes_match_reference() {
+    assert_group_matches_reference("block_size_code_");
+}
+
+#[test]
+fn sample_rate_codes_match_reference() {
+    assert_group_matches_reference("sample_rate_code_");
+}
+
+#[test]
+fn blocking_strategies_match_reference() {
+    assert_group_matches_reference("fixed_block_size_");
+    assert_group_matches_reference("variable_block_size");
+}
+
+#[test]
+fn metadata_blocks_are_parsed_or_skipped() {
+    assert_group_matches_reference("metadata_");
+}
+
+#[test]
+fn corpus_covers_every_bit_depth() {
+    for bit_depth in 4..=32 {
+        let path = corpus_file(&format!("bit_depth_{}.flac", bit_depth));
+        assert!(path.exists(), "missing {}", path.display());
+    }
+}
+
+#[test]
+fn rfc_9639_examples_match_libflac() {
+    // Interleaved samples as worked through in RFC 9639 Appendix D
+    let expected: [&[i32]; 3] = [
+        &[25588, 10416],
+        &[
+            10372, 6070, 18041, 10545, 14942, 8743, 17876, 10449, 15627, 9143, 17899, 10463, 16242,
+            9502, 18077, 10569, 16824, 9840, 18263, 10680, 17295, 10113, -14418, -8428, -15201,
+            -8895, -14508, -8476, -15195, -8896, -14818, -8653, -15486, -9072, -15349, -8958,
+            -16054, -9410,
+        ],
+        &[
+            0, 79, 111, 78, 8, -61, -90, -68, -13, 42, 67, 53, 13, -27, -46, -38, -12, 14, 24, 19,
+            6, -4, -5, 0,
+        ],
+    ];
+    for (i, samples) in expected.iter().enumerate() {
+        let path = rfc_9639_example(i + 1);
+        let decoded = interleave(&read_frames(&path).unwrap());
+        assert_eq!(decoded, *samples, "example {}", i + 1);
+        assert_eq!(verify(&path), Md5Status::Match, "example {}", i + 1);
+    }
+
+    // The second example ends in a short frame, after a seek table and Vorbis comments
+    let (_, flac) = open_flac(&rfc_9639_example(2)).unwrap();
+    let metadata = flac.get_metadata();
+    assert_eq!(metadata.get_total_samples(), Some(19));
+    assert_eq!(metadata.get_seek_points().len(), 1);
+    assert_eq!(
+        metadata.get_vendor(),
+        Some("reference libFLAC 1.3.3 20190804")
+    );
+    assert_eq!(metadata.get_comment("title"), Some("שלום"));
+
+    // The third is 8-bit mono at 32 kHz
+    let (_, flac) = open_flac(&rfc_9639_example(3)).unwrap();
+    let metadata = flac.get_metadata();
+    assert_eq!(metadata.get_sample_rate(), Some(32000));
+    assert_eq!(metadata.get_num_channels(), Some(1));
+    assert_eq!(metadata.get_bit_depth(), Some(8));
+}
+
+#[test]
+fn flacenc_files_match_reference() {
+    for path in flacenc_cases() {
+        assert_matches_reference(&path);
+        assert_eq!(verify(&path), Md5Status::Match, "{}", path.display());
+    }
+}
+
+#[test]
+fn subframe_types_and_orders_are_decoded() {
+    let expected = [
+        ("subframe_constant.flac", SubframeType::Constant, 0),
+        ("subframe_verbatim.flac", SubframeType::Verbatim, 0),
+        ("subframe_fixed_order_0.flac", SubframeType::Fixed, 0),
+        ("subframe_fixed_order_4.flac", SubframeType::Fixed, 4),
+        ("subframe_lpc_order_1.flac", SubframeType::LPC, 1),
+        ("subframe_lpc_order_32.flac", SubframeType::LPC, 32),
+    ];
+
+    for (name, subframe_type, order) in expected {
+        let frames = read_frames(&corpus_file(name)).unwrap();
+        let subframe = &frames[0].get_subframes()[0];
+        assert_eq!(subframe.get_type(), &subframe_type, "{}", name);
+        assert_eq!(subframe.get_order(), order, "{}", name);
+    }
+}
+
+#[test]
+fn wasted_bits_are_decoded() {
+    let frames = read_frames(&corpus_file("subframe_wasted_bits.flac")).unwrap();
+    assert_eq!(frames[0].get_subframes()[0].get_wasted_bits(), 4);
+}
+
+#[test]
+fn side_channel_of_32_bit_stereo_is_decoded() {
+    let path = corpus_file("bit_depth_32_side_channel.flac");
+    assert_matches_reference(&path);
+    assert_eq!(verify(&path), Md5Status::Match);
+
+    // The side channel of opposite full scale channels leaves the 32-bit range
+    let frames = read_frames(&path).unwrap();
+    let side = frames[0].get_subframes()[0].get_data();
+    assert_eq!(frames[0].get_channel_assignment(), 9);
+    assert!(side.iter().any(|&sample| i32::try_from(sample).is_err()));
+}
+
+#[test]
+fn stereo_channel_assignments_are_decoded() {
+    let expected = [
+        ("channels_stereo_independent.flac", 1),
+        ("channels_stereo_left_side.flac", 8),
+        ("channels_stereo_side_right.flac", 9),
+        ("channels_stereo_mid_side.flac", 10),
+    ];
+
+    for (name, channel_assignment) in expected {
+        for frame in read_frames(&corpus_file(name)).unwrap() {
+            assert_eq!(
+                frame.get_channel_assignment(),
+                channel_assignment,
+                "{}",
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn frame_positions_follow_blocking_strategy() {
+    for name in [
+        "fixed_block_size_short_last_frame.flac",
+        "fixed_block_size_many_frames.flac",
+        "variable_block_size.flac",
+        "variable_block_size_large_sample_number.flac",
+    ] {
+        let mut next_sample = 0;
+        for frame in read_frames(&corpus_file(name)).unwrap() {
+            assert_eq!(frame.get_first_sample(), next_sample, "{}", name);
+            next_sample += frame.get_block_size() as u64;
+        }
+    }
+}
+
+#[test]
+fn frame_timestamps_are_computed_from_first_sample() {
+    let frames = read_frames(&corpus_file("fixed_block_size_short_last_frame.flac")).unwrap();
+    let frame = &frames[2];
+    assert_eq!(frame.get_first_sample(), 2304);
+    assert_eq!(
+        frame.get_timestamp().as_nanos(),
+        2304 * 1_000_000_000 / 44100
+    );
+}
+
+#[test]
+fn unset_md5_signature_is_reported() {
+    let path = corpus_file("md5_unset.flac");
+    assert_matches_reference(&path);
+    assert_eq!(verify(&path), Md5Status::Unset);
+}
+
+#[test]
+fn altered_md5_signature_is_a_mismatch() {
+    // The signature is the last 16 bytes of STREAMINFO, after the 4-byte marker and block header
+    let path = corpus_file("seek_table.flac");
+    assert_eq!(verify(&path), Md5Status::Match);
+
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[8 + 33] ^= 0x01;
+    let altered = temp_file("md5_altered.flac", &bytes);
+    let (expected, _) = decode_with_policy(&path, CrcPolicy::Error).unwrap();
+    let (samples, md5_status) = decode_with_policy(&altered, CrcPolicy::Error).unwrap();
+    fs::remove_file(altered).unwrap();
+
+    // The audio itself decodes unchanged
+    assert_eq!(samples, expected);
+    assert_eq!(md5_status, Md5Status::Mismatch);
+}
+
+#[test]
+fn damaged_frame_fails_crc_check() {
+    let mut bytes = fs::read(corpus_file("subframe_verbatim.flac")).unwrap();
+    let last = bytes.len() - 10;
+    bytes[last] ^= 0x01;
+    let path = temp_file("damaged_frame.flac", &bytes);
+
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    flac.set_crc_policy(CrcPolicy::Error);
+    let result = flac.decode(&mut stream);
+    assert!(matches!(result, Err(AudioError::CrcMismatch(_))));
+
+    // A flipped bit in a verbatim sample still decodes when CRCs are ignored
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    flac.set_crc_policy(CrcPolicy::Ignore);
+    flac.decode(&mut stream).unwrap();
+    assert_eq!(flac.get_md5_status(), Some(Md5Status::Mismatch));
+
+    fs::remove_file(path).unwrap();
+}
+
+// Decode a file with the given CRC policy, along with the outcome of the MD5 check
+fn decode_with_policy(path: &Path, policy: CrcPolicy) -> Result<(Vec<i32>, Md5Status), AudioError> {
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    flac.set_crc_policy(policy);
+    let samples = flac.decode(&mut stream)?;
+    Ok((samples, flac.get_md5_status().unwrap()))
+}
+
+/**
+ * Check that a copy of a corpus file with one flipped byte fails to decode
+ * only under CrcPolicy::Error, and otherwise decodes to the reference
+ */
+fn assert_crc_policies(name: &str, damage: impl Fn(&mut Vec<u8>, &[u64])) {
+    let original = corpus_file(name);
+    let mut bytes = fs::read(&original).unwrap();
+    let offsets = frame_offsets(&bytes, name);
+    damage(&mut bytes, &offsets);
+    let path = temp_file(name, &bytes);
+
+    let result = decode_with_policy(&path, CrcPolicy::Error);
+    assert!(
+        matches!(result, Err(AudioError::CrcMismatch(_))),
+        "{}",
+        name
+    );
+
+    let (expected, _) = decode_with_policy(&original, CrcPolicy::Error).unwrap();
+    for policy in [CrcPolicy::Warn, CrcPolicy::Ignore] {
+        let (samples, md5_status) = decode_with_policy(&path, policy).unwrap();
+        assert_eq!(samples, expected, "{} with {:?}", name, policy);
+        assert_eq!(md5_status, Md5Status::Match, "{} with {:?}", name, policy);
+    }
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn damaged_header_crc_follows_policy() {
+    // The byte before a frame's first subframe is its header CRC-8
+    assert_crc_policies("subframe_verbatim.flac", |bytes, offsets| {
+        let mut stream = Stream::from_bytes(bytes.clone());
+        stream.seek_to(offsets[0]).unwrap();
+        let metadata = Metadata::from_stream_info(&bytes[8..42]).unwrap();
+        FrameHeader::read_next_header(&mut stream, &metadata, CrcPolicy::Error).unwrap();
+        bytes[stream.get_position().unwrap() as usize - 1] ^= 0x01;
+    });
+}
+
+#[test]
+fn damaged_frame_crc_follows_policy() {
+    // The two bytes before a frame's successor are its CRC-16
+    assert_crc_policies("seek_table.flac", |bytes, offsets| {
+        bytes[offsets[1] as usize - 1] ^= 0x80;
+    });
+    assert_crc_policies("channels_6.flac", |bytes, _| {
+        *bytes.last_mut().unwrap() ^= 0x01;
+    });
+}
+
+// Decode a file with the given recovery mode and number of threads
+fn decode_with(
+    path: &Path,
+    recovery_mode: RecoveryMode,
+    num_threads: usize,
+) -> (Result<Vec<i32>, AudioError>, Vec<Range<u64>>) {
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    flac.set_recovery_mode(recovery_mode);
+    flac.set_num_threads(num_threads);
+    let result = flac.decode(&mut stream);
+    (result, flac.get_lost_ranges().to_vec())
+}
+
+// Decode a damaged copy of a corpus file with the given recovery mode
+fn decode_damaged(
+    bytes: &[u8],
+    name: &str,
+    recovery_mode: RecoveryMode,
+) -> (Result<Vec<i32>, AudioError>, Vec<Range<u64>>) {
+    let path = temp_file(name, bytes);
+    let decoded = decode_with(&path, recovery_mode, 1);
+    fs::remove_file(path).unwrap();
+    decoded
+}
+
+#[test]
+fn recovery_fills_damaged_frame_with_silence() {
+    // Mono, frames of 1152 samples
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let reference = read_wav(&path.with_extension("wav")).samples;
+    let mut bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "recovery_offsets.flac");
+    assert_eq!(offsets.len(), 5);
+
+    // Damage the middle of the second frame
+    let middle = ((offsets[1] + offsets[2]) / 2) as usize;
+    bytes[middle] ^= 0xFF;
+
+    let (result, _) = decode_damaged(&bytes, "recovery_disabled.flac", RecoveryMode::Disabled);
+    assert!(matches!(result, Err(AudioError::CrcMismatch(_))));
+
+    let (result, lost_ranges) =
+        decode_damaged(&bytes, "recovery_silence.flac", RecoveryMode::FillSilence);
+    let samples = result.unwrap();
+    assert_eq!(lost_ranges, vec![1152..2304]);
+    assert_eq!(samples.len(), reference.len());
+    assert!(samples[1152..2304].iter().all(|&sample| sample == 0));
+    assert_eq!(samples[..1152], reference[..1152]);
+    assert_eq!(samples[2304..], reference[2304..]);
+
+    let (result, lost_ranges) = decode_damaged(&bytes, "recovery_skip.flac", RecoveryMode::Skip);
+    let samples = result.unwrap();
+    assert_eq!(lost_ranges, vec![1152..2304]);
+    assert_eq!(samples.len(), reference.len() - 1152);
+    assert_eq!(samples[1152..], reference[2304..]);
+}
+
+#[test]
+fn recovery_skips_garbage_between_frames() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let reference = read_wav(&path.with_extension("wav")).samples;
+    let bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "garbage_offsets.flac");
+
+    // Junk that includes false sync codes, inserted before the third frame
+    let insert_at = offsets[2] as usize;
+    let junk = [0x12, 0xFF, 0xF8, 0x00, 0xFF, 0xF9, 0xC9, 0x18, 0x00, 0xFF];
+    let mut damaged = bytes[..insert_at].to_vec();
+    damaged.extend_from_slice(&junk);
+    damaged.extend_from_slice(&bytes[insert_at..]);
+
+    let (result, lost_ranges) =
+        decode_damaged(&damaged, "garbage_skip.flac", RecoveryMode::FillSilence);
+    assert_eq!(result.unwrap(), reference);
+    assert!(lost_ranges.is_empty());
+}
+
+#[test]
+fn recovery_reports_truncated_stream() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let reference = read_wav(&path.with_extension("wav")).samples;
+    let bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "truncated_offsets.flac");
+
+    // Cut the file in the middle of the fourth frame
+    let cut = ((offsets[3] + offsets[4]) / 2) as usize;
+    let (result, lost_ranges) =
+        decode_damaged(&bytes[..cut], "truncated.flac", RecoveryMode::FillSilence);
+    let samples = result.unwrap();
+    assert_eq!(lost_ranges, vec![3456..5000]);
+    assert_eq!(samples.len(), reference.len());
+    assert_eq!(samples[..3456], reference[..3456]);
+}
+
+#[test]
+fn frame_decoder_yields_one_block_per_frame() {
+    let path = corpus_file("channels_stereo_mid_side.flac");
+    let frames = read_frames(&path).unwrap();
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+
+    let mut num_blocks = 0;
+    for (block, frame) in flac.frames(&mut stream).zip(&frames) {
+        let block = block.unwrap();
+        assert_eq!(block.get_first_sample(), frame.get_first_sample());
+        assert_eq!(block.get_block_size(), frame.get_block_size() as usize);
+        assert_eq!(block.get_num_channels(), 2);
+        assert_eq!(block.get_sample_rate(), frame.get_sample_rate());
+        assert_eq!(block.get_bit_depth(), frame.get_bit_depth());
+        assert_eq!(block.get_channels(), frame.get_channels());
+        num_blocks += 1;
+    }
+    assert_eq!(num_blocks, frames.len());
+
+    // The MD5 is checked once the iterator has been drained
+    assert_eq!(flac.get_md5_status(), Some(Md5Status::Match));
+}
+
+#[test]
+fn frame_decoder_yields_silence_for_lost_samples() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let mut bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "streaming_offsets.flac");
+    let middle = ((offsets[1] + offsets[2]) / 2) as usize;
+    bytes[middle] ^= 0xFF;
+
+    let damaged = temp_file("streaming_damaged.flac", &bytes);
+    let (mut stream, mut flac) = open_flac(&damaged).unwrap();
+    flac.set_recovery_mode(RecoveryMode::FillSilence);
+    let first_samples: Vec<u64> = flac
+        .frames(&mut stream)
+        .map(|block| block.unwrap().get_first_sample())
+        .collect();
+    assert_eq!(first_samples, vec![0, 1152, 2304, 3456, 4608]);
+    fs::remove_file(damaged).unwrap();
+}
+
+// Seek to a sample and decode the rest of the stream, interleaving the channels
+fn decode_from(path: &Path, sample: u64) -> Vec<i32> {
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    flac.seek_to_sample(&mut stream, sample).unwrap();
+
+    let mut samples = Vec::new();
+    for (index, block) in flac.frames(&mut stream).enumerate() {
+        let block = block.unwrap();
+        if index == 0 {
+            assert_eq!(block.get_first_sample(), sample);
+        }
+        let channels = block.get_channels();
+        for i in 0..block.get_block_size() {
+            samples.extend(channels.iter().map(|channel| channel[i]));
+        }
+    }
+
+    // Only a decode of the whole stream checks the MD5
+    assert_eq!(flac.get_md5_status(), None);
+    samples
+}
+
+// Seek to each target and compare the rest of the stream with a reference WAV
+fn assert_seeks_match_reference(path: &Path, reference: &Path, targets: &[u64]) {
+    let wav = read_wav(reference);
+    let num_channels = wav.num_channels as usize;
+    for &sample in targets {
+        let samples = decode_from(path, sample);
+        assert_eq!(
+            samples,
+            wav.samples[sample as usize * num_channels..],
+            "{} from sample {}",
+            path.display(),
+            sample
+        );
+    }
+}
+
+#[test]
+fn seek_uses_seek_table() {
+    let path = corpus_file("seek_table.flac");
+    let (_, flac) = open_flac(&path).unwrap();
+    let points = flac.get_metadata().get_seek_points();
+    assert_eq!(points.len(), 5);
+    assert_eq!(points[1].get_sample_number(), 4608);
+
+    assert_seeks_match_reference(
+        &path,
+        &path.with_extension("wav"),
+        &[0, 1, 1151, 1152, 4608, 12345, 19999],
+    );
+}
+
+#[test]
+fn seek_bisects_without_seek_table() {
+    // Turn the SEEKTABLE, the last metadata block, into PADDING
+    let mut bytes = fs::read(corpus_file("seek_table.flac")).unwrap();
+    assert_eq!(bytes[42], 0x83);
+    bytes[42] = 0x81;
+    let path = temp_file("seek_without_table.flac", &bytes);
+    let (_, flac) = open_flac(&path).unwrap();
+    assert!(flac.get_metadata().get_seek_points().is_empty());
+
+    let reference = corpus_file("seek_table.wav");
+    assert_seeks_match_reference(&path, &reference, &[0, 1000, 9999, 10368, 18431, 19999]);
+    fs::remove_file(path).unwrap();
+
+    let path = corpus_file("variable_block_size.flac");
+    let reference = path.with_extension("wav");
+    assert_seeks_match_reference(&path, &reference, &[0, 1151, 1152, 6527, 11724, 11725]);
+}
+
+#[test]
+fn seek_ignores_seek_points_that_miss_frames() {
+    // The second seek point claims sample 1152 is at the first frame
+    let path = corpus_file("metadata_all_blocks.flac");
+    assert_seeks_match_reference(&path, &path.with_extension("wav"), &[1200, 2303]);
+}
+
+#[test]
+fn seek_past_end_fails() {
+    let (mut stream, mut flac) = open_flac(&corpus_file("seek_table.flac")).unwrap();
+    let result = flac.seek_to_sample(&mut stream, 20000);
+    assert!(matches!(result, Err(AudioError::InvalidData(_))));
+}
+
+#[test]
+fn parallel_decode_matches_serial() {
+    for path in corpus_cases("") {
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        let serial = flac.decode(&mut stream).unwrap();
+        let md5_status = flac.get_md5_status();
+
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        flac.set_num_threads(4);
+        assert_eq!(
+            flac.decode(&mut stream).unwrap(),
+            serial,
+            "{}",
+            path.display()
+        );
+        assert_eq!(flac.get_md5_status(), md5_status, "{}", path.display());
+    }
+}
+
+#[test]
+fn parallel_decode_recovers_like_serial() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "parallel_offsets.flac");
+
+    let mut damaged = bytes.clone();
+    damaged[((offsets[1] + offsets[2]) / 2) as usize] ^= 0xFF;
+    let mut garbage = bytes[..offsets[2] as usize].to_vec();
+    garbage.extend_from_slice(&[0x12, 0xFF, 0xF8, 0x00, 0xFF, 0xF9, 0xC9, 0x18, 0x00, 0xFF]);
+    garbage.extend_from_slice(&bytes[offsets[2] as usize..]);
+    let truncated = bytes[..((offsets[3] + offsets[4]) / 2) as usize].to_vec();
+
+    for (name, bytes) in [
+        ("parallel_damaged.flac", damaged),
+        ("parallel_garbage.flac", garbage),
+        ("parallel_truncated.flac", truncated),
+    ] {
+        let path = temp_file(name, &bytes);
+        for recovery_mode in [
+            RecoveryMode::Disabled,
+            RecoveryMode::Skip,
+            RecoveryMode::FillSilence,
+        ] {
+            let (serial, serial_lost) = decode_with(&path, recovery_mode, 1);
+            let (parallel, parallel_lost) = decode_with(&path, recovery_mode, 3);
+            match (serial, parallel) {
+                (Ok(serial), Ok(parallel)) => assert_eq!(serial, parallel, "{}", name),
+                (Err(serial), Err(parallel)) => assert_eq!(
+                    std::mem::discriminant(&serial),
+                    std::mem::discriminant(&parallel),
+                    "{}",
+                    name
+                ),
+                (serial, parallel) => panic!("{}: {:?} vs {:?}", name, serial, parallel),
+            }
+            assert_eq!(serial_lost, parallel_lost, "{}", name);
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn parallel_decode_follows_crc_policy_like_serial() {
+    let path = corpus_file("fixed_block_size_short_last_frame.flac");
+    let mut bytes = fs::read(&path).unwrap();
+    let offsets = frame_offsets(&bytes, "parallel_crc_offsets.flac");
+
+    // Damage the header CRC-8 of the third frame
+    let mut stream = Stream::from_bytes(bytes.clone());
+    stream.seek_to(offsets[2]).unwrap();
+    let metadata = Metadata::from_stream_info(&bytes[8..42]).unwrap();
+    FrameHeader::read_next_header(&mut stream, &metadata, CrcPolicy::Error).unwrap();
+    bytes[stream.get_position().unwrap() as usize - 1] ^= 0x01;
+    let path = temp_file("parallel_crc.flac", &bytes);
+
+    let decode = |crc_policy, recovery_mode, num_threads| {
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        flac.set_crc_policy(crc_policy);
+        flac.set_recovery_mode(recovery_mode);
+        flac.set_num_threads(num_threads);
+        let samples = flac.decode(&mut stream).unwrap();
+        (
+            samples,
+            flac.get_lost_ranges().to_vec(),
+            flac.get_md5_status(),
+        )
+    };
+    for crc_policy in [CrcPolicy::Warn, CrcPolicy::Ignore] {
+        for recovery_mode in [
+            RecoveryMode::Disabled,
+            RecoveryMode::Skip,
+            RecoveryMode::FillSilence,
+        ] {
+            let serial = decode(crc_policy, recovery_mode, 1);
+            assert_eq!(serial.1, vec![]);
+            assert_eq!(serial.2, Some(Md5Status::Match));
+            assert_eq!(
+                decode(crc_policy, recovery_mode, 3),
+                serial,
+                "{:?} {:?}",
+                crc_policy,
+                recovery_mode
+            );
+        }
+    }
+
+    fs::remove_file(path).unwrap();
+}
+
+// Decode a range of a file, returning the interleaved samples
+fn decode_range(path: &Path, start: Position, end: Position) -> Vec<i32> {
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    interleave_blocks(flac.frames_range(&mut stream, start, end).unwrap())
+}
+
+#[test]
+fn range_decode_stops_at_end_sample() {
+    let path = corpus_file("seek_table.flac");
+    let wav = read_wav(&path.with_extension("wav"));
+    for (start, end) in [
+        (0, 1),
+        (1151, 1153),
+        (4608, 5760),
+        (12345, 19000),
+        (3, 20000),
+    ] {
+        assert_eq!(
+            decode_range(&path, Position::Sample(start), Position::Sample(end)),
+            wav.samples[start as usize * 2..end as usize * 2],
+            "{}..{}",
+            start,
+            end
+        );
+
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        let samples = flac.decode_range(&mut stream, start, end).unwrap();
+        assert_eq!(samples.len() as u64, (end - start) * 2);
+    }
+
+    // An end past the stream stops at its last sample, and an empty range yields nothing
+    let tail = decode_range(&path, Position::Sample(19990), Position::Sample(u64::MAX));
+    assert_eq!(tail, wav.samples[19990 * 2..]);
+    for sample in [0, 777, 20000] {
+        assert!(decode_range(&path, Position::Sample(sample), Position::Sample(sample)).is_empty());
+    }
+}
+
+#[test]
+fn range_decode_accepts_times() {
+    let path = corpus_file("seek_table.flac");
+    let wav = read_wav(&path.with_extension("wav"));
+
+    // 44100 Hz, so 100 ms is 4410 samples
+    let start = Position::Time(Duration::from_millis(100));
+    let end = Position::Time(Duration::from_millis(300));
+    assert_eq!(start.to_sample(44100), 4410);
+    assert_eq!(
+        decode_range(&path, start, end),
+        wav.samples[4410 * 2..13230 * 2]
+    );
+    assert_eq!(
+        Position::from(Duration::from_micros(22)).to_sample(44100),
+        0
+    );
+    assert_eq!(
+        Position::from(Duration::from_micros(23)).to_sample(44100),
+        1
+    );
+}
+
+#[test]
+fn range_decode_rejects_reversed_ranges() {
+    let (mut stream, mut flac) = open_flac(&corpus_file("seek_table.flac")).unwrap();
+    assert!(matches!(
+        flac.decode_range(&mut stream, 200, 100),
+        Err(AudioError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn md5_is_checked_only_when_decoding_reaches_the_end() {
+    let path = corpus_file("seek_table.flac");
+
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    assert_eq!(flac.frames(&mut stream).until(20000).count(), 18);
+    assert_eq!(flac.get_md5_status(), Some(Md5Status::Match));
+
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    assert_eq!(flac.frames(&mut stream).until(19999).count(), 18);
+    assert_eq!(flac.get_md5_status(), None);
+}
+
+#[test]
+fn frame_index_lists_every_frame() {
+    for path in corpus_cases("") {
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        let first_frame = flac.get_metadata().get_first_frame_offset().unwrap();
+        let frames = read_frames(&path).unwrap();
+
+        let entries = flac.get_frame_index(&mut stream).unwrap().get_entries();
+        assert_eq!(entries.len(), frames.len(), "{}", path.display());
+        assert_eq!(entries[0].get_offset(), first_frame, "{}", path.display());
+        for (entry, frame) in entries.iter().zip(&frames) {
+            assert_eq!(entry.get_first_sample(), frame.get_first_sample());
+            assert_eq!(entry.get_block_size(), frame.get_block_size());
+        }
+
+        // Building the index leaves the stream at the first frame
+        assert_eq!(stream.get_position().unwrap(), first_frame);
+    }
+}
+
+#[test]
+fn frame_index_lists_frames_after_damaged_frame() {
+    let original = corpus_file("seek_table.flac");
+    let (mut stream, mut flac) = open_flac(&original).unwrap();
+    let expected = flac.get_frame_index(&mut stream).unwrap().clone();
+
+    // Damage the middle of the body of a frame halfway through the stream
+    let mut bytes = fs::read(&original).unwrap();
+    let offsets = frame_offsets(&bytes, "index_damaged.flac");
+    let damaged = offsets.len() / 2;
+    let middle = (offsets[damaged] + offsets[damaged + 1]) / 2;
+    bytes[middle as usize] ^= 0xFF;
+    let path = temp_file("index_damaged.flac", &bytes);
+
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    flac.set_frame_index_mode(FrameIndexMode::InMemory);
+    let frame_index = flac.get_frame_index(&mut stream).unwrap();
+    assert_eq!(frame_index.get_entries(), expected.get_entries());
+
+    // Seeks past the damaged frame still land on the sample sought
+    let reference = read_wav(&corpus_file("seek_table.wav"));
+    let sample = expected.get_entries()[damaged + 1].get_first_sample() + 100;
+    flac.seek_to_sample(&mut stream, sample).unwrap();
+    assert_eq!(
+        interleave_blocks(flac.frames(&mut stream)),
+        reference.samples[sample as usize * 2..]
+    );
+
+    fs::remove_file(path).unwrap();
+}
+
+// Seek with a frame index to each target and compare the rest of the stream with the reference
+fn assert_indexed_seeks_match_reference(path: &Path, mode: FrameIndexMode, targets: &[u64]) {
+    let wav = read_wav(&path.with_extension("wav"));
+    let num_channels = wav.num_channels as usize;
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    flac.set_frame_index_mode(mode);
+    for &sample in targets {
+        flac.seek_to_sample(&mut stream, sample).unwrap();
+        let samples = interleave_blocks(flac.frames(&mut stream));
+        assert_eq!(
+            samples,
+            wav.samples[sample as usize * num_channels..],
+            "{} from sample {}",
+            path.display(),
+            sample
+        );
+    }
+}
+
+#[test]
+fn seek_uses_frame_index() {
+    let targets = [0, 1, 1151, 1152, 4608, 12345, 19999];
+    assert_indexed_seeks_match_reference(
+        &corpus_file("seek_table.flac"),
+        FrameIndexMode::InMemory,
+        &targets,
+    );
+    assert_indexed_seeks_match_reference(
+        &corpus_file("variable_block_size.flac"),
+        FrameIndexMode::InMemory,
+        &[0, 5000, 11725],
+    );
+}
+
+#[test]
+fn frame_index_persists_in_sidecar() {
+    let bytes = fs::read(corpus_file("seek_table.flac")).unwrap();
+    let path = temp_file("indexed.flac", &bytes);
+    let sidecar = index::sidecar_path(&path);
+    let _ = fs::remove_file(&sidecar);
+
+    // The first seek builds the index and saves it next to the file
+    assert_indexed_seeks_match_reference_at(&path, &[12345]);
+    assert!(sidecar.exists());
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    let key = IndexKey::new(&path, flac.get_metadata()).unwrap();
+    let built = flac.get_frame_index(&mut stream).unwrap().clone();
+    assert_eq!(FrameIndex::load(&sidecar, &key), Some(built.clone()));
+
+    // Later opens load the sidecar, which is only read, never rebuilt
+    let modified = fs::metadata(&sidecar).unwrap().modified().unwrap();
+    assert_indexed_seeks_match_reference_at(&path, &[4608, 0]);
+    assert_eq!(
+        fs::metadata(&sidecar).unwrap().modified().unwrap(),
+        modified
+    );
+
+    // A sidecar from a different file is ignored and replaced
+    let mut other_bytes = bytes.clone();
+    other_bytes.extend_from_slice(&[0; 8]);
+    fs::write(&path, &other_bytes).unwrap();
+    let (_, flac) = open_flac(&path).unwrap();
+    let other_key = IndexKey::new(&path, flac.get_metadata()).unwrap();
+    assert_eq!(FrameIndex::load(&sidecar, &other_key), None);
+    fs::write(&path, &bytes).unwrap();
+
+    // So is a sidecar that cannot be read
+    fs::write(&sidecar, b"FIDX\x01garbage").unwrap();
+    assert_indexed_seeks_match_reference_at(&path, &[19999]);
+    let (_, flac) = open_flac(&path).unwrap();
+    let key = IndexKey::new(&path, flac.get_metadata()).unwrap();
+    assert_eq!(FrameIndex::load(&sidecar, &key), Some(built));
+
+    fs::remove_file(sidecar).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+// Seek in a copy of seek_table.flac using a persistent frame index
+fn assert_indexed_seeks_match_reference_at(path: &Path, targets: &[u64]) {
+    let reference = read_wav(&corpus_file("seek_table.wav"));
+    let (mut stream, mut flac) = open_flac(path).unwrap();
+    flac.set_frame_index_mode(FrameIndexMode::Persistent);
+    for &sample in targets {
+        flac.seek_to_sample(&mut stream, sample).unwrap();
+        assert_eq!(
+            interleave_blocks(flac.frames(&mut stream)),
+            reference.samples[sample as usize * 2..]
+        );
+    }
+}
+
+// Collect decoded blocks into interleaved samples
+fn interleave_blocks(blocks: impl Iterator<Item = Result<DecodedBlock, AudioError>>) -> Vec<i32> {
+    let mut samples = Vec::new();
+    for block in blocks {
+        let block = block.unwrap();
+        let channels = block.get_channels();
+        for i in 0..block.get_block_size() {
+            samples.extend(channels.iter().map(|channel| channel[i]));
+        }
+    }
+    samples
+}
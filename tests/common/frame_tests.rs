@@ -0,0 +1,327 @@
+// tests/common/frame_tests.rs
+
+// Tests parsing frame headers built by hand
+
+use super::{coded_number, open_flac, stream_info, temp_file, with_crc16, with_crc8, BitWriter};
+use playback::codecs::flac::{
+    data::Metadata,
+    decoder::{ArithmeticMode, CrcPolicy},
+    frame::{Frame, FrameHeader},
+};
+use playback::common::{errors::AudioError, stream::Stream};
+use std::{fs, time::Duration};
+
+// Block size codes, the 8 and 16-bit ones followed by one less than the block size
+const BLOCK_SIZE_4096: u8 = 12;
+const BLOCK_SIZE_8_BIT: u8 = 6;
+const BLOCK_SIZE_16_BIT: u8 = 7;
+
+const SAMPLE_RATE_44100: u8 = 9;
+
+// Channel assignment and bit depth byte of a header, as the assignment then the bit depth code
+const MONO_16_BIT: u8 = 4 << 1;
+const LEFT_SIDE_32_BIT: u8 = 8 << 4 | 7 << 1;
+
+// Subframe type codes
+const CONSTANT: u64 = 0;
+const VERBATIM: u64 = 1;
+
+/**
+ * Build a frame header at 44.1 kHz, with the blocking strategy bit, frame
+ * or sample number, block size code, any block size bits following the
+ * number, and the channel assignment and bit depth byte
+ */
+fn header(
+    variable: bool,
+    number: u64,
+    block_size_code: u8,
+    block_size: &[u8],
+    channels: u8,
+) -> Vec<u8> {
+    header_with_number(
+        variable,
+        &coded_number(number),
+        block_size_code,
+        block_size,
+        channels,
+    )
+}
+
+// Build a frame header as above, around the bytes of an already coded number
+fn header_with_number(
+    variable: bool,
+    number: &[u8],
+    block_size_code: u8,
+    block_size: &[u8],
+    channels: u8,
+) -> Vec<u8> {
+    let mut bytes = vec![
+        0xFF,
+        0xF8 | variable as u8,
+        block_size_code << 4 | SAMPLE_RATE_44100,
+        channels,
+    ];
+    bytes.extend(number);
+    bytes.extend(block_size);
+    with_crc8(bytes)
+}
+
+fn read_header(bytes: Vec<u8>, metadata: &Metadata) -> Result<FrameHeader, AudioError> {
+    FrameHeader::read_next_header(&mut Stream::from_bytes(bytes), metadata, CrcPolicy::Error)
+}
+
+// Metadata of a stream whose only metadata block is padding
+fn without_stream_info() -> Metadata {
+    Metadata::new(&mut Stream::from_bytes(vec![0x81, 0, 0, 0])).unwrap()
+}
+
+#[test]
+fn block_size_field_cannot_hold_65536() {
+    let metadata = without_stream_info();
+    let header_of =
+        |size: u16| header(true, 0, BLOCK_SIZE_16_BIT, &size.to_be_bytes(), MONO_16_BIT);
+
+    let parsed = read_header(header_of(0xFFFE), &metadata).unwrap();
+    assert_eq!(parsed.get_block_size(), 65535);
+    assert!(matches!(
+        read_header(header_of(0xFFFF), &metadata),
+        Err(AudioError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn fixed_frames_start_at_multiples_of_the_nominal_block_size() {
+    let metadata = Metadata::from_stream_info(&stream_info(4096, 44100, 1, 16, 0)).unwrap();
+
+    // The last frame may be shorter than the nominal block size
+    let parsed = read_header(
+        header(false, 2, BLOCK_SIZE_16_BIT, &[0, 99], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_block_size(), 100);
+    assert_eq!(parsed.get_first_sample(), 8192);
+
+    let parsed = read_header(
+        header(true, 8192, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_first_sample(), 8192);
+}
+
+#[test]
+fn fixed_frames_need_stream_info_after_the_first() {
+    let metadata = without_stream_info();
+    let parsed = read_header(
+        header(false, 0, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_first_sample(), 0);
+
+    // A frame's own block size is not the stream's, so the position is unknown
+    assert!(matches!(
+        read_header(
+            header(false, 1, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+            &metadata
+        ),
+        Err(AudioError::InvalidData(_))
+    ));
+
+    // Variable block size frames carry their position
+    let parsed = read_header(
+        header(true, 4096, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_first_sample(), 4096);
+}
+
+#[test]
+fn side_channel_of_32_bit_stereo_takes_33_bits() {
+    let left = [i32::MAX, i32::MIN, 0, -1, 12345];
+    let right = [i32::MIN, i32::MAX, -1, 0, -54321];
+
+    // Verbatim subframes: the left channel at 32 bits, then the side channel at 33
+    let mut writer = BitWriter::new();
+    writer.bits(0, 1).bits(VERBATIM, 6).bits(0, 1);
+    for sample in left {
+        writer.signed(sample as i64, 32);
+    }
+    writer.bits(0, 1).bits(VERBATIM, 6).bits(0, 1);
+    for (l, r) in left.iter().zip(right) {
+        writer.signed(*l as i64 - r as i64, 33);
+    }
+    let mut bytes = header(false, 0, BLOCK_SIZE_8_BIT, &[4], LEFT_SIDE_32_BIT);
+    bytes.extend(writer.into_bytes());
+    let bytes = with_crc16(bytes);
+
+    let metadata = Metadata::from_stream_info(&stream_info(5, 44100, 2, 32, 5)).unwrap();
+    for mode in [ArithmeticMode::Fast, ArithmeticMode::Checked] {
+        let frame = Frame::read_next_frame(
+            &mut Stream::from_bytes(bytes.clone()),
+            &metadata,
+            CrcPolicy::Error,
+            mode,
+        )
+        .unwrap();
+        assert_eq!(frame.get_header().get_bit_depth(), 32);
+        assert_eq!(frame.get_channels(), [left.to_vec(), right.to_vec()]);
+    }
+}
+
+#[test]
+fn coded_numbers_take_one_to_seven_bytes() {
+    let metadata = Metadata::from_stream_info(&stream_info(4096, 44100, 1, 16, 0)).unwrap();
+
+    // The largest number of each length, then the smallest of the next
+    let boundaries = [
+        (0x7F, 1),
+        (0x80, 2),
+        (0x7FF, 2),
+        (0x800, 3),
+        (0xFFFF, 3),
+        (0x1_0000, 4),
+        (0x1F_FFFF, 4),
+        (0x20_0000, 5),
+        (0x3FF_FFFF, 5),
+        (0x400_0000, 6),
+        (0x7FFF_FFFF, 6),
+        (0x8000_0000, 7),
+        ((1 << 36) - 1, 7),
+    ];
+    for (number, num_bytes) in boundaries {
+        assert_eq!(coded_number(number).len(), num_bytes, "{:#x}", number);
+        let parsed = read_header(
+            header(true, number, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+            &metadata,
+        )
+        .unwrap();
+        assert_eq!(parsed.get_sample_number_or_frame_number(), number);
+        assert_eq!(parsed.get_first_sample(), number);
+    }
+}
+
+#[test]
+fn frame_numbers_are_limited_to_31_bits() {
+    let metadata = Metadata::from_stream_info(&stream_info(4096, 44100, 1, 16, 0)).unwrap();
+    let read_fixed = |number| {
+        read_header(
+            header(false, number, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+            &metadata,
+        )
+    };
+
+    let parsed = read_fixed((1 << 31) - 1).unwrap();
+    assert_eq!(parsed.get_first_sample(), ((1 << 31) - 1) * 4096);
+    assert!(matches!(
+        read_fixed(1 << 31),
+        Err(AudioError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn malformed_coded_numbers_are_rejected() {
+    let metadata = Metadata::from_stream_info(&stream_info(4096, 44100, 1, 16, 0)).unwrap();
+    let numbers: [&[u8]; 4] = [
+        &[0x80],       // A continuation byte cannot lead
+        &[0xFF; 8],    // Nor can eight leading ones
+        &[0xC2, 0x40], // A continuation byte must start with 10
+        &[0xE0, 0x80], // The number is cut short by the block size code
+    ];
+    for number in numbers {
+        let bytes = header_with_number(true, number, BLOCK_SIZE_4096, &[], MONO_16_BIT);
+        assert!(
+            matches!(
+                read_header(bytes, &metadata),
+                Err(AudioError::InvalidData(_))
+            ),
+            "{:x?}",
+            number
+        );
+    }
+}
+
+#[test]
+fn variable_block_size_frames_are_positioned_by_sample_number() {
+    // Mono constant frames of uneven sizes, each carrying its first sample
+    let blocks = [(0, 100, 1000), (100, 300, -2000), (400, 37, 3000)];
+    let mut frames = Vec::new();
+    for (first_sample, block_size, value) in blocks {
+        let mut writer = BitWriter::new();
+        writer
+            .bits(0, 1)
+            .bits(CONSTANT, 6)
+            .bits(0, 1)
+            .signed(value, 16);
+        let size = (block_size as u16 - 1).to_be_bytes();
+        let mut frame = header(true, first_sample, BLOCK_SIZE_16_BIT, &size, MONO_16_BIT);
+        frame.extend(writer.into_bytes());
+        frames.extend(with_crc16(frame));
+    }
+
+    let metadata = Metadata::from_stream_info(&stream_info(300, 44100, 1, 16, 437)).unwrap();
+    let mut stream = Stream::from_bytes(frames.clone());
+    for (first_sample, block_size, _) in blocks {
+        let frame = Frame::read_next_frame(
+            &mut stream,
+            &metadata,
+            CrcPolicy::Error,
+            ArithmeticMode::Fast,
+        )
+        .unwrap();
+        assert!(frame.get_header().get_block_strategy());
+        assert_eq!(frame.get_block_size(), block_size);
+        assert_eq!(frame.get_first_sample(), first_sample);
+        assert_eq!(
+            frame.get_timestamp(),
+            Duration::from_nanos(first_sample * 1_000_000_000 / 44100)
+        );
+    }
+
+    // The decoder follows the 0xFFF9 sync code through the whole stream
+    let mut bytes = b"fLaC".to_vec();
+    bytes.extend([0x80, 0, 0, 34]);
+    bytes.extend(stream_info(300, 44100, 1, 16, 437));
+    bytes.extend(frames);
+    let path = temp_file("variable_block_size.flac", &bytes);
+    let (mut stream, mut flac) = open_flac(&path).unwrap();
+    let samples = flac.decode(&mut stream).unwrap();
+    fs::remove_file(path).unwrap();
+
+    let expected: Vec<i32> = blocks
+        .iter()
+        .flat_map(|&(_, block_size, value)| vec![value as i32; block_size as usize])
+        .collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn timestamps_are_exact_to_the_nanosecond() {
+    let metadata = Metadata::from_stream_info(&stream_info(4096, 44100, 1, 16, 0)).unwrap();
+
+    // One second and one sample in, the fraction is rounded down to whole nanoseconds
+    let parsed = read_header(
+        header(true, 44101, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_timestamp(), Duration::new(1, 22_675));
+
+    // Frame numbers count in nominal blocks
+    let parsed = read_header(
+        header(false, 441, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_timestamp(), Duration::from_millis(40_960));
+
+    // The largest sample number does not overflow
+    let parsed = read_header(
+        header(true, (1 << 36) - 1, BLOCK_SIZE_4096, &[], MONO_16_BIT),
+        &metadata,
+    )
+    .unwrap();
+    assert_eq!(parsed.get_timestamp().as_secs(), ((1 << 36) - 1) / 44100);
+}
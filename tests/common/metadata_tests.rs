@@ -0,0 +1,28 @@
+// tests/common/metadata_tests.rs
+
+// Tests parsing FLAC metadata blocks
+
+use super::stream_info;
+use playback::codecs::flac::data::Metadata;
+
+#[test]
+fn stream_info_fields_are_parsed() {
+    let metadata = Metadata::from_stream_info(&stream_info(4096, 44100, 2, 16, 1 << 35)).unwrap();
+    assert_eq!(metadata.get_min_block_size(), Some(4096));
+    assert_eq!(metadata.get_max_block_size(), Some(4096));
+    assert_eq!(metadata.get_sample_rate(), Some(44100));
+    assert_eq!(metadata.get_num_channels(), Some(2));
+    assert_eq!(metadata.get_bit_depth(), Some(16));
+    assert_eq!(metadata.get_total_samples(), Some(1 << 35));
+}
+
+#[test]
+fn stream_info_bit_depth_spans_two_bytes() {
+    // The 5-bit field starts at the last bit of one byte, so 32 sets all of its bits
+    for bit_depth in 1..=32 {
+        let metadata =
+            Metadata::from_stream_info(&stream_info(4096, 48000, 8, bit_depth, 0)).unwrap();
+        assert_eq!(metadata.get_bit_depth(), Some(bit_depth));
+        assert_eq!(metadata.get_num_channels(), Some(8));
+    }
+}
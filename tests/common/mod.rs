@@ -0,0 +1,322 @@
+// tests/common/mod.rs
+
+// Shared helpers for reading the conformance corpus in tests/data
+
+mod analysis_tests;
+mod buffer_tests;
+mod flac_tests;
+mod frame_tests;
+mod metadata_tests;
+mod packet_tests;
+mod predictor_tests;
+mod subframe_tests;
+
+use playback::codecs::flac::{
+    decoder::{ArithmeticMode, CrcPolicy, Decoder},
+    flac::FLAC,
+    frame::Frame,
+};
+use playback::common::{crc, errors::AudioError, stream::Stream};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// Directory holding the generated FLAC files and their reference WAV files
+pub fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/flac")
+}
+
+// Path of a single corpus file
+pub fn corpus_file(name: &str) -> PathBuf {
+    corpus_dir().join(name)
+}
+
+/**
+ * Path of one of the example files of RFC 9639 Appendix D, encoded by
+ * libFLAC and transcribed from the hex dumps printed there
+ * Their STREAMINFO carries the MD5 signature libFLAC computed, so they check
+ * the decoder against an independent encoder rather than tests/data/generate.py
+ */
+pub fn rfc_9639_example(number: usize) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(format!("tests/data/rfc9639/example_{}.flac", number))
+}
+
+/**
+ * FLAC files encoded by flacenc along with the WAV files they were encoded
+ * from, written by tests/data/flacenc/encode.rs
+ * Like the RFC 9639 examples, they come from an encoder independent of
+ * tests/data/generate.py
+ */
+pub fn flacenc_cases() -> Vec<PathBuf> {
+    flac_files(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/flacenc"),
+        "",
+    )
+}
+
+// All FLAC files in the corpus whose name starts with the prefix, sorted by name
+pub fn corpus_cases(prefix: &str) -> Vec<PathBuf> {
+    flac_files(&corpus_dir(), prefix)
+}
+
+// All FLAC files in a directory whose name starts with the prefix, sorted by name
+fn flac_files(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut cases: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("corpus directory should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with(prefix) && name.ends_with(".flac")
+        })
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no corpus files start with {}", prefix);
+    cases
+}
+
+// Reference PCM read from a WAV file
+pub struct Wav {
+    pub num_channels: u16,
+    pub sample_rate: u32,
+    pub bit_depth: u16,
+    pub samples: Vec<i32>, // Interleaved samples
+}
+
+/**
+ * Read a PCM WAV file, taking the valid bits per sample from the
+ * WAVE_FORMAT_EXTENSIBLE header when present
+ */
+pub fn read_wav(path: &Path) -> Wav {
+    let bytes = fs::read(path).expect("reference WAV should exist");
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(&bytes[8..12], b"WAVE");
+
+    let u16_at = |pos: usize| u16::from_le_bytes([bytes[pos], bytes[pos + 1]]);
+    let u32_at = |pos: usize| u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let size = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+        match &bytes[pos..pos + 4] {
+            b"fmt " => {
+                let container_bits = u16_at(body + 14);
+                let valid_bits = if u16_at(body) == 0xFFFE {
+                    u16_at(body + 18)
+                } else {
+                    container_bits
+                };
+                format = Some((
+                    u16_at(body + 2),
+                    u32_at(body + 4),
+                    container_bits,
+                    valid_bits,
+                ));
+            }
+            b"data" => {
+                let (num_channels, sample_rate, container_bits, bit_depth) =
+                    format.expect("fmt chunk should precede data");
+                let width = container_bits as usize / 8;
+                let unused_bits = 32 - container_bits as u32;
+                let samples = bytes[body..body + size]
+                    .chunks_exact(width)
+                    .map(|sample| {
+                        let mut word = [0; 4];
+                        word[..width].copy_from_slice(sample);
+                        (i32::from_le_bytes(word) << unused_bits) >> unused_bits
+                    })
+                    .collect();
+                return Wav {
+                    num_channels,
+                    sample_rate,
+                    bit_depth,
+                    samples,
+                };
+            }
+            _ => {}
+        }
+        pos = body + size + (size & 1);
+    }
+    panic!("{} has no data chunk", path.display());
+}
+
+// Open a FLAC file and parse its metadata, leaving the stream at the first frame
+pub fn open_flac(path: &Path) -> Result<(Stream, FLAC), AudioError> {
+    let mut stream = Stream::new(path)?;
+
+    let mut magic = [0; 4];
+    stream.reader().read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        return Err(AudioError::UnsupportedFileCodec);
+    }
+
+    let flac = FLAC::new(&mut stream)?;
+    Ok((stream, flac))
+}
+
+// Decode every frame of a FLAC file, with the CRCs checked
+pub fn read_frames(path: &Path) -> Result<Vec<Frame>, AudioError> {
+    let (mut stream, flac) = open_flac(path)?;
+    let mut frames = Vec::new();
+
+    loop {
+        match stream.peek_u16() {
+            Ok(_) => frames.push(Frame::read_next_frame(
+                &mut stream,
+                flac.get_metadata(),
+                CrcPolicy::Error,
+                ArithmeticMode::Fast,
+            )?),
+            Err(AudioError::EOF) => return Ok(frames),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Byte offsets of every frame in a corpus file, found by scanning for valid headers
+pub fn frame_offsets(bytes: &[u8], name: &str) -> Vec<u64> {
+    let path = temp_file(name, bytes);
+    let (mut stream, flac) = open_flac(&path).unwrap();
+    let mut offsets = Vec::new();
+    let mut from = stream.get_position().unwrap();
+    while let Some(offset) =
+        Decoder::find_next_frame(&mut stream, flac.get_metadata(), from).unwrap()
+    {
+        offsets.push(offset);
+        from = offset + 1;
+    }
+    fs::remove_file(path).unwrap();
+    offsets
+}
+
+// Interleave the decoded channels of each frame, in the layout of a WAV file
+pub fn interleave(frames: &[Frame]) -> Vec<i32> {
+    let mut samples = Vec::new();
+    for frame in frames {
+        let channels = frame.get_channels();
+        for i in 0..frame.get_block_size() as usize {
+            samples.extend(channels.iter().map(|channel| channel[i]));
+        }
+    }
+    samples
+}
+
+// Write bytes to a file in the temporary directory, unique to this test process
+pub fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("playback-{}-{}", std::process::id(), name));
+    fs::write(&path, bytes).expect("temporary file should be writable");
+    path
+}
+
+// Writer of a bitstream, most significant bit first, for building frames by hand
+#[derive(Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    num_bits: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Append the lowest bits of an unsigned value
+    pub fn bits(&mut self, value: u64, num: u8) -> &mut Self {
+        for i in (0..num).rev() {
+            if self.num_bits % 8 == 0 {
+                self.bytes.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.num_bits % 8);
+            self.num_bits += 1;
+        }
+        self
+    }
+
+    // Append a signed value in two's complement
+    pub fn signed(&mut self, value: i64, num: u8) -> &mut Self {
+        self.bits(value as u64 & (u64::MAX >> (64 - num)), num)
+    }
+
+    // Append a unary code: the given number of zero bits, then a one bit
+    pub fn unary(&mut self, zeros: u32) -> &mut Self {
+        for _ in 0..zeros {
+            self.bits(0, 1);
+        }
+        self.bits(1, 1)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/**
+ * Build the 34 bytes of a STREAMINFO block, with unknown frame sizes and no
+ * MD5 signature
+ */
+pub fn stream_info(
+    block_size: u16,
+    sample_rate: u32,
+    num_channels: u8,
+    bit_depth: u8,
+    total_samples: u64,
+) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer
+        .bits(block_size as u64, 16)
+        .bits(block_size as u64, 16)
+        .bits(0, 24)
+        .bits(0, 24)
+        .bits(sample_rate as u64, 20)
+        .bits(num_channels as u64 - 1, 3)
+        .bits(bit_depth as u64 - 1, 5)
+        .bits(total_samples, 36)
+        .bits(0, 64)
+        .bits(0, 64);
+    writer.into_bytes()
+}
+
+/**
+ * Code a frame or sample number as a frame header does, like UTF-8 stretched
+ * to 36 bits
+ */
+pub fn coded_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    // Each byte after the first carries 6 bits, and the first loses a bit per byte
+    let num_bytes = (2..=7)
+        .find(|&n| value < 1 << (5 * n + 1).min(36))
+        .expect("coded numbers hold at most 36 bits");
+    let mut bytes: Vec<u8> = (0..num_bytes - 1)
+        .rev()
+        .map(|i| 0x80 | ((value >> (6 * i)) & 0x3F) as u8)
+        .collect();
+    let prefix = !(0xFFu8 >> num_bytes);
+    bytes.insert(0, prefix | (value >> (6 * (num_bytes - 1))) as u8);
+    bytes
+}
+
+// Append the CRC-8 of the bytes, closing a frame header
+pub fn with_crc8(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.push(
+        bytes
+            .iter()
+            .fold(0, |crc, &byte| crc::crc8_update(crc, byte)),
+    );
+    bytes
+}
+
+// Append the big-endian CRC-16 of the bytes, closing a frame
+pub fn with_crc16(mut bytes: Vec<u8>) -> Vec<u8> {
+    let crc = bytes
+        .iter()
+        .fold(0, |crc, &byte| crc::crc16_update(crc, byte));
+    bytes.extend(crc.to_be_bytes());
+    bytes
+}
@@ -0,0 +1,230 @@
+// tests/common/packet_tests.rs
+
+// Tests splitting FLAC streams into packets and decoding the packets
+
+use super::{corpus_cases, corpus_file, interleave, read_frames};
+use playback::codecs::{
+    flac::{demuxer::FlacDemuxer, packet_decoder::FlacPacketDecoder},
+    packet::{CodecId, Demuxer, Packet, PacketDecoder},
+};
+use playback::common::{block::DecodedBlock, errors::AudioError, stream::Stream};
+use std::{fs, path::Path};
+
+// Split a stream into its packets
+fn demux(stream: &mut Stream) -> (FlacDemuxer, Vec<Packet>) {
+    let mut demuxer = FlacDemuxer::new(stream).unwrap();
+    let mut packets = Vec::new();
+    while let Some(packet) = demuxer.next_packet(stream).unwrap() {
+        packets.push(packet);
+    }
+    (demuxer, packets)
+}
+
+// Decode packets, returning the interleaved samples
+fn decode_packets(decoder: &mut FlacPacketDecoder, packets: &[Packet]) -> Vec<i32> {
+    let mut samples = Vec::new();
+    for packet in packets {
+        let block = decoder.decode(packet).unwrap();
+        assert_eq!(block.get_first_sample(), packet.get_timestamp());
+        assert_eq!(block.get_block_size() as u64, packet.get_duration());
+        let channels = block.get_channels();
+        for i in 0..block.get_block_size() {
+            samples.extend(channels.iter().map(|channel| channel[i]));
+        }
+    }
+    samples
+}
+
+fn assert_packets_match_frames(path: &Path) {
+    let mut stream = Stream::new(path).unwrap();
+    let (demuxer, packets) = demux(&mut stream);
+
+    let tracks = demuxer.get_tracks();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].get_codec_id(), CodecId::Flac);
+    assert!(packets.iter().all(|packet| packet.get_track_id() == 0));
+
+    // The packets cover every byte after the metadata, in order
+    let bytes = fs::read(path).unwrap();
+    let first_frame = demuxer.get_metadata().get_first_frame_offset().unwrap() as usize;
+    let joined: Vec<u8> = packets
+        .iter()
+        .flat_map(|packet| packet.get_data().iter().copied())
+        .collect();
+    assert_eq!(joined, bytes[first_frame..], "{}", path.display());
+
+    let mut decoder = FlacPacketDecoder::new(demuxer.get_metadata().clone());
+    assert_eq!(
+        decode_packets(&mut decoder, &packets),
+        interleave(&read_frames(path).unwrap()),
+        "{}",
+        path.display()
+    );
+}
+
+#[test]
+fn packets_decode_like_frames() {
+    for path in corpus_cases("") {
+        assert_packets_match_frames(&path);
+    }
+}
+
+#[test]
+fn demuxes_streams_held_in_memory() {
+    let path = corpus_file("fixed_block_size_many_frames.flac");
+    let bytes = fs::read(&path).unwrap();
+    let mut stream = Stream::from_bytes(bytes);
+    let (demuxer, packets) = demux(&mut stream);
+    assert!(packets.len() > 1);
+
+    let mut decoder = FlacPacketDecoder::new(demuxer.get_metadata().clone());
+    assert_eq!(
+        decode_packets(&mut decoder, &packets),
+        interleave(&read_frames(&path).unwrap())
+    );
+}
+
+#[test]
+fn damaged_packets_fail_to_decode() {
+    let mut stream = Stream::new(corpus_file("fixed_block_size_many_frames.flac")).unwrap();
+    let (demuxer, packets) = demux(&mut stream);
+    let mut decoder = FlacPacketDecoder::new(demuxer.get_metadata().clone());
+
+    let mut data = packets[1].get_data().to_vec();
+    let middle = data.len() / 2;
+    data[middle] ^= 0x10;
+    let damaged = Packet::new(
+        CodecId::Flac,
+        0,
+        packets[1].get_timestamp(),
+        packets[1].get_duration(),
+        data,
+    );
+    assert!(matches!(
+        decoder.decode(&damaged),
+        Err(AudioError::CrcMismatch(_))
+    ));
+}
+
+#[test]
+fn damaged_frame_ends_at_next_header() {
+    let path = corpus_file("fixed_block_size_many_frames.flac");
+    let (_, expected) = demux(&mut Stream::new(&path).unwrap());
+
+    // Damage the middle of a frame, so no header after it agrees with its CRC-16
+    let mut bytes = fs::read(&path).unwrap();
+    let damaged = expected.len() / 2;
+    let offset = bytes.len()
+        - expected[damaged..]
+            .iter()
+            .map(|packet| packet.get_data().len())
+            .sum::<usize>();
+    bytes[offset + expected[damaged].get_data().len() / 2] ^= 0x10;
+
+    let (demuxer, packets) = demux(&mut Stream::from_bytes(bytes));
+    assert_eq!(packets.len(), expected.len());
+    let mut decoder = FlacPacketDecoder::new(demuxer.get_metadata().clone());
+    for (i, (packet, expected)) in packets.iter().zip(&expected).enumerate() {
+        assert_eq!(packet.get_data().len(), expected.get_data().len());
+        if i == damaged {
+            assert!(matches!(
+                decoder.decode(packet),
+                Err(AudioError::CrcMismatch(_))
+            ));
+        } else {
+            let block = decoder.decode(packet).unwrap();
+            let expected = decoder.decode(expected).unwrap();
+            assert_eq!(block.get_channels(), expected.get_channels());
+        }
+    }
+}
+
+#[test]
+fn trailing_data_is_not_a_packet() {
+    let path = corpus_file("fixed_block_size_many_frames.flac");
+    let (_, expected) = demux(&mut Stream::new(&path).unwrap());
+    let bytes = fs::read(&path).unwrap();
+
+    // An ID3v1 tag, and junk longer than the largest frame
+    let mut tag = b"TAG".to_vec();
+    tag.resize(128, b' ');
+    for junk in [tag, vec![0x55; 1 << 16]] {
+        let mut stream = Stream::from_bytes([&bytes[..], &junk].concat());
+        let (demuxer, packets) = demux(&mut stream);
+        assert_eq!(packets.len(), expected.len());
+
+        let mut decoder = FlacPacketDecoder::new(demuxer.get_metadata().clone());
+        assert_eq!(
+            decode_packets(&mut decoder, &packets),
+            decode_packets(&mut decoder, &expected)
+        );
+
+        // Only the last packet carries junk, up to the largest frame size
+        let last = packets.last().unwrap().get_data().len();
+        let max_frame_size = demuxer.get_metadata().get_max_frame_size().unwrap();
+        assert!(last <= max_frame_size as usize);
+    }
+}
+
+#[test]
+fn decodes_raw_frames_with_external_stream_info() {
+    // Frame headers of these files defer the sample rate or bit depth to STREAMINFO
+    let cases = corpus_cases("sample_rate_code_0")
+        .into_iter()
+        .chain(corpus_cases("bit_depth_code"));
+    for path in cases {
+        let bytes = fs::read(&path).unwrap();
+        let (_, packets) = demux(&mut Stream::new(&path).unwrap());
+        let expected = interleave(&read_frames(&path).unwrap());
+
+        // STREAMINFO follows the stream marker, with or without its block header
+        for stream_info in [&bytes[8..42], &bytes[4..42]] {
+            let mut decoder = FlacPacketDecoder::from_stream_info(stream_info).unwrap();
+            let mut samples = Vec::new();
+            for packet in &packets {
+                let block = decoder.decode_frame(packet.get_data()).unwrap();
+                let channels = block.get_channels();
+                for i in 0..block.get_block_size() {
+                    samples.extend(channels.iter().map(|channel| channel[i]));
+                }
+            }
+            assert_eq!(samples, expected, "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn rejects_malformed_stream_info() {
+    let bytes = fs::read(corpus_file("channels_stereo_mid_side.flac")).unwrap();
+    for stream_info in [&bytes[8..40], &bytes[0..42], &bytes[8..43]] {
+        assert!(matches!(
+            FlacPacketDecoder::from_stream_info(stream_info),
+            Err(AudioError::ParseError(_))
+        ));
+    }
+}
+
+// Decoder of packets holding raw signed 8-bit mono samples, built on common types alone
+struct RawDecoder;
+
+impl PacketDecoder for RawDecoder {
+    fn decode(&mut self, packet: &Packet) -> Result<DecodedBlock, AudioError> {
+        let samples = packet.get_data().iter().map(|&byte| byte as i8 as i32);
+        Ok(DecodedBlock::new(
+            8000,
+            8,
+            packet.get_timestamp(),
+            vec![samples.collect()],
+        ))
+    }
+}
+
+#[test]
+fn packet_decoders_are_independent_of_flac() {
+    let packet = Packet::new(CodecId::Flac, 0, 96, 3, vec![0x01, 0xFF, 0x80]);
+    let decoder: &mut dyn PacketDecoder = &mut RawDecoder;
+    let block = decoder.decode(&packet).unwrap();
+    assert_eq!(block.get_first_sample(), 96);
+    assert_eq!(block.get_sample_rate(), 8000);
+    assert_eq!(block.into_channels(), [vec![1, -1, -128]]);
+}
@@ -0,0 +1,117 @@
+// tests/common/predictor_tests.rs
+
+// Tests comparing the fast predictor kernels with checked arithmetic
+
+use super::{corpus_cases, open_flac};
+use playback::codecs::flac::{decoder::ArithmeticMode, predictor};
+use playback::common::errors::AudioError;
+
+// Small LCG so the inputs are reproducible
+struct Random(u64);
+
+impl Random {
+    // Next value in the signed range of the given number of bits
+    fn next(&mut self, bits: u8) -> i64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 11) >> (53 - bits)) as i64 - (1 << (bits - 1))
+    }
+}
+
+/**
+ * Encode random samples within the bit depth the way an encoder would, then
+ * restore them in both modes, returning the original and both results
+ */
+fn restore_lpc_both(order: usize, bit_depth: u8, precision: u8) -> [Vec<i64>; 3] {
+    let mut random = Random(order as u64 * 1000 + bit_depth as u64 * 10 + precision as u64);
+    let original: Vec<i64> = (0..300).map(|_| random.next(bit_depth.min(30))).collect();
+    let coefficients: Vec<i32> = (0..order).map(|_| random.next(precision) as i32).collect();
+
+    // A shift that keeps every prediction within the bit depth
+    let shift = precision as u32 - 1 + order.next_power_of_two().ilog2();
+    let residuals: Vec<i32> = (order..original.len())
+        .map(|i| {
+            let prediction: i64 = (0..order)
+                .map(|j| coefficients[j] as i64 * original[i - j - 1])
+                .sum();
+            (original[i] - (prediction >> shift)) as i32
+        })
+        .collect();
+
+    let mut fast: Vec<i64> = original[..order].to_vec();
+    fast.extend(residuals.iter().map(|&residual| residual as i64));
+    let mut checked = fast.clone();
+    let mut scratch = Vec::new();
+    for (samples, mode) in [
+        (&mut fast, ArithmeticMode::Fast),
+        (&mut checked, ArithmeticMode::Checked),
+    ] {
+        predictor::restore_lpc(
+            samples,
+            &coefficients,
+            shift,
+            bit_depth,
+            precision,
+            mode,
+            &mut scratch,
+        )
+        .unwrap();
+    }
+    [original, fast, checked]
+}
+
+#[test]
+fn lpc_kernels_match_checked_arithmetic() {
+    for order in 1..=32 {
+        // 32-bit sums at low bit depths, 64-bit sums above
+        for (bit_depth, precision) in [(8, 12), (16, 15), (24, 15), (33, 15)] {
+            let [original, fast, checked] = restore_lpc_both(order, bit_depth, precision);
+            assert_eq!(fast, original, "order {} bit depth {}", order, bit_depth);
+            assert_eq!(checked, original, "order {} bit depth {}", order, bit_depth);
+        }
+    }
+}
+
+#[test]
+fn fixed_kernels_match_checked_arithmetic() {
+    let mut random = Random(7);
+    for order in 0..=4 {
+        let mut fast: Vec<i64> = (0..order).map(|_| random.next(24)).collect();
+        fast.extend((0..300).map(|_| random.next(12)));
+
+        let mut checked = fast.clone();
+        predictor::restore_fixed(&mut fast, order, ArithmeticMode::Fast).unwrap();
+        predictor::restore_fixed(&mut checked, order, ArithmeticMode::Checked).unwrap();
+        assert_eq!(fast, checked, "order {}", order);
+    }
+}
+
+#[test]
+fn checked_arithmetic_reports_overflow() {
+    let mut samples = [i64::MAX / 2, -(i64::MAX / 2), 0, 0];
+    let result = predictor::restore_fixed(&mut samples, 2, ArithmeticMode::Checked);
+    assert!(matches!(result, Err(AudioError::ArithmeticOverflow)));
+
+    // The fast kernels wrap around instead
+    let mut samples = [i64::MAX / 2, -(i64::MAX / 2), 0, 0];
+    predictor::restore_fixed(&mut samples, 2, ArithmeticMode::Fast).unwrap();
+}
+
+#[test]
+fn checked_decode_matches_fast_decode() {
+    for path in corpus_cases("subframe_") {
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        let fast = flac.decode(&mut stream).unwrap();
+
+        let (mut stream, mut flac) = open_flac(&path).unwrap();
+        flac.set_arithmetic_mode(ArithmeticMode::Checked);
+        assert_eq!(
+            flac.decode(&mut stream).unwrap(),
+            fast,
+            "{}",
+            path.display()
+        );
+    }
+}
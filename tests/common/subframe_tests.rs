@@ -0,0 +1,229 @@
+// tests/common/subframe_tests.rs
+
+// Tests decoding single subframes built bit by bit
+
+use super::BitWriter;
+use playback::codecs::flac::{
+    decoder::ArithmeticMode,
+    subframe::{Subframe, SubframeType},
+};
+use playback::common::{errors::AudioError, stream::Stream};
+
+// Subframe type codes, as placed after the zero padding bit
+const CONSTANT: u64 = 0;
+const VERBATIM: u64 = 1;
+const FIXED: u64 = 8; // Plus the predictor order
+
+// Residual coding methods
+const RICE: u64 = 0;
+const RICE2: u64 = 1;
+
+// Decode one subframe of a block, the way a frame of the given bit depth would
+fn decode(bytes: Vec<u8>, bit_depth: u8, block_size: usize) -> Result<Subframe, AudioError> {
+    let mut stream = Stream::from_bytes(bytes);
+    Subframe::read_next_subframe(&mut stream, bit_depth, block_size, ArithmeticMode::Fast)
+}
+
+// Write a subframe header, with the wasted bits flag and count when there are any
+fn header(writer: &mut BitWriter, type_code: u64, wasted_bits: u32) -> &mut BitWriter {
+    writer.bits(0, 1).bits(type_code, 6);
+    if wasted_bits == 0 {
+        writer.bits(0, 1)
+    } else {
+        writer.bits(1, 1).unary(wasted_bits - 1)
+    }
+}
+
+#[test]
+fn wasted_bits_are_shifted_back_into_samples() {
+    // Three wasted bits leave 13 bits per sample of a 16-bit subframe
+    let values = [5, -3, 4095, -4096];
+    let mut writer = BitWriter::new();
+    header(&mut writer, VERBATIM, 3);
+    for value in values {
+        writer.signed(value, 13);
+    }
+
+    let subframe = decode(writer.into_bytes(), 16, values.len()).unwrap();
+    assert_eq!(subframe.get_type(), &SubframeType::Verbatim);
+    assert_eq!(subframe.get_wasted_bits(), 3);
+    assert_eq!(subframe.get_data(), [40, -24, 32760, -32768]);
+}
+
+#[test]
+fn wasted_bits_apply_to_constant_subframes() {
+    let mut writer = BitWriter::new();
+    header(&mut writer, CONSTANT, 1).signed(-3, 23);
+
+    let subframe = decode(writer.into_bytes(), 24, 3).unwrap();
+    assert_eq!(subframe.get_wasted_bits(), 1);
+    assert_eq!(subframe.get_data(), [-6, -6, -6]);
+}
+
+#[test]
+fn wasted_bits_must_leave_a_sample_bit() {
+    let mut writer = BitWriter::new();
+    header(&mut writer, CONSTANT, 8).bits(0, 8);
+    assert!(matches!(
+        decode(writer.into_bytes(), 8, 1),
+        Err(AudioError::InvalidData(_))
+    ));
+
+    let mut writer = BitWriter::new();
+    header(&mut writer, CONSTANT, 7).bits(1, 1);
+    assert_eq!(
+        decode(writer.into_bytes(), 8, 1).unwrap().get_data(),
+        [-128]
+    );
+}
+
+// Write a residual in a Rice partition: the zig-zag folded value as a unary quotient and a remainder
+fn rice(writer: &mut BitWriter, parameter: u8, residual: i64) {
+    let folded = if residual < 0 {
+        (-2 * residual - 1) as u64
+    } else {
+        2 * residual as u64
+    };
+    writer
+        .unary((folded >> parameter) as u32)
+        .bits(folded & ((1 << parameter) - 1), parameter);
+}
+
+/**
+ * Write a first order FIXED subframe whose warm-up sample is zero, so each
+ * sample is the sum of the residuals so far
+ */
+fn fixed_order_1(bit_depth: u8) -> BitWriter {
+    let mut writer = BitWriter::new();
+    header(&mut writer, FIXED + 1, 0).signed(0, bit_depth);
+    writer
+}
+
+// Running sums of the residuals, after the zero warm-up sample
+fn running_sums(residuals: &[i64]) -> Vec<i64> {
+    let mut sum = 0;
+    let mut samples = vec![0];
+    for residual in residuals {
+        sum += residual;
+        samples.push(sum);
+    }
+    samples
+}
+
+#[test]
+fn rice_residuals_unfold_zig_zag() {
+    let residuals = [0, -1, 1, -2, 2, 7, -8];
+    let mut writer = fixed_order_1(16);
+    writer.bits(RICE, 2).bits(0, 4).bits(2, 4);
+    for residual in residuals {
+        rice(&mut writer, 2, residual);
+    }
+
+    let subframe = decode(writer.into_bytes(), 16, residuals.len() + 1).unwrap();
+    assert_eq!(subframe.get_data(), running_sums(&residuals));
+}
+
+#[test]
+fn rice2_partitions_take_5_bit_parameters() {
+    let residuals = [100_000, -70_000, 1];
+    let mut writer = fixed_order_1(24);
+    writer.bits(RICE2, 2).bits(0, 4).bits(17, 5);
+    for residual in residuals {
+        rice(&mut writer, 17, residual);
+    }
+
+    let subframe = decode(writer.into_bytes(), 24, residuals.len() + 1).unwrap();
+    assert_eq!(subframe.get_data(), running_sums(&residuals));
+}
+
+#[test]
+fn escaped_partitions_hold_raw_residuals() {
+    // Partition order 1 splits 8 samples in two, the first short by the warm-up sample
+    let mut writer = fixed_order_1(16);
+    writer.bits(RICE, 2).bits(1, 4);
+    writer.bits(0b1111, 4).bits(5, 5);
+    for residual in [-16, 15, 3] {
+        writer.signed(residual, 5);
+    }
+    // Escaped with zero bits per residual, every residual is zero
+    writer.bits(0b1111, 4).bits(0, 5);
+
+    let subframe = decode(writer.into_bytes(), 16, 8).unwrap();
+    assert_eq!(subframe.get_data(), running_sums(&[-16, 15, 3, 0, 0, 0, 0]));
+
+    // Rice2 escapes with a 5-bit code
+    let mut writer = fixed_order_1(16);
+    writer.bits(RICE2, 2).bits(0, 4).bits(0b11111, 5).bits(3, 5);
+    writer.signed(-4, 3).signed(3, 3);
+    let subframe = decode(writer.into_bytes(), 16, 3).unwrap();
+    assert_eq!(subframe.get_data(), [0, -4, -1]);
+}
+
+#[test]
+fn partitions_must_fit_the_block() {
+    // Four partitions of one sample cannot hold the three warm-up samples of order 3
+    let mut writer = BitWriter::new();
+    header(&mut writer, FIXED + 3, 0);
+    for _ in 0..3 {
+        writer.signed(0, 8);
+    }
+    writer.bits(RICE, 2).bits(2, 4).bits(0, 4).unary(0);
+    assert!(matches!(
+        decode(writer.into_bytes(), 8, 4),
+        Err(AudioError::InvalidData(_))
+    ));
+
+    // Six samples do not split into four partitions
+    let mut writer = fixed_order_1(8);
+    writer.bits(RICE, 2).bits(2, 4);
+    assert!(matches!(
+        decode(writer.into_bytes(), 8, 6),
+        Err(AudioError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn rice_residuals_must_fit_32_bits() {
+    let mut writer = fixed_order_1(16);
+    writer.bits(RICE2, 2).bits(0, 4).bits(30, 5);
+    writer.unary(8).bits(0, 30);
+    assert!(matches!(
+        decode(writer.into_bytes(), 16, 2),
+        Err(AudioError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn reserved_subframe_types_are_rejected() {
+    for type_code in (2..=7).chain(13..=31) {
+        let mut writer = BitWriter::new();
+        header(&mut writer, type_code, 0).bits(0, 32);
+        assert!(
+            matches!(
+                decode(writer.into_bytes(), 16, 4),
+                Err(AudioError::InvalidData(_))
+            ),
+            "type {}",
+            type_code
+        );
+    }
+}
+
+#[test]
+fn order_0_fixed_subframes_hold_residuals_as_samples() {
+    // No warm-up samples, and no prediction: every sample is its residual
+    let residuals = [3, -7, 0, 12, -1];
+    let mut writer = BitWriter::new();
+    header(&mut writer, FIXED, 0)
+        .bits(RICE, 2)
+        .bits(0, 4)
+        .bits(3, 4);
+    for residual in residuals {
+        rice(&mut writer, 3, residual);
+    }
+
+    let subframe = decode(writer.into_bytes(), 16, residuals.len()).unwrap();
+    assert_eq!(subframe.get_data(), residuals);
+    assert_eq!(subframe.get_type(), &SubframeType::Fixed);
+    assert_eq!(subframe.get_order(), 0);
+}
@@ -0,0 +1,143 @@
+// tests/data/flacenc/encode.rs
+
+/*
+ * Encode the reference files in this directory with flacenc, a FLAC encoder
+ * written independently of this crate and of tests/data/generate.py, so the
+ * decoder is not only checked against its own author's reading of RFC 9639
+ * The WAV files beside them hold the samples that were encoded
+ *
+ * Run as the main.rs of a scratch crate depending on flacenc = "=0.5.1":
+ *     cargo run -- tests/data/flacenc
+ */
+
+use flacenc::{bitsink::ByteSink, component::BitRepr, config, error::Verify, source::MemSource};
+use std::{fs, path::Path};
+
+// Deterministic test signal: two tones and noise from a linear congruential generator
+fn signal(len: usize, channels: usize, bits: u32, seed: u64, noise_level: f64) -> Vec<i32> {
+    let amplitude = ((1i64 << (bits - 1)) - 1) as f64;
+    let mut state = seed;
+    let mut samples = Vec::with_capacity(len * channels);
+    for i in 0..len {
+        for channel in 0..channels {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let noise = ((state >> 33) as f64 / (1u64 << 31) as f64) - 1.0;
+            let t = i as f64;
+            let x = 0.5 * (t * (0.011 + 0.003 * channel as f64)).sin()
+                + 0.2 * (t * 0.173).sin()
+                + noise_level * noise;
+            samples.push((x * amplitude).round() as i32);
+        }
+    }
+    samples
+}
+
+// WAVE_FORMAT_EXTENSIBLE file carrying the valid bits per sample, as tests/data/generate.py writes
+fn write_wav(path: &Path, samples: &[i32], channels: usize, bits: u32, rate: u32) {
+    let width = bits.div_ceil(8) as usize;
+    let block_align = (channels * width) as u16;
+    let mut fmt = Vec::new();
+    fmt.extend(0xFFFEu16.to_le_bytes());
+    fmt.extend((channels as u16).to_le_bytes());
+    fmt.extend(rate.to_le_bytes());
+    fmt.extend((rate * block_align as u32).to_le_bytes());
+    fmt.extend(block_align.to_le_bytes());
+    fmt.extend((width as u16 * 8).to_le_bytes());
+    fmt.extend(22u16.to_le_bytes());
+    fmt.extend((bits as u16).to_le_bytes());
+    fmt.extend(0u32.to_le_bytes());
+    fmt.extend([
+        1, 0, 0, 0, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+    ]);
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes()[..width].to_vec())
+        .collect();
+
+    let mut body = b"WAVEfmt ".to_vec();
+    body.extend((fmt.len() as u32).to_le_bytes());
+    body.extend(fmt);
+    body.extend(b"data");
+    body.extend((data.len() as u32).to_le_bytes());
+    body.extend(data);
+    let mut file = b"RIFF".to_vec();
+    file.extend((body.len() as u32).to_le_bytes());
+    file.extend(body);
+    fs::write(path, file).unwrap();
+}
+
+fn encode(
+    dir: &Path,
+    name: &str,
+    channels: usize,
+    bits: u32,
+    rate: u32,
+    len: usize,
+    block_size: usize,
+    lpc_order: Option<usize>,
+) {
+    // Residuals must stay within the 4-bit Rice parameters the encoder writes
+    let noise_level = 0.05 / (1u64 << bits.saturating_sub(16)) as f64;
+    let samples = signal(len, channels, bits, name.len() as u64, noise_level);
+    let mut config = config::Encoder::default();
+    config.block_size = block_size;
+    config.multithread = false;
+    if let Some(order) = lpc_order {
+        config.subframe_coding.qlpc.lpc_order = order;
+        config.subframe_coding.use_fixed = false;
+    }
+    let config = config.into_verified().unwrap();
+    let source = MemSource::from_samples(&samples, channels, bits as usize, rate as usize);
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, block_size).unwrap();
+    let mut sink = ByteSink::new();
+    stream.write(&mut sink).unwrap();
+    fs::write(dir.join(format!("{}.flac", name)), sink.as_slice()).unwrap();
+    write_wav(
+        &dir.join(format!("{}.wav", name)),
+        &samples,
+        channels,
+        bits,
+        rate,
+    );
+}
+
+// Cases cover high LPC orders, stereo decorrelation and the common bit depths
+fn main() {
+    let dir = Path::new(&std::env::args().nth(1).expect("output directory")).to_path_buf();
+    fs::create_dir_all(&dir).unwrap();
+    encode(
+        &dir,
+        "flacenc_lpc_order_24",
+        1,
+        16,
+        44100,
+        10000,
+        4096,
+        Some(24),
+    );
+    encode(
+        &dir,
+        "flacenc_lpc_order_12_stereo",
+        2,
+        16,
+        48000,
+        10000,
+        4096,
+        Some(12),
+    );
+    encode(&dir, "flacenc_bit_depth_8", 2, 8, 22050, 5000, 1152, None);
+    encode(&dir, "flacenc_bit_depth_12", 1, 12, 8000, 5000, 1024, None);
+    encode(&dir, "flacenc_bit_depth_20", 2, 20, 96000, 9000, 4608, None);
+    encode(
+        &dir,
+        "flacenc_bit_depth_24",
+        2,
+        24,
+        96000,
+        6000,
+        4096,
+        Some(24),
+    );
+}
@@ -0,0 +1,632 @@
+#!/usr/bin/env python3
+"""
+Generate the FLAC conformance corpus used by tests/common/flac_tests.rs
+
+Every case is written by a small, deliberately simple FLAC encoder that lets
+each test pin down exactly which subframe type, predictor order, residual
+coding, channel assignment, bit depth, block size code, sample rate code and
+metadata block appears in the stream. Alongside every <case>.flac a
+<case>.wav holds the reference PCM the decoder must reproduce bit for bit.
+
+Run from the repository root:  python3 tests/data/generate.py
+
+Since this encoder and the decoder share an author, the corpus was checked
+with claxon 0.4.3, an independent Rust FLAC decoder: 72 of the 101 files
+decode with it to exactly the samples of their WAV and to the MD5 signature in
+their STREAMINFO. Among them are LPC orders up to 32, Rice2 partitions, wasted
+bits, variable block sizes and every stereo decorrelation. The other 29 use
+what claxon does not implement: escaped partitions (residual_escape*), 32-bit
+samples (bit_depth_32*), and bit depths given only in STREAMINFO (the other
+bit_depth_* files and bit_depth_code_0). Those remain checked by this encoder
+alone, next to the libFLAC files in tests/data/rfc9639 and the flacenc files
+in tests/data/flacenc.
+"""
+
+import hashlib
+import math
+import os
+import struct
+
+OUT_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "flac")
+
+
+# ---------------------------------------------------------------------------
+# Bit writing and CRCs
+# ---------------------------------------------------------------------------
+
+
+class BitWriter:
+    def __init__(self):
+        self.bytes = bytearray()
+        self.acc = 0
+        self.nbits = 0
+
+    def write(self, value, bits):
+        if bits == 0:
+            return
+        value &= (1 << bits) - 1
+        self.acc = (self.acc << bits) | value
+        self.nbits += bits
+        while self.nbits >= 8:
+            self.nbits -= 8
+            self.bytes.append((self.acc >> self.nbits) & 0xFF)
+        self.acc &= (1 << self.nbits) - 1
+
+    def write_signed(self, value, bits):
+        assert -(1 << (bits - 1)) <= value < (1 << (bits - 1)), (value, bits)
+        self.write(value, bits)
+
+    def write_unary(self, zeros):
+        while zeros > 32:
+            self.write(0, 32)
+            zeros -= 32
+        self.write(1, zeros + 1)
+
+    def align(self):
+        if self.nbits:
+            self.write(0, 8 - self.nbits)
+
+    def getvalue(self):
+        assert self.nbits == 0
+        return bytes(self.bytes)
+
+
+def crc8(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x07) & 0xFF if crc & 0x80 else (crc << 1) & 0xFF
+    return crc
+
+
+def crc16(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x8005) & 0xFFFF if crc & 0x8000 else (crc << 1) & 0xFFFF
+    return crc
+
+
+# ---------------------------------------------------------------------------
+# Residual coding
+# ---------------------------------------------------------------------------
+
+
+def fold(value):
+    return (value << 1) if value >= 0 else ((-value) << 1) - 1
+
+
+def rice_cost(residuals, parameter):
+    return sum((fold(r) >> parameter) + 1 + parameter for r in residuals)
+
+
+def write_residuals(w, residuals, block_size, order, spec):
+    """
+    spec keys: method (0 = RICE, 1 = RICE2), partition_order, and optionally
+    escape (list of partition indices coded unencoded) and parameter (forced)
+    """
+    partition_order = spec.get("partition_order", 0)
+    escapes = spec.get("escape", [])
+    per_partition = block_size >> partition_order
+    assert per_partition << partition_order == block_size
+    if "method" in spec:
+        method = spec["method"]
+    else:
+        # Fall back to 5-bit parameters only when 4-bit ones cannot code a partition well
+        widest = max((abs(r) for r in residuals), default=0).bit_length()
+        method = 1 if widest > 15 else 0
+    w.write(method, 2)
+    w.write(partition_order, 4)
+    param_bits = 4 if method == 0 else 5
+    escape_code = (1 << param_bits) - 1
+    pos = 0
+    for partition in range(1 << partition_order):
+        count = per_partition - (order if partition == 0 else 0)
+        part = residuals[pos:pos + count]
+        pos += count
+        if partition in escapes:
+            w.write(escape_code, param_bits)
+            width = 0
+            for r in part:
+                while not (-(1 << (width - 1)) <= r < (1 << (width - 1)) if width else r == 0):
+                    width += 1
+            w.write(width, 5)
+            for r in part:
+                if width:
+                    w.write_signed(r, width)
+            continue
+        if "parameter" in spec:
+            parameter = spec["parameter"]
+        else:
+            parameter = min(range(escape_code), key=lambda p: rice_cost(part, p))
+        w.write(parameter, param_bits)
+        for r in part:
+            u = fold(r)
+            w.write_unary(u >> parameter)
+            w.write(u & ((1 << parameter) - 1), parameter)
+    assert pos == len(residuals)
+
+
+# ---------------------------------------------------------------------------
+# Subframes
+# ---------------------------------------------------------------------------
+
+FIXED_COEFFICIENTS = [[], [1], [2, -1], [3, -3, 1], [4, -6, 4, -1]]
+
+
+def write_subframe(w, samples, bits, spec):
+    kind = spec["type"]
+    wasted = spec.get("wasted", 0)
+    if wasted:
+        assert all(s % (1 << wasted) == 0 for s in samples)
+        samples = [s >> wasted for s in samples]
+        bits -= wasted
+
+    def header(type_code):
+        w.write(0, 1)
+        w.write(type_code, 6)
+        if wasted:
+            w.write(1, 1)
+            w.write_unary(wasted - 1)
+        else:
+            w.write(0, 1)
+
+    n = len(samples)
+    if kind == "constant":
+        assert all(s == samples[0] for s in samples)
+        header(0)
+        w.write_signed(samples[0], bits)
+    elif kind == "verbatim":
+        header(1)
+        for s in samples:
+            w.write_signed(s, bits)
+    elif kind == "fixed":
+        order = spec["order"]
+        header(8 + order)
+        for s in samples[:order]:
+            w.write_signed(s, bits)
+        coefficients = FIXED_COEFFICIENTS[order]
+        residuals = [
+            samples[i] - sum(c * samples[i - j - 1] for j, c in enumerate(coefficients))
+            for i in range(order, n)
+        ]
+        write_residuals(w, residuals, n, order, spec.get("residual", {}))
+    elif kind == "lpc":
+        order = spec["order"]
+        precision = spec.get("precision", 12)
+        coefficients = spec.get("coefficients") or quantized_lpc(samples, order, precision)
+        shift = coefficients[1]
+        coefficients = coefficients[0]
+        header(32 + order - 1)
+        for s in samples[:order]:
+            w.write_signed(s, bits)
+        w.write(precision - 1, 4)
+        w.write_signed(shift, 5)
+        for c in coefficients:
+            w.write_signed(c, precision)
+        residuals = [
+            samples[i] - (sum(c * samples[i - j - 1] for j, c in enumerate(coefficients)) >> shift)
+            for i in range(order, n)
+        ]
+        write_residuals(w, residuals, n, order, spec.get("residual", {}))
+    else:
+        raise ValueError(kind)
+
+
+def quantized_lpc(samples, order, precision):
+    """Levinson-Durbin on the autocorrelation, quantized to the given precision"""
+    n = len(samples)
+    data = [float(s) for s in samples]
+    autoc = [sum(data[i] * data[i - lag] for i in range(lag, n)) for lag in range(order + 1)]
+    if autoc[0] == 0:
+        return ([0] * order, 0)
+    lpc = [0.0] * order
+    err = autoc[0]
+    for i in range(order):
+        acc = autoc[i + 1] - sum(lpc[j] * autoc[i - j] for j in range(i))
+        k = acc / err if err else 0.0
+        new = lpc[:]
+        new[i] = k
+        for j in range(i):
+            new[j] = lpc[j] - k * lpc[i - 1 - j]
+        lpc = new
+        err *= (1 - k * k)
+        if err <= 0:
+            err = 1e-9
+    cmax = max(abs(c) for c in lpc) or 1.0
+    limit = (1 << (precision - 1)) - 1
+    shift = precision - 1 - max(0, math.frexp(cmax)[1])
+    shift = max(0, min(15, shift))
+    quantized = [max(-limit - 1, min(limit, int(round(c * (1 << shift))))) for c in lpc]
+    return (quantized, shift)
+
+
+# ---------------------------------------------------------------------------
+# Frames
+# ---------------------------------------------------------------------------
+
+BLOCK_SIZE_CODES = {192: 1, 576: 2, 1152: 3, 2304: 4, 4608: 5,
+                    256: 8, 512: 9, 1024: 10, 2048: 11, 4096: 12,
+                    8192: 13, 16384: 14, 32768: 15}
+SAMPLE_RATE_CODES = {88200: 1, 176400: 2, 192000: 3, 8000: 4, 16000: 5, 22050: 6,
+                     24000: 7, 32000: 8, 44100: 9, 48000: 10, 96000: 11}
+BIT_DEPTH_CODES = {8: 1, 12: 2, 16: 4, 20: 5, 24: 6, 32: 7}
+
+
+def coded_number(value):
+    if value < 0x80:
+        return bytes([value])
+    for length in range(2, 8):
+        if value < (1 << (5 * length + 1)):
+            break
+    out = []
+    for _ in range(length - 1):
+        out.append(0x80 | (value & 0x3F))
+        value >>= 6
+    prefix = (0xFF << (8 - length)) & 0xFF
+    out.append(prefix | value)
+    return bytes(reversed(out))
+
+
+def write_frame(channels, bits, rate, number, variable, spec):
+    block_size = len(channels[0])
+    assignment = spec.get("assignment", len(channels) - 1)
+
+    w = BitWriter()
+    w.write(0x3FFE, 14)
+    w.write(0, 1)
+    w.write(1 if variable else 0, 1)
+
+    block_code = spec.get("block_code")
+    if block_code is None:
+        block_code = BLOCK_SIZE_CODES.get(block_size, 6 if block_size <= 256 else 7)
+    rate_code = spec.get("rate_code")
+    if rate_code is None:
+        if rate in SAMPLE_RATE_CODES:
+            rate_code = SAMPLE_RATE_CODES[rate]
+        elif rate % 1000 == 0 and rate // 1000 < 256:
+            rate_code = 12
+        elif rate < 65536:
+            rate_code = 13
+        elif rate % 10 == 0:
+            rate_code = 14
+        else:
+            rate_code = 0
+    depth_code = spec.get("depth_code", BIT_DEPTH_CODES.get(bits, 0))
+
+    w.write(block_code, 4)
+    w.write(rate_code, 4)
+    w.write(assignment, 4)
+    w.write(depth_code, 3)
+    w.write(0, 1)
+    for byte in coded_number(number):
+        w.write(byte, 8)
+    if block_code == 6:
+        w.write(block_size - 1, 8)
+    elif block_code == 7:
+        w.write(block_size - 1, 16)
+    if rate_code == 12:
+        w.write(rate // 1000, 8)
+    elif rate_code == 13:
+        w.write(rate, 16)
+    elif rate_code == 14:
+        w.write(rate // 10, 16)
+    w.write(crc8(w.getvalue()), 8)
+
+    # Decorrelate stereo
+    if assignment == 8:
+        coded = [channels[0], [l - r for l, r in zip(*channels)]]
+        depths = [bits, bits + 1]
+    elif assignment == 9:
+        coded = [[l - r for l, r in zip(*channels)], channels[1]]
+        depths = [bits + 1, bits]
+    elif assignment == 10:
+        coded = [[(l + r) >> 1 for l, r in zip(*channels)], [l - r for l, r in zip(*channels)]]
+        depths = [bits, bits + 1]
+    else:
+        coded = channels
+        depths = [bits] * len(channels)
+
+    subframes = spec.get("subframes")
+    for i, samples in enumerate(coded):
+        sub = subframes[i] if subframes else {"type": "fixed", "order": 2}
+        write_subframe(w, samples, depths[i], sub)
+    w.align()
+    body = w.getvalue()
+    return body + struct.pack(">H", crc16(body))
+
+
+# ---------------------------------------------------------------------------
+# Metadata and files
+# ---------------------------------------------------------------------------
+
+
+def md5_of(channels, bits):
+    nbytes = (bits + 7) // 8
+    out = bytearray()
+    for i in range(len(channels[0])):
+        for ch in channels:
+            out += (ch[i] & ((1 << (8 * nbytes)) - 1)).to_bytes(nbytes, "little")
+    return hashlib.md5(out).digest()
+
+
+def streaminfo(min_block, max_block, min_frame, max_frame, rate, nch, bits, total, md5):
+    data = struct.pack(">HH", min_block, max_block)
+    data += min_frame.to_bytes(3, "big") + max_frame.to_bytes(3, "big")
+    packed = (rate << 44) | ((nch - 1) << 41) | ((bits - 1) << 36) | total
+    data += packed.to_bytes(8, "big") + md5
+    assert len(data) == 34
+    return data
+
+
+def vorbis_comment(comments):
+    vendor = b"playback test corpus"
+    data = struct.pack("<I", len(vendor)) + vendor + struct.pack("<I", len(comments))
+    for comment in comments:
+        encoded = comment.encode()
+        data += struct.pack("<I", len(encoded)) + encoded
+    return data
+
+
+def seektable(points):
+    return b"".join(struct.pack(">QQH", *point) for point in points)
+
+
+def cuesheet():
+    data = b"1234567890123".ljust(128, b"\0")
+    data += struct.pack(">Q", 0) + bytes([0x80]) + bytes(258) + bytes([1])
+    # One lead-out track
+    data += struct.pack(">Q", 0) + bytes([170]) + bytes(12) + bytes([0]) + bytes(13) + bytes([0])
+    return data
+
+
+def picture():
+    mime = b"image/png"
+    description = b"cover"
+    image = bytes(range(16))
+    data = struct.pack(">I", 3) + struct.pack(">I", len(mime)) + mime
+    data += struct.pack(">I", len(description)) + description
+    data += struct.pack(">IIIII", 1, 1, 24, 0, len(image)) + image
+    return data
+
+
+def metadata_block(block_type, data, last):
+    return bytes([(0x80 if last else 0) | block_type]) + len(data).to_bytes(3, "big") + data
+
+
+def write_wav(path, channels, bits, rate):
+    nbytes = (bits + 7) // 8
+    nch = len(channels)
+    frames = bytearray()
+    for i in range(len(channels[0])):
+        for ch in channels:
+            frames += (ch[i] & ((1 << (8 * nbytes)) - 1)).to_bytes(nbytes, "little")
+    # WAVE_FORMAT_EXTENSIBLE carries the valid bits per sample
+    fmt = struct.pack("<HHIIHH", 0xFFFE, nch, rate, rate * nch * nbytes, nch * nbytes, nbytes * 8)
+    subformat = struct.pack("<IHH", 1, 0x0000, 0x0010) + bytes.fromhex("800000aa00389b71")
+    fmt += struct.pack("<HHI", 22, bits, 0) + subformat
+    body = b"WAVE" + b"fmt " + struct.pack("<I", len(fmt)) + fmt
+    body += b"data" + struct.pack("<I", len(frames)) + frames
+    with open(path, "wb") as f:
+        f.write(b"RIFF" + struct.pack("<I", len(body)) + body)
+
+
+def write_case(name, channels, bits, rate, frames, variable=False, extra_blocks=(),
+               md5=True, streaminfo_overrides=None, seek_every=None):
+    """
+    frames: list of (block_size, frame spec) covering all samples in order
+    extra_blocks: list of (block_type, data) written after STREAMINFO
+    seek_every: write a SEEKTABLE pointing at the first frame of every run of this many samples
+    """
+    encoded = []
+    pos = 0
+    block_sizes = []
+    for index, (block_size, spec) in enumerate(frames):
+        chunk = [ch[pos:pos + block_size] for ch in channels]
+        number = pos if variable else index
+        encoded.append(write_frame(chunk, bits, rate, number, variable, spec))
+        block_sizes.append(block_size)
+        pos += block_size
+    total = len(channels[0])
+    assert pos == total, (name, pos, total)
+
+    # The last block may be short, and no block size below 16 is valid in STREAMINFO
+    if variable:
+        min_block = max(16, min(block_sizes[:-1] or block_sizes))
+        max_block = max(16, max(block_sizes))
+    else:
+        min_block = max_block = max(16, block_sizes[0])
+    info = dict(
+        min_block=min_block, max_block=max_block,
+        min_frame=min(len(f) for f in encoded), max_frame=max(len(f) for f in encoded),
+        rate=rate, nch=len(channels), bits=bits, total=total,
+        md5=md5_of(channels, bits) if md5 else bytes(16),
+    )
+    info.update(streaminfo_overrides or {})
+
+    blocks = [(0, streaminfo(**info))] + list(extra_blocks)
+    if seek_every:
+        points, offset, sample = [], 0, 0
+        for frame, block_size in zip(encoded, block_sizes):
+            if not points or sample // seek_every > points[-1][0] // seek_every:
+                points.append((sample, offset, block_size))
+            offset += len(frame)
+            sample += block_size
+        # A placeholder point, which readers must ignore
+        points.append((0xFFFFFFFFFFFFFFFF, 0, 0))
+        blocks.append((3, seektable(points)))
+    out = bytearray(b"fLaC")
+    for i, (block_type, data) in enumerate(blocks):
+        out += metadata_block(block_type, data, i == len(blocks) - 1)
+    for frame in encoded:
+        out += frame
+
+    os.makedirs(OUT_DIR, exist_ok=True)
+    with open(os.path.join(OUT_DIR, name + ".flac"), "wb") as f:
+        f.write(out)
+    write_wav(os.path.join(OUT_DIR, name + ".wav"), channels, bits, rate)
+
+
+# ---------------------------------------------------------------------------
+# Signals
+# ---------------------------------------------------------------------------
+
+
+class Random:
+    """Small LCG so the corpus is reproducible without depending on Python's RNG"""
+
+    def __init__(self, seed):
+        self.state = seed
+
+    def next(self):
+        self.state = (self.state * 1103515245 + 12345) & 0x7FFFFFFF
+        return self.state
+
+    def uniform(self):
+        return self.next() / 0x7FFFFFFF
+
+
+def signal(length, bits, seed=1, frequency=0.013, noise=0.02, level=0.7):
+    rng = Random(seed)
+    peak = (1 << (bits - 1)) - 1
+    out = []
+    for i in range(length):
+        value = math.sin(i * frequency * (1 + seed * 0.1)) * level
+        value += (rng.uniform() - 0.5) * 2 * noise
+        out.append(max(-peak - 1, min(peak, int(round(value * peak)))))
+    return out
+
+
+def full_scale(length, bits, seed=3):
+    """Random samples across the full range, including both extremes"""
+    rng = Random(seed)
+    low, high = -(1 << (bits - 1)), (1 << (bits - 1)) - 1
+    out = [low, high, low, high]
+    while len(out) < length:
+        out.append(low + int(rng.uniform() * (high - low)))
+    return out[:length]
+
+
+def uniform_frames(total, block_size, spec):
+    frames = []
+    pos = 0
+    while pos < total:
+        size = min(block_size, total - pos)
+        frames.append((size, dict(spec)))
+        pos += size
+    return frames
+
+
+# ---------------------------------------------------------------------------
+# Corpus
+# ---------------------------------------------------------------------------
+
+
+def main():
+    fixed = lambda order, **kw: dict(type="fixed", order=order, **kw)
+    lpc = lambda order, **kw: dict(type="lpc", order=order, **kw)
+
+    # Subframe types and predictor orders
+    mono = [signal(1152, 16, seed=1)]
+    write_case("subframe_constant", [[-1234] * 1152], 16, 44100,
+               [(1152, {"subframes": [{"type": "constant"}]})])
+    write_case("subframe_verbatim", mono, 16, 44100,
+               [(1152, {"subframes": [{"type": "verbatim"}]})])
+    for order in range(5):
+        write_case("subframe_fixed_order_%d" % order, mono, 16, 44100,
+                   [(1152, {"subframes": [fixed(order)]})])
+    for order in (1, 2, 8, 12, 32):
+        write_case("subframe_lpc_order_%d" % order, mono, 16, 44100,
+                   [(1152, {"subframes": [lpc(order)]})])
+    write_case("subframe_lpc_precision_15", mono, 16, 44100,
+               [(1152, {"subframes": [lpc(8, precision=15)]})])
+    write_case("subframe_wasted_bits",
+               [[(s >> 4) << 4 for s in signal(1152, 24, seed=2)]], 24, 48000,
+               [(1152, {"subframes": [fixed(2, wasted=4)]})])
+    write_case("subframe_wasted_bits_verbatim",
+               [[(s >> 3) << 3 for s in signal(1152, 16, seed=2)]], 16, 48000,
+               [(1152, {"subframes": [{"type": "verbatim", "wasted": 3}]})])
+
+    # Residual coding
+    write_case("residual_rice2", mono, 16, 44100,
+               [(1152, {"subframes": [fixed(2, residual={"method": 1, "partition_order": 3})]})])
+    write_case("residual_partition_order_8", [signal(4096, 16, seed=4)], 16, 44100,
+               [(4096, {"subframes": [lpc(8, residual={"partition_order": 8})]})])
+    write_case("residual_escape",
+               [signal(1152, 16, seed=5)], 16, 44100,
+               [(1152, {"subframes": [fixed(1, residual={"partition_order": 2, "escape": [0, 2]})]})])
+    write_case("residual_escape_zero_width", [[7] * 1152], 16, 44100,
+               [(1152, {"subframes": [fixed(1, residual={"partition_order": 1, "escape": [0, 1]})]})])
+
+    # Channel assignments
+    left, right = signal(2304, 16, seed=6), signal(2304, 16, seed=7, level=0.5)
+    for assignment, name in ((1, "independent"), (8, "left_side"), (9, "side_right"), (10, "mid_side")):
+        write_case("channels_stereo_%s" % name, [left, right], 16, 44100,
+                   uniform_frames(2304, 1152, {"assignment": assignment}))
+    for count in range(3, 9):
+        channels = [signal(1152, 16, seed=10 + c) for c in range(count)]
+        write_case("channels_%d" % count, channels, 16, 48000, [(1152, {})])
+
+    # Bit depths, including those only available through STREAMINFO
+    for bits in range(4, 33):
+        length = 1152
+        channels = [signal(length, bits, seed=bits), signal(length, bits, seed=bits + 1)]
+        subframes = [lpc(8), fixed(3)] if bits < 32 else [fixed(2), fixed(1)]
+        write_case("bit_depth_%d" % bits, channels, bits, 44100,
+                   [(length, {"assignment": 10, "subframes": subframes})])
+    write_case("bit_depth_32_full_scale", [full_scale(1152, 32, seed=1), full_scale(1152, 32, seed=2)],
+               32, 48000, [(1152, {"assignment": 8, "subframes": [{"type": "verbatim"}] * 2})])
+    # Opposite channels at near full scale need all 33 bits of the side channel
+    loud = signal(1152, 32, seed=3, level=0.98, noise=0.001)
+    write_case("bit_depth_32_side_channel", [loud, [-s for s in loud]], 32, 48000,
+               [(1152, {"assignment": 9, "subframes": [fixed(1), fixed(0)]})])
+
+    # Block size codes
+    for size, code in ((192, 1), (576, 2), (1152, 3), (2304, 4), (4608, 5), (256, 8), (512, 9),
+                       (1024, 10), (2048, 11), (4096, 12), (8192, 13), (16384, 14), (32768, 15),
+                       (100, 6), (1000, 7), (1, 6), (65535, 7)):
+        write_case("block_size_code_%d_%d" % (code, size), [signal(size, 16, seed=size % 97)], 16, 44100,
+                   [(size, {"block_code": code,
+                            "subframes": [{"type": "verbatim"}] if size < 32 else [fixed(2)]})])
+
+    # Sample rate codes
+    for rate, code in ((44100, 0), (88200, 1), (176400, 2), (192000, 3), (8000, 4), (16000, 5),
+                       (22050, 6), (24000, 7), (32000, 8), (44100, 9), (48000, 10), (96000, 11),
+                       (11000, 12), (11025, 13), (352800, 14)):
+        write_case("sample_rate_code_%d_%d" % (code, rate), [signal(576, 16, seed=code)], 16, rate,
+                   [(576, {"rate_code": code})])
+    write_case("bit_depth_code_0", [signal(576, 16, seed=9)], 16, 44100, [(576, {"depth_code": 0})])
+
+    # Blocking strategies and stream structure
+    write_case("fixed_block_size_short_last_frame", [signal(5000, 16, seed=11)], 16, 44100,
+               uniform_frames(5000, 1152, {}))
+    sizes = [1152, 576, 192, 4608, 100, 1000, 4096, 2]
+    frames, total = [], 0
+    for index, size in enumerate(sizes):
+        frames.append((size, {"subframes": [fixed(index % 4) if size > 8 else {"type": "verbatim"}]}))
+        total += size
+    write_case("variable_block_size", [signal(total, 16, seed=12)], 16, 44100, frames, variable=True)
+    write_case("fixed_block_size_many_frames", [signal(3200, 16, seed=13)], 16, 44100,
+               uniform_frames(3200, 16, {"subframes": [fixed(1)]}))
+    write_case("variable_block_size_large_sample_number",
+               [signal(70000, 8, seed=13, noise=0.0)], 8, 8000,
+               uniform_frames(70000, 4608, {}), variable=True)
+    write_case("seek_table", [signal(20000, 16, seed=16), signal(20000, 16, seed=17)], 16, 44100,
+               uniform_frames(20000, 1152, {"assignment": 10}), seek_every=4096)
+    write_case("md5_unset", [signal(1152, 16, seed=14)], 16, 44100, [(1152, {})], md5=False)
+
+    # Metadata block types
+    comments = vorbis_comment(["TITLE=Conformance", "WAVEFORMATEXTENSIBLE_CHANNEL_MASK=0x0003"])
+    table = seektable([(0, 0, 1152), (1152, 0, 1152), (0xFFFFFFFFFFFFFFFF, 0, 0)])
+    write_case("metadata_all_blocks", [left[:2304], right[:2304]], 16, 44100,
+               uniform_frames(2304, 1152, {"assignment": 10}),
+               extra_blocks=[(1, bytes(100)), (2, b"test" + bytes(8)), (3, table), (4, comments),
+                             (5, cuesheet()), (6, picture()), (1, bytes(3))])
+    write_case("metadata_reserved_block", [signal(1152, 16, seed=15)], 16, 44100, [(1152, {})],
+               extra_blocks=[(99, bytes(17))])
+
+
+if __name__ == "__main__":
+    main()
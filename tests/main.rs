@@ -0,0 +1,3 @@
+// tests/main.rs
+mod codecs;
+mod common;